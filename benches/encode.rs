@@ -0,0 +1,42 @@
+use bytes::{BufMut, BytesMut};
+use criterion::{Criterion, black_box, criterion_group, criterion_main};
+use spop::{SpopFrame, TypedData, VarScope, frames::Ack};
+
+fn bench_encode(c: &mut Criterion) {
+    let ack = Ack::new(3, 7)
+        .set_var(VarScope::Session, "score", TypedData::UInt32(42))
+        .set_var(
+            VarScope::Transaction,
+            "country",
+            TypedData::String("xx".to_string()),
+        )
+        .set_var(
+            VarScope::Request,
+            "token",
+            TypedData::Binary(vec![0xab; 64]),
+        );
+
+    let mut group = c.benchmark_group("encode");
+    let mut dst = BytesMut::with_capacity(16 * 1024);
+
+    // what SpopCodec::encode used to do
+    group.bench_function("serialize", |b| {
+        b.iter(|| {
+            dst.clear();
+            let serialized = black_box(&ack).serialize().unwrap();
+            dst.put_slice(&serialized);
+        })
+    });
+
+    group.bench_function("serialize_into", |b| {
+        b.iter(|| {
+            dst.clear();
+            black_box(&ack).serialize_into(&mut dst).unwrap();
+        })
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_encode);
+criterion_main!(benches);
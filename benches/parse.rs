@@ -0,0 +1,57 @@
+use criterion::{Criterion, Throughput, black_box, criterion_group, criterion_main};
+use spop::{
+    SpopFrame, TypedData,
+    frames::notify::NotifyFrame,
+    parser::{parse_frame, parse_frame_owned},
+};
+use std::net::Ipv4Addr;
+
+/// A NOTIFY frame as sent by the usual on-frontend-http-request event
+fn notify_bytes() -> Vec<u8> {
+    NotifyFrame::new(3, 7)
+        .add_message("check-client-ip")
+        .arg("ip", TypedData::IPv4(Ipv4Addr::new(10, 0, 0, 1)))
+        .add_message("log-request")
+        .arg("method", TypedData::String("GET".to_string()))
+        .arg(
+            "path",
+            TypedData::String("/api/v1/items?page=2".to_string()),
+        )
+        .arg(
+            "user_agent",
+            TypedData::String(
+                "Mozilla/5.0 (X11; Linux x86_64; rv:128.0) Gecko/20100101".to_string(),
+            ),
+        )
+        .arg("status", TypedData::UInt32(200))
+        .arg("body", TypedData::Binary(vec![0xab; 256]))
+        .build()
+        .serialize()
+        .unwrap()
+}
+
+fn bench_parse(c: &mut Criterion) {
+    let input = notify_bytes();
+
+    let mut group = c.benchmark_group("parse");
+    group.throughput(Throughput::Bytes(input.len() as u64));
+
+    group.bench_function("notify", |b| {
+        b.iter(|| {
+            let (_, frame) = parse_frame(black_box(&input)).unwrap();
+            black_box(frame);
+        })
+    });
+
+    group.bench_function("notify_owned", |b| {
+        b.iter(|| {
+            let (_, frame) = parse_frame_owned(black_box(&input)).unwrap();
+            black_box(frame);
+        })
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_parse);
+criterion_main!(benches);
@@ -0,0 +1,80 @@
+use criterion::{Criterion, black_box, criterion_group, criterion_main};
+use spop::types::{TypedData, typed_data, typed_data_ref};
+
+/// A KV-LIST value sequence similar to the arguments of a NOTIFY message
+fn notify_args() -> Vec<u8> {
+    let values = [
+        TypedData::String("Mozilla/5.0 (X11; Linux x86_64; rv:128.0) Gecko/20100101".to_string()),
+        TypedData::String("/api/v1/items?page=2".to_string()),
+        TypedData::Binary(vec![0xab; 256]),
+        TypedData::IPv4([10, 0, 0, 1].into()),
+        TypedData::UInt32(443),
+    ];
+
+    let mut buf = Vec::new();
+    for value in &values {
+        value.to_bytes(&mut buf);
+    }
+    buf
+}
+
+fn bench_typed_data(c: &mut Criterion) {
+    let input = notify_args();
+    let mut group = c.benchmark_group("typed_data");
+
+    group.bench_function("owned", |b| {
+        b.iter(|| {
+            let mut input = black_box(input.as_slice());
+            while !input.is_empty() {
+                let (rest, data) = typed_data(input).unwrap();
+                black_box(data);
+                input = rest;
+            }
+        })
+    });
+
+    group.bench_function("borrowed", |b| {
+        b.iter(|| {
+            let mut input = black_box(input.as_slice());
+            while !input.is_empty() {
+                let (rest, data) = typed_data_ref(input).unwrap();
+                black_box(data);
+                input = rest;
+            }
+        })
+    });
+
+    group.finish();
+}
+
+/// Parses a single value of each type
+fn bench_typed_data_types(c: &mut Criterion) {
+    let values = [
+        TypedData::Null,
+        TypedData::Bool(true),
+        TypedData::Int32(-42),
+        TypedData::UInt32(443),
+        TypedData::Int64(-1 << 40),
+        TypedData::UInt64(1 << 40),
+        TypedData::IPv4([10, 0, 0, 1].into()),
+        TypedData::IPv6([0x2001, 0xdb8, 0, 0, 0, 0, 0, 1].into()),
+        TypedData::String("/api/v1/items?page=2".to_string()),
+        TypedData::Binary(vec![0xab; 256]),
+    ];
+
+    let mut group = c.benchmark_group("typed_data_type");
+
+    for value in &values {
+        let mut input = Vec::new();
+        value.to_bytes(&mut input);
+
+        group.bench_function(value.type_name(), |b| {
+            b.iter(|| black_box(typed_data(black_box(&input)).unwrap()))
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_typed_data, bench_typed_data_types);
+criterion_main!(benches);
@@ -1,12 +1,8 @@
 use anyhow::Result;
 use futures::{SinkExt, StreamExt};
-use semver::Version;
 use spop::{
-    SpopCodec, SpopFrame,
-    actions::VarScope,
-    frame::{FramePayload, FrameType},
-    frames::{Ack, AgentDisconnect, AgentHello, FrameCapabilities, HaproxyHello},
-    types::TypedData,
+    AgentConfig, AgentSession, SessionState, SpopCodec, SpopFrame, actions::VarScope,
+    frame::FrameType, frames::Ack, types::TypedData,
 };
 use std::{os::unix::fs::PermissionsExt, path::Path};
 use tokio::net::{UnixListener, UnixStream};
@@ -42,7 +38,8 @@ async fn main() -> Result<()> {
 }
 
 async fn handle_connection(u_stream: UnixStream) -> Result<()> {
-    let mut socket = Framed::new(u_stream, SpopCodec);
+    let mut socket = Framed::new(u_stream, SpopCodec::default());
+    let mut session = AgentSession::new(AgentConfig::default());
 
     while let Some(result) = socket.next().await {
         let frame = match result {
@@ -53,71 +50,36 @@ async fn handle_connection(u_stream: UnixStream) -> Result<()> {
             }
         };
 
-        match frame.frame_type() {
-            // Respond with AgentHello frame
-            FrameType::HaproxyHello => {
-                let hello = HaproxyHello::try_from(frame.payload())
-                    .map_err(|_| anyhow::anyhow!("Failed to parse HaproxyHello"))?;
-
-                let max_frame_size = hello.max_frame_size;
-                let is_healthcheck = hello.healthcheck.unwrap_or(false);
-                // * "version"    <STRING>
-                // This is the SPOP version the agent supports. It must follow the format
-                // "Major.Minor" and it must be lower or equal than one of major versions
-                // announced by HAProxy.
-                let version = Version::parse("2.0.0")?;
-
-                // Create the AgentHello with the values
-                let agent_hello = AgentHello {
-                    version,
-                    max_frame_size,
-                    capabilities: vec![FrameCapabilities::Pipelining],
-                };
-
-                println!("Sending AgentHello: {:#?}", agent_hello.payload());
-
-                match socket.send(Box::new(agent_hello)).await {
-                    Ok(_) => println!("Frame sent successfully"),
-                    Err(e) => eprintln!("Failed to send frame: {:?}", e),
+        // HELLO and DISCONNECT frames are answered by the session
+        match session.on_frame(frame.clone()) {
+            Ok(Some(reply)) => {
+                if *reply.frame_type() == FrameType::AgentHello
+                    && let Some(engine_id) = session.handshake().and_then(|h| h.engine_id.as_ref())
+                {
+                    println!("HAProxy engine: {}", engine_id);
                 }
 
-                // If "healthcheck" item was set to TRUE in the HAPROXY-HELLO frame, the
-                // agent can safely close the connection without DISCONNECT frame. In all
-                // cases, HAProxy will close the connection at the end of the health check.
-                if is_healthcheck {
-                    println!("Handled healthcheck. Closing socket.");
-                    return Ok(());
-                }
+                println!("Sending {}: {:#?}", reply.frame_type(), reply.payload());
+                socket.send(reply).await?;
             }
 
-            // Respond with AgentDisconnect frame
-            FrameType::HaproxyDisconnect => {
-                let agent_disconnect = AgentDisconnect {
-                    status_code: 0,
-                    message: "Goodbye".to_string(),
-                };
-
-                println!("Sending AgentDisconnect: {:#?}", agent_disconnect.payload());
-
-                socket.send(Box::new(agent_disconnect)).await?;
-                socket.close().await?;
-
-                return Ok(());
-            }
-
-            // Respond with Ack frame
-            FrameType::Notify => {
-                if let FramePayload::ListOfMessages(messages) = &frame.payload() {
+            // Respond to NOTIFY frames with an Ack frame
+            Ok(None) => {
+                if let Some(messages) = frame.payload().as_messages() {
                     let mut vars = Vec::new();
 
                     for message in messages {
                         match message.name.as_str() {
                             "check-client-ip" => {
-                                let random_value: u32 = rand::random_range(0..100);
+                                // trust local clients, score everyone else randomly
+                                let score: u32 = match message.client_ip() {
+                                    Some(ip) if ip.is_loopback() => 100,
+                                    _ => rand::random_range(0..100),
+                                };
                                 vars.push((
                                     VarScope::Session,
                                     "ip_score",
-                                    TypedData::UInt32(random_value),
+                                    TypedData::UInt32(score),
                                 ));
                             }
 
@@ -147,10 +109,19 @@ async fn handle_connection(u_stream: UnixStream) -> Result<()> {
                 }
             }
 
-            _ => {
-                eprintln!("Unsupported frame type: {:?}", frame.frame_type());
+            Err(e) => {
+                eprintln!("Unsupported frame: {}", e);
             }
         }
+
+        // If "healthcheck" item was set to TRUE in the HAPROXY-HELLO frame, the agent can
+        // safely close the connection without DISCONNECT frame. After a DISCONNECT frame or a
+        // failed handshake, the connection is closed too.
+        if session.state() == SessionState::Closed {
+            println!("Closing socket.");
+            socket.close().await?;
+            return Ok(());
+        }
     }
 
     println!("Socket closed by peer");
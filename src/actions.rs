@@ -1,4 +1,15 @@
-use crate::types::TypedData;
+use crate::{
+    SpopError,
+    parser::{SpopParserConfig, parse_value},
+    types::{TypedData, put_string},
+    varint::{decode_varint, put_varint, varint_len},
+};
+use alloc::{format, string::String, string::ToString, vec::Vec};
+use bytes::BufMut;
+use core::fmt;
+use nom::{
+    IResult, Parser, bytes::complete::take, error::ErrorKind, multi::count, number::complete::be_u8,
+};
 
 /// <https://github.com/haproxy/haproxy/blob/master/doc/SPOE.txt#L1053>
 ///
@@ -47,12 +58,12 @@ use crate::types::TypedData;
 ///     REQUEST     : <3>
 ///     RESPONSE    : <4>
 ///
-///
 /// NOTE: Name of the variables will be automatically prefixed by HAProxy to avoid
 ///       name clashes with other variables used in HAProxy. Moreover, unknown
 ///       variable will be silently ignored.
 /// ```
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Action {
     SetVar {
         scope: VarScope,
@@ -63,12 +74,240 @@ pub enum Action {
         scope: VarScope,
         name: String,
     },
+    /// Non-standard extension, not part of SPOE.txt: sets a variable from a sample-fetch
+    /// expression, encoded as action type 3 with the scope, the name and the expression as a
+    /// STRING. Stock HAProxy only knows set-var and unset-var and does not understand this
+    /// action, only send it to a peer known to support it.
+    SetVarExpr {
+        scope: VarScope,
+        name: String,
+        expr: String,
+    },
+    /// An action type this crate does not model, its NB-ARGS arguments are kept as TYPED-DATA
+    /// so the action can be passed through unchanged. At most 255 arguments can be encoded,
+    /// [`Action::to_bytes`] fails with [`SpopError::TooManyArgs`] above.
+    Unknown {
+        action_type: u8,
+        raw_args: Vec<TypedData>,
+    },
+}
+
+const ACTION_SET_VAR: u8 = 0x01;
+const ACTION_UNSET_VAR: u8 = 0x02;
+/// Non-standard, see [`Action::SetVarExpr`]
+const ACTION_SET_VAR_EXPR: u8 = 0x03;
+
+impl Action {
+    /// Returns the number of bytes [`Action::to_bytes`] writes
+    pub fn encoded_len(&self) -> usize {
+        // <ACTION-TYPE:1 byte><NB-ARGS:1 byte><VAR-SCOPE:1 byte><VAR-NAME>
+        let header = |name: &str| 3 + varint_len(name.len() as u64) + name.len();
+
+        match self {
+            Self::SetVar { name, value, .. } => header(name) + value.encoded_len(),
+            Self::UnSetVar { name, .. } => header(name),
+            Self::SetVarExpr { name, expr, .. } => {
+                header(name) + 1 + varint_len(expr.len() as u64) + expr.len()
+            }
+            Self::Unknown { raw_args, .. } => {
+                2 + raw_args.iter().map(TypedData::encoded_len).sum::<usize>()
+            }
+        }
+    }
+
+    /// Writes the action at the end of `buf`, nothing is written on error
+    pub fn to_bytes<B: BufMut + ?Sized>(&self, buf: &mut B) -> Result<(), SpopError> {
+        match self {
+            Self::SetVar { scope, name, value } => {
+                // Action type: SET-VAR (1 byte)
+                buf.put_u8(ACTION_SET_VAR);
+
+                // Number of arguments: 3 (1 byte)
+                buf.put_u8(0x03);
+
+                // Scope (1 byte)
+                buf.put_u8(scope.to_u8());
+
+                // Serialize variable name (length + bytes)
+                put_varint(buf, name.len() as u64);
+                buf.put_slice(name.as_bytes());
+
+                // Serialize variable value based on type
+                value.to_bytes(buf);
+            }
+
+            Self::UnSetVar { scope, name } => {
+                // Action type: UNSET-VAR (1 byte)
+                buf.put_u8(ACTION_UNSET_VAR);
+
+                // Number of arguments: 2 (1 byte)
+                buf.put_u8(0x02);
+
+                // Scope (1 byte)
+                buf.put_u8(scope.to_u8());
+
+                // Serialize variable name (length + bytes)
+                put_varint(buf, name.len() as u64);
+                buf.put_slice(name.as_bytes());
+            }
+
+            Self::SetVarExpr { scope, name, expr } => {
+                // Action type: SET-VAR-EXPR (1 byte)
+                buf.put_u8(ACTION_SET_VAR_EXPR);
+
+                // Number of arguments: 3 (1 byte)
+                buf.put_u8(0x03);
+
+                // Scope (1 byte)
+                buf.put_u8(scope.to_u8());
+
+                // Serialize variable name (length + bytes)
+                put_varint(buf, name.len() as u64);
+                buf.put_slice(name.as_bytes());
+
+                // Serialize the expression as a STRING typed data
+                put_string(buf, expr);
+            }
+
+            Self::Unknown {
+                action_type,
+                raw_args,
+            } => {
+                let nb_args = u8::try_from(raw_args.len()).map_err(|_| SpopError::TooManyArgs {
+                    message: format!("action type {}", action_type),
+                    count: raw_args.len(),
+                })?;
+
+                buf.put_u8(*action_type);
+                buf.put_u8(nb_args);
+
+                for arg in raw_args {
+                    arg.to_bytes(buf);
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Displays the action with the variable as named in HAProxy, e.g. `set-var(txn.score=42)`
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::SetVar { scope, name, value } => {
+                write!(f, "set-var({}.{}={})", scope, name, value)
+            }
+            Self::UnSetVar { scope, name } => write!(f, "unset-var({}.{})", scope, name),
+            Self::SetVarExpr { scope, name, expr } => {
+                write!(f, "set-var-expr({}.{}={})", scope, name, expr)
+            }
+            Self::Unknown {
+                action_type,
+                raw_args,
+            } => {
+                let args: Vec<_> = raw_args.iter().map(ToString::to_string).collect();
+                write!(f, "action-{}({})", action_type, args.join(", "))
+            }
+        }
+    }
+}
+
+/// Parse a single action from a LIST-OF-ACTIONS
+///
+/// ```text
+/// <ACTION-TYPE:1 byte><NB-ARGS:1 byte><VAR-SCOPE:1 byte><VAR-NAME><VAR-VALUE?>
+/// ```
+///
+/// NB-ARGS must match the number of arguments of the action type, otherwise
+/// [`SpopError::InvalidActionArgs`] is returned as a failure since the rest of the list can't be
+/// trusted. An invalid VAR-SCOPE, a VAR-NAME which is not UTF-8 ([`SpopError::InvalidUtf8`]) or
+/// a set-var-expr expression which is not a STRING ([`SpopError::WrongFieldType`]) are failures
+/// as well. Unknown action types are decoded as [`Action::Unknown`], with NB-ARGS TYPED-DATA
+/// arguments.
+///
+/// Argument values are parsed with the default [`SpopParserConfig`], see
+/// [`crate::parser::parse_frame_with_config`] to apply limits to the actions of an ACK frame.
+pub fn parse_action(input: &[u8]) -> IResult<&[u8], Action, SpopError> {
+    parse_action_with_config(input, &SpopParserConfig::default(), &mut false)
+}
+
+/// Same as [`parse_action`], argument values go through the same checks as KV-LIST values
+///
+/// `truncated` is set when a value was cut to [`crate::parser::ParseLimits::truncate_values`].
+pub(crate) fn parse_action_with_config<'a>(
+    input: &'a [u8],
+    config: &SpopParserConfig,
+    truncated: &mut bool,
+) -> IResult<&'a [u8], Action, SpopError> {
+    let (input, action_type) = be_u8(input)?;
+    let (input, nb_args) = be_u8(input)?;
+
+    let expected = match action_type {
+        ACTION_SET_VAR => ("set-var", 3),
+        ACTION_UNSET_VAR => ("unset-var", 2),
+        ACTION_SET_VAR_EXPR => ("set-var-expr", 3),
+        _ => {
+            let (input, raw_args) = count(
+                |input| parse_value(input, config, truncated),
+                nb_args as usize,
+            )
+            .parse(input)?;
+            return Ok((
+                input,
+                Action::Unknown {
+                    action_type,
+                    raw_args,
+                },
+            ));
+        }
+    };
+    if nb_args != expected.1 {
+        return Err(nom::Err::Failure(SpopError::InvalidActionArgs {
+            action: expected.0,
+            expected: expected.1,
+            got: nb_args,
+        }));
+    }
+
+    let (input, scope_byte) = be_u8(input)?;
+    let scope =
+        VarScope::from_u8(scope_byte).map_err(|kind| nom::Err::Failure(SpopError::Parse(kind)))?;
+
+    // VAR-NAME is a <STRING> (varint length + bytes)
+    let (input, length) = decode_varint(input).map_err(nom::Err::convert)?;
+    let (input, name_bytes) = take(length)(input)?;
+    let name = String::from_utf8(name_bytes.to_vec())
+        .map_err(|_| nom::Err::Failure(SpopError::InvalidUtf8))?;
+
+    match action_type {
+        ACTION_SET_VAR => {
+            let (input, value) = parse_value(input, config, truncated)?;
+            Ok((input, Action::SetVar { scope, name, value }))
+        }
+
+        ACTION_UNSET_VAR => Ok((input, Action::UnSetVar { scope, name })),
+
+        ACTION_SET_VAR_EXPR => match parse_value(input, config, truncated)? {
+            (input, TypedData::String(expr)) => {
+                Ok((input, Action::SetVarExpr { scope, name, expr }))
+            }
+            (_, other) => Err(nom::Err::Failure(SpopError::WrongFieldType {
+                field: "expression",
+                expected: "STRING",
+                found: other.type_name(),
+            })),
+        },
+
+        _ => unreachable!("unknown action types are decoded above"),
+    }
 }
 
 /// ```text
 /// VAR-SCOPE: <PROCESS> | <SESSION> | <TRANSACTION> | <REQUEST> | <RESPONSE>
 /// ```
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum VarScope {
     Process = 0,
     Session = 1,
@@ -78,7 +317,19 @@ pub enum VarScope {
 }
 
 impl VarScope {
-    /// Converts FrameType to its corresponding u8 value
+    /// Converts a u8 value to its corresponding VarScope
+    pub const fn from_u8(value: u8) -> Result<Self, ErrorKind> {
+        match value {
+            0 => Ok(Self::Process),
+            1 => Ok(Self::Session),
+            2 => Ok(Self::Transaction),
+            3 => Ok(Self::Request),
+            4 => Ok(Self::Response),
+            _ => Err(ErrorKind::Alt),
+        }
+    }
+
+    /// Converts VarScope to its corresponding u8 value
     pub const fn to_u8(&self) -> u8 {
         match self {
             Self::Process => 0,
@@ -89,3 +340,358 @@ impl VarScope {
         }
     }
 }
+
+/// Displays the scope as the prefix of HAProxy variable names, e.g. `txn`
+impl fmt::Display for VarScope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Self::Process => "proc",
+            Self::Session => "sess",
+            Self::Transaction => "txn",
+            Self::Request => "req",
+            Self::Response => "res",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// Zero-sized markers mirroring [`VarScope`] at the type level
+///
+/// They are used by [`crate::frames::RequestAck`] and [`crate::frames::ResponseAck`] to reject,
+/// at compile time, variables whose scope has no effect in the current processing phase.
+pub mod scope {
+    use super::VarScope;
+
+    /// A variable scope known at compile time
+    pub trait Scope {
+        const SCOPE: VarScope;
+    }
+
+    /// Scopes usable while processing a request
+    pub trait RequestScope: Scope {}
+
+    /// Scopes usable while processing a response
+    pub trait ResponseScope: Scope {}
+
+    #[derive(Debug, Clone, Copy)]
+    pub struct Process;
+
+    #[derive(Debug, Clone, Copy)]
+    pub struct Session;
+
+    #[derive(Debug, Clone, Copy)]
+    pub struct Transaction;
+
+    #[derive(Debug, Clone, Copy)]
+    pub struct Request;
+
+    #[derive(Debug, Clone, Copy)]
+    pub struct Response;
+
+    impl Scope for Process {
+        const SCOPE: VarScope = VarScope::Process;
+    }
+
+    impl Scope for Session {
+        const SCOPE: VarScope = VarScope::Session;
+    }
+
+    impl Scope for Transaction {
+        const SCOPE: VarScope = VarScope::Transaction;
+    }
+
+    impl Scope for Request {
+        const SCOPE: VarScope = VarScope::Request;
+    }
+
+    impl Scope for Response {
+        const SCOPE: VarScope = VarScope::Response;
+    }
+
+    impl RequestScope for Process {}
+    impl RequestScope for Session {}
+    impl RequestScope for Transaction {}
+    impl RequestScope for Request {}
+
+    impl ResponseScope for Process {}
+    impl ResponseScope for Session {}
+    impl ResponseScope for Transaction {}
+    impl ResponseScope for Response {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_var_scope_from_u8() {
+        assert_eq!(VarScope::from_u8(0), Ok(VarScope::Process));
+        assert_eq!(VarScope::from_u8(1), Ok(VarScope::Session));
+        assert_eq!(VarScope::from_u8(2), Ok(VarScope::Transaction));
+        assert_eq!(VarScope::from_u8(3), Ok(VarScope::Request));
+        assert_eq!(VarScope::from_u8(4), Ok(VarScope::Response));
+        assert_eq!(VarScope::from_u8(5), Err(ErrorKind::Alt));
+
+        for scope in 0..=4 {
+            assert_eq!(VarScope::from_u8(scope).unwrap().to_u8(), scope);
+        }
+    }
+
+    #[test]
+    fn test_set_var_expr_round_trip() {
+        let action = Action::SetVarExpr {
+            scope: VarScope::Transaction,
+            name: "ua_len".to_string(),
+            expr: "req.hdr(user-agent),length".to_string(),
+        };
+
+        let mut buf = Vec::new();
+        action.to_bytes(&mut buf).unwrap();
+
+        #[rustfmt::skip]
+        let mut expected = vec![
+            0x03, // SET-VAR-EXPR
+            0x03, // NB-ARGS
+            0x02, // VAR-SCOPE = TRANSACTION
+            0x06, b'u', b'a', b'_', b'l', b'e', b'n',
+            0x08, 0x1a, // TYPE=STRING, len = 26
+        ];
+        expected.extend_from_slice(b"req.hdr(user-agent),length");
+        assert_eq!(buf, expected);
+
+        let (rest, parsed) = parse_action(&buf).unwrap();
+        assert!(rest.is_empty());
+        match parsed {
+            Action::SetVarExpr { scope, name, expr } => {
+                assert_eq!(scope.to_u8(), VarScope::Transaction.to_u8());
+                assert_eq!(name, "ua_len");
+                assert_eq!(expr, "req.hdr(user-agent),length");
+            }
+            _ => panic!("Wrong action: {:?}", parsed),
+        }
+    }
+
+    #[test]
+    fn test_set_var_ip_round_trip() {
+        use crate::{SpopFrame, frames::Ack, parser::parse_frame};
+        use std::net::{Ipv4Addr, Ipv6Addr};
+
+        let ipv4 = Ipv4Addr::new(192, 168, 0, 1);
+        let ipv6 = Ipv6Addr::new(0x2001, 0x0db8, 0, 0, 0, 0, 0, 1);
+
+        let mut buf = Vec::new();
+        Action::SetVar {
+            scope: VarScope::Session,
+            name: "ip".to_string(),
+            value: TypedData::IPv4(ipv4),
+        }
+        .to_bytes(&mut buf)
+        .unwrap();
+
+        #[rustfmt::skip]
+        let expected = [
+            0x01, // SET-VAR
+            0x03, // NB-ARGS
+            0x01, // VAR-SCOPE = SESSION
+            0x02, b'i', b'p',
+            0x06, 192, 168, 0, 1, // TYPE=IPV4, STRUCT IN_ADDR
+        ];
+        assert_eq!(buf, expected);
+
+        let mut buf = Vec::new();
+        Action::SetVar {
+            scope: VarScope::Session,
+            name: "ip".to_string(),
+            value: TypedData::IPv6(ipv6),
+        }
+        .to_bytes(&mut buf)
+        .unwrap();
+
+        // TYPE=IPV6, STRUCT IN_ADDR6
+        assert_eq!(buf[..6], [0x01, 0x03, 0x01, 0x02, b'i', b'p']);
+        assert_eq!(buf[6], 0x07);
+        assert_eq!(buf[7..], ipv6.octets());
+
+        // both parse back through the ACK parser
+        let ack = Ack::new(1, 2)
+            .set_var(VarScope::Session, "ipv4", ipv4)
+            .set_var(VarScope::Request, "ipv6", ipv6);
+        let (_, frame) = parse_frame(&ack.serialize().unwrap()).unwrap();
+        assert_eq!(
+            Ack::try_from((frame.metadata(), frame.payload())).unwrap(),
+            ack
+        );
+    }
+
+    #[test]
+    fn test_set_var_invalid_nb_args() {
+        use crate::parser::parse_frame;
+
+        #[rustfmt::skip]
+        let action = [
+            0x01, // SET-VAR
+            0x02, // NB-ARGS = 2, 3 are expected
+            0x01, // VAR-SCOPE = SESSION
+            0x01, b'x',
+            0x03, 0x2a, // TYPE=UINT32, 42
+        ];
+        assert_eq!(
+            parse_action(&action),
+            Err(nom::Err::Failure(SpopError::InvalidActionArgs {
+                action: "set-var",
+                expected: 3,
+                got: 2,
+            }))
+        );
+
+        // the error is not swallowed when parsing an ACK frame
+        #[rustfmt::skip]
+        let mut frame = vec![
+            0x00, 0x00, 0x00, 0x0e, // FRAME-LENGTH
+            0x67,                   // FRAME-TYPE = ACK
+            0x00, 0x00, 0x00, 0x01, // FLAGS = FIN
+            0x01, 0x01,             // STREAM-ID, FRAME-ID
+        ];
+        frame.extend_from_slice(&action);
+        assert_eq!(
+            parse_frame(&frame).err(),
+            Some(nom::Err::Failure(SpopError::InvalidActionArgs {
+                action: "set-var",
+                expected: 3,
+                got: 2,
+            }))
+        );
+    }
+
+    #[test]
+    fn test_action_invalid_fields() {
+        #[rustfmt::skip]
+        let bad_scope = [
+            0x02, 0x02, // UNSET-VAR, NB-ARGS
+            0x05,       // VAR-SCOPE = 5
+            0x01, b'x', // VAR-NAME
+        ];
+        assert_eq!(
+            parse_action(&bad_scope).unwrap_err(),
+            nom::Err::Failure(SpopError::Parse(ErrorKind::Alt))
+        );
+
+        #[rustfmt::skip]
+        let bad_name = [
+            0x02, 0x02, // UNSET-VAR, NB-ARGS
+            0x02,       // VAR-SCOPE = TRANSACTION
+            0x01, 0xff, // VAR-NAME, not UTF-8
+        ];
+        assert_eq!(
+            parse_action(&bad_name).unwrap_err(),
+            nom::Err::Failure(SpopError::InvalidUtf8)
+        );
+
+        #[rustfmt::skip]
+        let bad_expr = [
+            0x03, 0x03, // SET-VAR-EXPR, NB-ARGS
+            0x02,       // VAR-SCOPE = TRANSACTION
+            0x01, b'x', // VAR-NAME
+            0x03, 0x2a, // TYPE=UINT32, 42
+        ];
+        assert_eq!(
+            parse_action(&bad_expr).unwrap_err(),
+            nom::Err::Failure(SpopError::WrongFieldType {
+                field: "expression",
+                expected: "STRING",
+                found: "UINT32",
+            })
+        );
+    }
+
+    #[test]
+    fn test_action_reserved_type() {
+        #[rustfmt::skip]
+        let set_var = [
+            0x01, 0x03,       // SET-VAR, NB-ARGS
+            0x02,             // VAR-SCOPE = TRANSACTION
+            0x01, b'x',       // VAR-NAME
+            0x0c, 0x01, 0x02, // TYPE=12
+        ];
+        assert_eq!(
+            parse_action(&set_var).unwrap_err(),
+            nom::Err::Failure(SpopError::ReservedType(12))
+        );
+
+        // arguments of unknown actions are checked as well
+        let unknown = [0x04, 0x01, 0x0c];
+        assert_eq!(
+            parse_action(&unknown).unwrap_err(),
+            nom::Err::Failure(SpopError::ReservedType(12))
+        );
+    }
+
+    #[test]
+    fn test_unknown_action_round_trip() {
+        use crate::{SpopFrame, frames::Ack, parser::parse_frame};
+
+        #[rustfmt::skip]
+        let action = [
+            0x04, // unknown action type
+            0x02, // NB-ARGS
+            0x08, 0x03, b'f', b'o', b'o', // TYPE=STRING, "foo"
+            0x03, 0x2a,                   // TYPE=UINT32, 42
+        ];
+        let (rest, parsed) = parse_action(&action).unwrap();
+        assert!(rest.is_empty());
+        assert_eq!(
+            parsed,
+            Action::Unknown {
+                action_type: 0x04,
+                raw_args: vec![TypedData::String("foo".to_string()), TypedData::UInt32(42)],
+            }
+        );
+        assert_eq!(parsed.to_string(), "action-4(\"foo\", 42)");
+
+        let mut buf = Vec::new();
+        parsed.to_bytes(&mut buf).unwrap();
+        assert_eq!(buf, action);
+        assert_eq!(parsed.encoded_len(), action.len());
+
+        // relayed unchanged in an ACK frame, next to known actions
+        let mut ack = Ack::new(1, 2).unset_var(VarScope::Transaction, "tmp");
+        ack.actions.push(parsed);
+        let serialized = ack.serialize().unwrap();
+        assert!(serialized.ends_with(&action));
+
+        let (_, frame) = parse_frame(&serialized).unwrap();
+        assert_eq!(
+            Ack::try_from((frame.metadata(), frame.payload())).unwrap(),
+            ack
+        );
+    }
+
+    #[test]
+    fn test_unknown_action_too_many_args() {
+        use crate::{SpopFrame, frames::Ack};
+
+        let unknown = |count: u32| Action::Unknown {
+            action_type: 0x04,
+            raw_args: (0..count).map(TypedData::UInt32).collect(),
+        };
+
+        let mut buf = Vec::new();
+        unknown(255).to_bytes(&mut buf).unwrap();
+        assert_eq!(buf[..2], [0x04, 0xff]);
+
+        let mut buf = Vec::new();
+        assert_eq!(
+            unknown(256).to_bytes(&mut buf).unwrap_err(),
+            SpopError::TooManyArgs {
+                message: "action type 4".to_string(),
+                count: 256
+            }
+        );
+        assert!(buf.is_empty());
+
+        // an ACK carrying it is not serialized either
+        let mut ack = Ack::new(1, 2);
+        ack.actions.push(unknown(256));
+        assert!(ack.serialize_bounded(u32::MAX).is_err());
+    }
+}
@@ -0,0 +1,503 @@
+use crate::{
+    SpopError, SpopFrame,
+    frame::FrameType,
+    frames::{
+        AgentDisconnect, AgentHello, DisconnectStatus, EngineId, FrameCapabilities, HaproxyHello,
+    },
+};
+use alloc::{boxed::Box, string::ToString, vec, vec::Vec};
+use semver::Version;
+
+/// Agent settings advertised during the HELLO handshake
+///
+/// Defines in one place the SPOP version, maximum frame size and capabilities supported by the
+/// agent, and builds the AGENT-HELLO reply to a HAPROXY-HELLO frame from them.
+#[derive(Debug, Clone)]
+pub struct AgentConfig {
+    /// SPOP version supported by the agent
+    pub version: Version,
+
+    /// Largest frame the agent accepts, the reply never advertises more than HAProxy's value.
+    /// Per the spec it must be greater than or equal to 256 bytes.
+    pub max_frame_size: u32,
+
+    /// Capabilities implemented by the agent
+    pub capabilities: Vec<FrameCapabilities>,
+}
+
+impl Default for AgentConfig {
+    /// SPOP 2.0 with pipelining, accepting the default HAProxy frame size (tune.bufsize - 4)
+    fn default() -> Self {
+        Self {
+            version: Version::new(2, 0, 0),
+            max_frame_size: 16380,
+            capabilities: vec![FrameCapabilities::Pipelining],
+        }
+    }
+}
+
+impl AgentConfig {
+    /// Builds the AGENT-HELLO reply to a HAPROXY-HELLO frame
+    ///
+    /// The reply is negotiated by [`HaproxyHello::negotiate`] from the agent settings: the
+    /// advertised max-frame-size is the lower value between the agent and HAProxy ones, and only
+    /// the capabilities supported by both peers are advertised. Fails with
+    /// [`SpopError::NoCommonVersion`] when HAProxy does not announce the major version of the
+    /// agent, the agent should then send an AGENT-DISCONNECT frame.
+    pub fn reply(&self, hello: &HaproxyHello) -> Result<AgentHello, SpopError> {
+        hello.negotiate(
+            core::slice::from_ref(&self.version),
+            self.max_frame_size,
+            &self.capabilities,
+        )
+    }
+
+    /// Completes the HELLO handshake, see [`Handshake`]
+    ///
+    /// The AGENT-HELLO reply is built by [`AgentConfig::reply`], this fails the same way.
+    pub fn handshake(&self, hello: &HaproxyHello) -> Result<Handshake, SpopError> {
+        let reply = self.reply(hello)?;
+
+        Ok(Handshake {
+            reply,
+            engine_id: hello.engine_id.as_deref().and_then(|id| id.parse().ok()),
+            healthcheck: hello.healthcheck.unwrap_or(false),
+        })
+    }
+}
+
+/// Result of a HELLO handshake, built by [`AgentConfig::handshake`]
+///
+/// Keeps what the agent needs to know about the connection once the AGENT-HELLO reply is sent.
+#[derive(Debug)]
+pub struct Handshake {
+    /// The AGENT-HELLO frame to send back
+    pub reply: AgentHello,
+
+    /// The "engine-id" announced by HAProxy, shared by all the connections of one HAProxy
+    /// process, so per-engine state can be tracked across them
+    ///
+    /// An empty engine-id is treated as missing.
+    pub engine_id: Option<EngineId>,
+
+    /// The connection is a health check, it may be closed without a DISCONNECT frame
+    pub healthcheck: bool,
+}
+
+impl Handshake {
+    /// Returns `true` when both peers agreed on pipelining, NOTIFY frames may then interleave
+    pub fn pipelining(&self) -> bool {
+        self.reply
+            .capabilities
+            .contains(&FrameCapabilities::Pipelining)
+    }
+}
+
+/// State of a connection handled by an [`AgentSession`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionState {
+    /// Waiting for the HAPROXY-HELLO frame
+    AwaitingHello,
+
+    /// The HELLO handshake is done, NOTIFY frames are expected
+    Established,
+
+    /// The connection must be closed: a DISCONNECT frame was exchanged, the handshake failed or
+    /// the connection was a health check
+    Closed,
+}
+
+/// Agent side of the SPOP connection flow: HELLO, then NOTIFY/ACK, then DISCONNECT
+///
+/// Each received frame is passed to [`AgentSession::on_frame`], which handles the HELLO and
+/// DISCONNECT frames and lets the application reply to the NOTIFY frames:
+///
+/// ```
+/// use spop::{AgentConfig, AgentSession, SpopFrame, frames::{Ack, HaproxyHello}};
+/// use semver::Version;
+///
+/// let mut session = AgentSession::new(AgentConfig::default());
+///
+/// let hello = HaproxyHello::new(vec![Version::new(2, 0, 0)], 16380, vec![]).into_frame(0, 0);
+/// let reply = session.on_frame(Box::new(hello)).unwrap().unwrap();
+/// assert_eq!(reply.frame_type().to_string(), "AGENT-HELLO");
+///
+/// // NOTIFY frames are left to the application, which sends the ACK
+/// let notify = spop::build::haproxy::notify(1, 1, vec![]);
+/// assert!(session.on_frame(Box::new(notify)).unwrap().is_none());
+/// ```
+#[derive(Debug)]
+pub struct AgentSession {
+    config: AgentConfig,
+    state: SessionState,
+    handshake: Option<Handshake>,
+}
+
+impl AgentSession {
+    /// Creates a session waiting for the HAPROXY-HELLO frame
+    pub const fn new(config: AgentConfig) -> Self {
+        Self {
+            config,
+            state: SessionState::AwaitingHello,
+            handshake: None,
+        }
+    }
+
+    pub const fn state(&self) -> SessionState {
+        self.state
+    }
+
+    /// Returns the negotiated version, max-frame-size and capabilities, once the HELLO handshake
+    /// succeeded
+    pub const fn handshake(&self) -> Option<&Handshake> {
+        self.handshake.as_ref()
+    }
+
+    /// Handles a frame received from HAProxy and returns the frame to send back
+    ///
+    /// * HAPROXY-HELLO: the AGENT-HELLO reply, or an AGENT-DISCONNECT when the handshake fails,
+    ///   see [`AgentConfig::handshake`]
+    /// * HAPROXY-DISCONNECT: the AGENT-DISCONNECT reply
+    /// * NOTIFY: `None`, the application builds the ACK
+    ///
+    /// Any other frame, including a NOTIFY before the handshake or a frame after the connection
+    /// is closed, fails with [`SpopError::UnexpectedFrame`].
+    pub fn on_frame(
+        &mut self,
+        frame: Box<dyn SpopFrame>,
+    ) -> Result<Option<Box<dyn SpopFrame>>, SpopError> {
+        let frame_type = *frame.frame_type();
+
+        match (self.state, frame_type) {
+            (SessionState::AwaitingHello, FrameType::HaproxyHello) => {
+                let handshake = HaproxyHello::try_from(frame.payload())
+                    .and_then(|hello| self.config.handshake(&hello));
+
+                match handshake {
+                    Ok(handshake) => {
+                        let reply = handshake.reply.clone();
+
+                        // HAProxy closes a health check connection after the HELLO
+                        self.state = if handshake.healthcheck {
+                            SessionState::Closed
+                        } else {
+                            SessionState::Established
+                        };
+                        self.handshake = Some(handshake);
+
+                        Ok(Some(Box::new(reply)))
+                    }
+                    Err(e) => {
+                        self.state = SessionState::Closed;
+
+                        Ok(Some(Box::new(AgentDisconnect::from_error(&e))))
+                    }
+                }
+            }
+
+            (SessionState::Established, FrameType::Notify) => Ok(None),
+
+            (
+                SessionState::AwaitingHello | SessionState::Established,
+                FrameType::HaproxyDisconnect,
+            ) => {
+                self.state = SessionState::Closed;
+
+                Ok(Some(Box::new(AgentDisconnect {
+                    status_code: DisconnectStatus::Normal.to_u32(),
+                    message: DisconnectStatus::Normal.to_string(),
+                })))
+            }
+
+            _ => Err(SpopError::UnexpectedFrame(frame_type)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        SpopFrame,
+        frame::{FramePayload, FrameType},
+        parser::parse_frame,
+        types::TypedData,
+    };
+
+    #[test]
+    fn test_agent_config_reply() {
+        let config = AgentConfig {
+            version: Version::new(2, 0, 0),
+            max_frame_size: 16380,
+            capabilities: vec![FrameCapabilities::Pipelining],
+        };
+
+        // minimal HAPROXY-HELLO: 2.0, 256 bytes max-frame-size, no capabilities
+        let bytes = FrameType::HaproxyHello.minimal_valid_frame();
+        let (_, frame) = parse_frame(&bytes).unwrap();
+        let hello = HaproxyHello::try_from(frame.payload()).unwrap();
+
+        let agent_hello = config.reply(&hello).unwrap();
+        assert_eq!(agent_hello.version, Version::new(2, 0, 0));
+        assert_eq!(agent_hello.max_frame_size, 256);
+        assert!(agent_hello.capabilities.is_empty());
+
+        // pipelining announced by HAProxy
+        let hello = HaproxyHello {
+            supported_versions: vec![Version::new(2, 0, 0)],
+            max_frame_size: 16380,
+            capabilities: vec![FrameCapabilities::Pipelining],
+            healthcheck: None,
+            engine_id: None,
+        };
+
+        let agent_hello = config.reply(&hello).unwrap();
+        assert_eq!(agent_hello.max_frame_size, 16380);
+        assert_eq!(
+            agent_hello.capabilities,
+            vec![FrameCapabilities::Pipelining]
+        );
+
+        match agent_hello.payload() {
+            FramePayload::KVList(kv_list) => {
+                assert_eq!(
+                    kv_list.get("version"),
+                    Some(&TypedData::String("2.0".to_string()))
+                );
+                assert_eq!(
+                    kv_list.get("max-frame-size"),
+                    Some(&TypedData::UInt32(16380))
+                );
+                assert_eq!(
+                    kv_list.get("capabilities"),
+                    Some(&TypedData::String("pipelining".to_string()))
+                );
+            }
+            _ => panic!("Wrong type of payload"),
+        }
+    }
+
+    #[test]
+    fn test_agent_config_reply_unsupported_version() {
+        let config = AgentConfig::default();
+
+        let hello = HaproxyHello {
+            supported_versions: vec![Version::new(1, 0, 0)],
+            max_frame_size: 16380,
+            capabilities: vec![FrameCapabilities::Pipelining],
+            healthcheck: None,
+            engine_id: None,
+        };
+
+        assert_eq!(
+            config.reply(&hello).unwrap_err(),
+            SpopError::NoCommonVersion(vec![Version::new(1, 0, 0)])
+        );
+    }
+
+    #[test]
+    fn test_agent_config_reply_clamps_max_frame_size() {
+        let config = AgentConfig {
+            max_frame_size: 4096,
+            ..AgentConfig::default()
+        };
+
+        let hello = HaproxyHello {
+            supported_versions: vec![Version::new(2, 0, 0)],
+            max_frame_size: 16380,
+            capabilities: vec![],
+            healthcheck: None,
+            engine_id: None,
+        };
+
+        let agent_hello = config.reply(&hello).unwrap();
+        assert_eq!(agent_hello.max_frame_size, 4096);
+
+        // the clamped value is the one serialized
+        match agent_hello.payload() {
+            FramePayload::KVList(kv_list) => {
+                assert_eq!(
+                    kv_list.get("max-frame-size"),
+                    Some(&TypedData::UInt32(4096))
+                );
+            }
+            _ => panic!("Wrong type of payload"),
+        }
+
+        let serialized = agent_hello
+            .serialize_with_order(&["max-frame-size"])
+            .unwrap();
+        let mut expected = vec![0x0e];
+        expected.extend_from_slice(b"max-frame-size");
+        TypedData::UInt32(4096).to_bytes(&mut expected);
+        // <FRAME-LENGTH:4> <TYPE:1> <FLAGS:4> <STREAM-ID:1> <FRAME-ID:1> <KV-LIST>
+        assert_eq!(serialized[11..11 + expected.len()], expected);
+
+        // HAProxy's value is kept when it is the lower one
+        let config = AgentConfig {
+            max_frame_size: 65536,
+            ..AgentConfig::default()
+        };
+        assert_eq!(config.reply(&hello).unwrap().max_frame_size, 16380);
+    }
+
+    #[test]
+    fn test_agent_config_handshake_version() {
+        let hello = |versions: &[(u64, u64)]| HaproxyHello {
+            supported_versions: versions
+                .iter()
+                .map(|&(major, minor)| Version::new(major, minor, 0))
+                .collect(),
+            max_frame_size: 16380,
+            capabilities: vec![FrameCapabilities::Pipelining],
+            healthcheck: None,
+            engine_id: Some("engine-1".to_string()),
+        };
+        let config = AgentConfig {
+            version: Version::new(2, 1, 0),
+            ..AgentConfig::default()
+        };
+
+        let handshake = config.handshake(&hello(&[(1, 0), (2, 0)])).unwrap();
+        assert_eq!(handshake.reply.version, Version::new(2, 0, 0));
+        assert_eq!(
+            handshake.engine_id.as_ref().map(EngineId::as_str),
+            Some("engine-1")
+        );
+        assert!(handshake.pipelining());
+        assert!(!handshake.healthcheck);
+
+        // the highest version not above the agent one
+        let handshake = config.handshake(&hello(&[(2, 0), (2, 1), (2, 2)])).unwrap();
+        assert_eq!(handshake.reply.version, Version::new(2, 1, 0));
+
+        // a different major version is never selected
+        let err = config.handshake(&hello(&[(1, 0), (3, 0)])).unwrap_err();
+        assert_eq!(
+            err,
+            SpopError::NoCommonVersion(vec![Version::new(1, 0, 0), Version::new(3, 0, 0)])
+        );
+        assert_eq!(
+            err.to_string(),
+            "No common version, HAProxy supports 1.0, 3.0"
+        );
+    }
+
+    #[test]
+    fn test_agent_config_handshake_max_frame_size() {
+        let mut hello = HaproxyHello {
+            supported_versions: vec![Version::new(2, 0, 0)],
+            max_frame_size: 16380,
+            capabilities: vec![],
+            healthcheck: Some(true),
+            engine_id: None,
+        };
+        let config = AgentConfig {
+            max_frame_size: 4096,
+            ..AgentConfig::default()
+        };
+
+        let handshake = config.handshake(&hello).unwrap();
+        assert_eq!(handshake.reply.max_frame_size, 4096);
+        assert!(!handshake.pipelining());
+        assert!(handshake.healthcheck);
+        assert_eq!(handshake.engine_id, None);
+
+        hello.engine_id = Some(String::new());
+        assert_eq!(config.handshake(&hello).unwrap().engine_id, None);
+
+        hello.max_frame_size = 1024;
+        let handshake = config.handshake(&hello).unwrap();
+        assert_eq!(handshake.reply.max_frame_size, 1024);
+    }
+
+    fn haproxy_hello(healthcheck: bool) -> Box<dyn SpopFrame> {
+        let mut hello = HaproxyHello::new(
+            vec![Version::new(2, 0, 0)],
+            16380,
+            vec![FrameCapabilities::Pipelining],
+        );
+        hello.healthcheck = Some(healthcheck);
+
+        Box::new(hello.into_frame(0, 0))
+    }
+
+    #[test]
+    fn test_agent_session_flow() {
+        let mut session = AgentSession::new(AgentConfig::default());
+        assert_eq!(session.state(), SessionState::AwaitingHello);
+
+        // NOTIFY before the handshake
+        let notify = || Box::new(crate::build::haproxy::notify(1, 1, vec![]));
+        assert_eq!(
+            session.on_frame(notify()).unwrap_err(),
+            SpopError::UnexpectedFrame(FrameType::Notify)
+        );
+        assert!(session.handshake().is_none());
+
+        // HELLO
+        let reply = session.on_frame(haproxy_hello(false)).unwrap().unwrap();
+        assert_eq!(*reply.frame_type(), FrameType::AgentHello);
+        assert_eq!(session.state(), SessionState::Established);
+        let handshake = session.handshake().unwrap();
+        assert_eq!(handshake.reply.version, Version::new(2, 0, 0));
+        assert!(handshake.pipelining());
+
+        // a second HELLO is not expected
+        assert_eq!(
+            session.on_frame(haproxy_hello(false)).unwrap_err(),
+            SpopError::UnexpectedFrame(FrameType::HaproxyHello)
+        );
+
+        // NOTIFY frames are left to the application
+        assert!(session.on_frame(notify()).unwrap().is_none());
+        assert!(session.on_frame(notify()).unwrap().is_none());
+
+        // frames sent by agents are never expected
+        assert_eq!(
+            session
+                .on_frame(Box::new(crate::build::agent::ack(1, 1)))
+                .unwrap_err(),
+            SpopError::UnexpectedFrame(FrameType::Ack)
+        );
+
+        // DISCONNECT
+        let disconnect = crate::build::haproxy::disconnect(0, "normal");
+        let reply = session.on_frame(Box::new(disconnect)).unwrap().unwrap();
+        assert_eq!(*reply.frame_type(), FrameType::AgentDisconnect);
+        let reply = AgentDisconnect::try_from(reply.payload()).unwrap();
+        assert_eq!(reply.status(), DisconnectStatus::Normal);
+        assert_eq!(session.state(), SessionState::Closed);
+
+        assert_eq!(
+            session.on_frame(notify()).unwrap_err(),
+            SpopError::UnexpectedFrame(FrameType::Notify)
+        );
+    }
+
+    #[test]
+    fn test_agent_session_failed_handshake() {
+        let config = AgentConfig {
+            version: Version::new(3, 0, 0),
+            ..AgentConfig::default()
+        };
+        let mut session = AgentSession::new(config);
+
+        let reply = session.on_frame(haproxy_hello(false)).unwrap().unwrap();
+        let reply = AgentDisconnect::try_from(reply.payload()).unwrap();
+        assert_eq!(reply.status(), DisconnectStatus::UnsupportedVersion);
+        assert_eq!(reply.message, "No common version, HAProxy supports 2.0");
+        assert_eq!(session.state(), SessionState::Closed);
+        assert!(session.handshake().is_none());
+    }
+
+    #[test]
+    fn test_agent_session_healthcheck() {
+        let mut session = AgentSession::new(AgentConfig::default());
+
+        let reply = session.on_frame(haproxy_hello(true)).unwrap().unwrap();
+        assert_eq!(*reply.frame_type(), FrameType::AgentHello);
+        assert_eq!(session.state(), SessionState::Closed);
+        assert!(session.handshake().unwrap().healthcheck);
+    }
+}
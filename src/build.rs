@@ -0,0 +1,146 @@
+//! Frame constructors grouped by peer role
+//!
+//! An agent only ever sends AGENT-HELLO, AGENT-DISCONNECT and ACK frames, while HAProxy sends
+//! HAPROXY-HELLO, HAPROXY-DISCONNECT and NOTIFY frames. Importing only the module matching the
+//! role of the code makes building a frame of the other peer a visible mistake:
+//!
+//! ```
+//! use spop::{build::agent, frames::FrameCapabilities};
+//! use semver::Version;
+//!
+//! let hello = agent::hello(Version::new(2, 0, 0), 16380, vec![FrameCapabilities::Pipelining]);
+//! let ack = agent::ack(1, 1);
+//! ```
+
+/// Frames sent by an agent
+pub mod agent {
+    use crate::frames::{Ack, AgentDisconnect, AgentHello, FrameCapabilities};
+    use alloc::{string::ToString, vec::Vec};
+    use semver::Version;
+
+    /// Builds an AGENT-HELLO frame as is, without checking it against the HAPROXY-HELLO one
+    ///
+    /// Use [`crate::AgentConfig::reply`] or [`crate::AgentConfig::handshake`] to negotiate the
+    /// reply to a HAPROXY-HELLO frame.
+    pub const fn hello(
+        version: Version,
+        max_frame_size: u32,
+        capabilities: Vec<FrameCapabilities>,
+    ) -> AgentHello {
+        AgentHello {
+            version,
+            max_frame_size,
+            capabilities,
+        }
+    }
+
+    /// Builds an AGENT-DISCONNECT frame
+    pub fn disconnect(status_code: u32, message: &str) -> AgentDisconnect {
+        AgentDisconnect {
+            status_code,
+            message: message.to_string(),
+        }
+    }
+
+    /// Builds an ACK frame with no actions, replying to the given NOTIFY ids
+    pub const fn ack(stream_id: u64, frame_id: u64) -> Ack {
+        Ack::new(stream_id, frame_id)
+    }
+}
+
+/// Frames sent by HAProxy
+pub mod haproxy {
+    use crate::{
+        frame::Message,
+        frames::{
+            FrameCapabilities, HaproxyDisconnect, HaproxyHello,
+            haproxy_disconnect::HaproxyDisconnectFrame, haproxy_hello::HaproxyHelloFrame,
+            notify::NotifyFrame,
+        },
+    };
+    use alloc::{string::ToString, vec::Vec};
+    use semver::Version;
+
+    /// Builds a HAPROXY-HELLO frame, without the optional "healthcheck" and "engine-id" items
+    pub const fn hello(
+        supported_versions: Vec<Version>,
+        max_frame_size: u32,
+        capabilities: Vec<FrameCapabilities>,
+    ) -> HaproxyHelloFrame {
+        HaproxyHello::new(supported_versions, max_frame_size, capabilities).into_frame(0, 0)
+    }
+
+    /// Builds a HAPROXY-DISCONNECT frame
+    pub fn disconnect(status_code: u32, message: &str) -> HaproxyDisconnectFrame {
+        HaproxyDisconnect {
+            status_code,
+            message: message.to_string(),
+        }
+        .into_frame(0, 0)
+    }
+
+    /// Builds a NOTIFY frame attached to the given stream
+    pub fn notify(stream_id: u64, frame_id: u64, messages: Vec<Message>) -> NotifyFrame {
+        NotifyFrame::from_messages(stream_id, frame_id, messages)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::HashMap;
+    use crate::{
+        SpopFrame,
+        frame::{FrameType, Message},
+        parser::parse_frame,
+    };
+    use semver::Version;
+
+    #[test]
+    fn test_build_agent_frames() {
+        let frames: Vec<Box<dyn SpopFrame>> = vec![
+            Box::new(agent::hello(Version::new(2, 0, 0), 16380, vec![])),
+            Box::new(agent::disconnect(0, "normal")),
+            Box::new(agent::ack(3, 7)),
+        ];
+
+        let types: Vec<_> = frames.iter().map(|f| *f.frame_type()).collect();
+        assert_eq!(
+            types,
+            vec![
+                FrameType::AgentHello,
+                FrameType::AgentDisconnect,
+                FrameType::Ack
+            ]
+        );
+
+        let metadata = frames[2].metadata();
+        assert!(metadata.flags.is_fin());
+        assert_eq!((metadata.stream_id, metadata.frame_id), (3, 7));
+    }
+
+    #[test]
+    fn test_build_haproxy_frames() {
+        let frames: Vec<Box<dyn SpopFrame>> = vec![
+            Box::new(haproxy::hello(vec![Version::new(2, 0, 0)], 16380, vec![])),
+            Box::new(haproxy::disconnect(0, "normal")),
+            Box::new(haproxy::notify(
+                3,
+                7,
+                vec![Message {
+                    name: "check-client-ip".to_string(),
+                    args: HashMap::new(),
+                }],
+            )),
+        ];
+
+        // HAProxy frames are parseable by an agent
+        for frame in frames {
+            let serialized = frame.serialize().unwrap();
+            let (rest, parsed) = parse_frame(&serialized).unwrap();
+            assert!(rest.is_empty());
+            assert_eq!(parsed.frame_type(), frame.frame_type());
+            assert_eq!(parsed.metadata(), frame.metadata());
+        }
+    }
+}
@@ -1,37 +1,160 @@
-use crate::{SpopFrame, parser::parse_frame};
-use bytes::{Buf, BufMut, BytesMut};
+use crate::{
+    SpopError, SpopFrame,
+    parser::{SpopParserConfig, parse_frame, parse_frame_at},
+};
+use bytes::{Buf, BytesMut};
 use std::io;
+use tokio::io::{AsyncRead, AsyncReadExt};
 use tokio_util::codec::{Decoder, Encoder};
 
-pub struct SpopCodec;
+/// Codec for reading and writing SPOP frames with `tokio_util::codec::Framed`
+///
+/// By default the read buffer is unbounded. Use [`SpopCodec::with_max_buffer`] to cap the
+/// number of pending bytes: complete frames are still decoded from a buffer past the limit, but
+/// once no complete frame is left in it, `decode` refuses to go on and fails with
+/// [`SpopError::BufferFull`] so the caller can apply backpressure or drop the connection.
+///
+/// Frames announcing a FRAME-LENGTH over [`MAX_FRAME_LENGTH`], or over the value set with
+/// [`SpopCodec::with_max_frame_size`], are rejected with [`SpopError::FrameTooLarge`] as soon as
+/// the length prefix is read, before buffering the frame.
+///
+/// Frames are parsed with the default [`SpopParserConfig`], like [`crate::parser::parse_frame`].
+/// Use [`SpopCodec::with_config`] to apply other parser settings.
+#[derive(Debug, Clone, Default)]
+pub struct SpopCodec {
+    max_buffer: Option<usize>,
+    max_frame_size: Option<u32>,
+    config: SpopParserConfig,
+}
+
+pub use crate::frame::MAX_FRAME_LENGTH;
+
+impl SpopCodec {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Creates a codec decoding frames with the given parser settings
+    pub fn with_config(config: SpopParserConfig) -> Self {
+        Self {
+            config,
+            ..Self::default()
+        }
+    }
+
+    /// Returns the parser settings used to decode frames
+    pub const fn config(&self) -> &SpopParserConfig {
+        &self.config
+    }
+
+    /// Sets the maximum number of bytes allowed to accumulate in the read buffer
+    pub const fn with_max_buffer(mut self, max_buffer: usize) -> Self {
+        self.max_buffer = Some(max_buffer);
+        self
+    }
+
+    pub const fn max_buffer(&self) -> Option<usize> {
+        self.max_buffer
+    }
+
+    /// Sets the maximum FRAME-LENGTH accepted, usually the max-frame-size negotiated during the
+    /// HELLO handshake
+    pub const fn with_max_frame_size(mut self, max_frame_size: u32) -> Self {
+        self.max_frame_size = Some(max_frame_size);
+        self
+    }
+
+    /// Returns the maximum FRAME-LENGTH accepted by the decoder
+    pub const fn max_frame_size(&self) -> u32 {
+        match self.max_frame_size {
+            Some(max_frame_size) if max_frame_size < MAX_FRAME_LENGTH => max_frame_size,
+            _ => MAX_FRAME_LENGTH,
+        }
+    }
+}
 
 impl Decoder for SpopCodec {
     type Item = Box<dyn SpopFrame>;
     type Error = io::Error;
 
     fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        self.decode_frame(src, false)
+    }
+
+    /// Same as `decode`, once the stream is closed: this is the only place where
+    /// [`crate::parser::ParseLimits::tolerate_missing_fin`] applies, to the last frame
+    fn decode_eof(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        match self.decode_frame(src, true)? {
+            Some(frame) => Ok(Some(frame)),
+            None if src.is_empty() => Ok(None),
+            None => Err(io::Error::other("bytes remaining on stream")),
+        }
+    }
+}
+
+impl SpopCodec {
+    /// Decodes the next frame of `src`, `end_of_input` telling whether the stream is closed
+    fn decode_frame(
+        &mut self,
+        src: &mut BytesMut,
+        end_of_input: bool,
+    ) -> Result<Option<Box<dyn SpopFrame>>, io::Error> {
         let initial_len = src.len();
 
-        match parse_frame(src) {
-            Ok((remaining, frame)) => {
+        if let Some(prefix) = src.first_chunk::<4>() {
+            let length = u32::from_be_bytes(*prefix);
+            let max = self.max_frame_size();
+
+            if length > max {
+                #[cfg(feature = "tracing")]
+                tracing::debug!(length, max, "frame too large");
+
+                return Err(SpopError::FrameTooLarge { length, max }.into());
+            }
+        }
+
+        match parse_frame_at(src, &self.config, end_of_input) {
+            Ok((remaining, (frame, _))) => {
                 // Calculate the number of bytes consumed by the frame
                 let parsed_len = initial_len - remaining.len();
 
                 // Advance the src buffer by the consumed length
                 src.advance(parsed_len);
 
+                #[cfg(feature = "tracing")]
+                tracing::trace!(
+                    frame_type = %frame.frame_type(),
+                    stream_id = frame.metadata().stream_id,
+                    frame_id = frame.metadata().frame_id,
+                    length = parsed_len,
+                    "decoded frame"
+                );
+
                 // Return the frame
                 Ok(Some(frame))
             }
 
-            Err(nom::Err::Incomplete(_)) => Ok(None),
+            // Waiting for more bytes, unless the buffer is full without a complete frame
+            Err(nom::Err::Incomplete(_)) => match self.max_buffer {
+                Some(max_buffer) if initial_len > max_buffer => {
+                    #[cfg(feature = "tracing")]
+                    tracing::debug!(pending = initial_len, max_buffer, "read buffer is full");
+
+                    Err(SpopError::BufferFull {
+                        pending: initial_len,
+                        max: max_buffer,
+                    }
+                    .into())
+                }
+                _ => Ok(None),
+            },
 
-            Err(e) => {
-                // Return a generic io::Error, including the error message from nom::Err
-                Err(io::Error::new(
-                    io::ErrorKind::InvalidData,
-                    format!("Failed to parse frame: {:?}", e),
-                ))
+            // The SpopError is kept as the source of the io::Error
+            Err(nom::Err::Error(e) | nom::Err::Failure(e)) => {
+                #[cfg(feature = "tracing")]
+                tracing::debug!(error = %e, pending = initial_len, "failed to decode frame");
+
+                Err(e.into())
             }
         }
     }
@@ -41,10 +164,504 @@ impl Encoder<Box<dyn SpopFrame>> for SpopCodec {
     type Error = io::Error;
 
     fn encode(&mut self, frame: Box<dyn SpopFrame>, dst: &mut BytesMut) -> Result<(), Self::Error> {
-        let serialized = frame.serialize()?;
+        #[cfg(feature = "tracing")]
+        tracing::trace!(
+            frame_type = %frame.frame_type(),
+            stream_id = frame.metadata().stream_id,
+            frame_id = frame.metadata().frame_id,
+            "encoding frame"
+        );
+
+        let result = frame.serialize_into(dst);
+
+        #[cfg(feature = "tracing")]
+        if let Err(e) = &result {
+            tracing::debug!(error = %e, "failed to encode frame");
+        }
+
+        result
+    }
+}
+
+/// Reads a single frame from `reader`, for agents not using `tokio_util::codec::Framed`
+///
+/// The FRAME-LENGTH is checked against `max` before reading the frame, a longer frame fails with
+/// [`SpopError::FrameTooLarge`], as does one over [`MAX_FRAME_LENGTH`]. Returns `Ok(None)` when
+/// the stream ends on a frame boundary, a stream ending in the middle of a frame is an
+/// `UnexpectedEof` error. Parse errors are reported as `InvalidData`, with the [`SpopError`] as
+/// source.
+///
+/// ```
+/// # tokio::runtime::Runtime::new().unwrap().block_on(async {
+/// use spop::{SpopFrame, build::agent, codec::read_frame};
+///
+/// let bytes = agent::ack(1, 1).serialize().unwrap();
+/// let mut reader = &bytes[..];
+///
+/// let frame = read_frame(&mut reader, 16380).await.unwrap().unwrap();
+/// assert_eq!(frame.metadata().frame_id, 1);
+/// assert!(read_frame(&mut reader, 16380).await.unwrap().is_none());
+/// # });
+/// ```
+pub async fn read_frame<R: AsyncRead + Unpin>(
+    reader: &mut R,
+    max: usize,
+) -> io::Result<Option<Box<dyn SpopFrame>>> {
+    let mut prefix = [0u8; 4];
+    let mut filled = 0;
+
+    while filled < prefix.len() {
+        match reader.read(&mut prefix[filled..]).await? {
+            0 if filled == 0 => return Ok(None),
+            0 => return Err(io::ErrorKind::UnexpectedEof.into()),
+            n => filled += n,
+        }
+    }
+
+    let length = u32::from_be_bytes(prefix);
+    let max = max.min(MAX_FRAME_LENGTH as usize) as u32;
+
+    if length > max {
+        return Err(SpopError::FrameTooLarge { length, max }.into());
+    }
+
+    let mut frame = vec![0; 4 + length as usize];
+    frame[..4].copy_from_slice(&prefix);
+    reader.read_exact(&mut frame[4..]).await?;
+
+    match parse_frame(&frame) {
+        Ok((_, frame)) => Ok(Some(frame)),
+        // the whole frame was read, parse_frame can't ask for more bytes
+        Err(nom::Err::Incomplete(_)) => Err(io::ErrorKind::UnexpectedEof.into()),
+        Err(nom::Err::Error(e) | nom::Err::Failure(e)) => Err(e.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::frame::{FrameFlags, FrameType, Metadata};
+    use crate::frames::haproxy_disconnect::{HaproxyDisconnect, HaproxyDisconnectFrame};
+    use crate::frames::notify::NotifyFrame;
+    use crate::parser::{DuplicateKeyPolicy, ParseLimits};
+    use crate::types::TypedData;
+
+    fn haproxy_disconnect_bytes() -> Vec<u8> {
+        let frame = HaproxyDisconnectFrame {
+            metadata: Metadata {
+                flags: FrameFlags::new(true, false),
+                stream_id: 0,
+                frame_id: 0,
+            },
+            payload: HaproxyDisconnect {
+                status_code: 0,
+                message: "normal".to_string(),
+            },
+        };
+
+        frame.serialize().unwrap()
+    }
+
+    #[test]
+    fn test_decode_unbounded() {
+        let frame = haproxy_disconnect_bytes();
+
+        let mut src = BytesMut::new();
+        for _ in 0..100 {
+            src.extend_from_slice(&frame);
+        }
+
+        let mut codec = SpopCodec::default();
+        assert_eq!(codec.max_buffer(), None);
+
+        let mut count = 0;
+        while let Some(_frame) = codec.decode(&mut src).unwrap() {
+            count += 1;
+        }
+        assert_eq!(count, 100);
+        assert!(src.is_empty());
+    }
+
+    #[test]
+    fn test_decode_max_buffer() {
+        let frame = haproxy_disconnect_bytes();
+        let max_buffer = frame.len() * 10;
+
+        let mut codec = SpopCodec::new().with_max_buffer(max_buffer);
+        assert_eq!(codec.max_buffer(), Some(max_buffer));
+
+        // complete frames are drained even when they add up to more than the limit
+        let mut src = BytesMut::new();
+        for _ in 0..100 {
+            src.extend_from_slice(&frame);
+        }
+
+        let mut count = 0;
+        while let Some(_frame) = codec.decode(&mut src).unwrap() {
+            count += 1;
+        }
+        assert_eq!(count, 100);
+        assert!(src.is_empty());
+
+        // a partial frame under the limit waits for more bytes
+        let large = NotifyFrame::new(1, 1)
+            .add_message("body")
+            .arg("data", vec![0u8; max_buffer])
+            .build()
+            .serialize()
+            .unwrap();
+        src.extend_from_slice(&large[..max_buffer]);
+        assert!(codec.decode(&mut src).unwrap().is_none());
+
+        // over the limit without a complete frame, the decoder gives up
+        src.extend_from_slice(&large[max_buffer..large.len() - 1]);
+        let err = codec.decode(&mut src).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(
+            err.into_inner().unwrap().downcast_ref::<SpopError>(),
+            Some(&SpopError::BufferFull {
+                pending: large.len() - 1,
+                max: max_buffer,
+            })
+        );
+
+        // nothing was consumed, the caller decides what to do with the buffer
+        assert_eq!(src.len(), large.len() - 1);
+    }
+
+    #[test]
+    fn test_decode_every_split_point() {
+        let frame = haproxy_disconnect_bytes();
+        let mut codec = SpopCodec::default();
+
+        for split in 0..frame.len() {
+            let mut src = BytesMut::from(&frame[..split]);
+            assert!(
+                codec.decode(&mut src).unwrap().is_none(),
+                "frame decoded with only {} of {} bytes",
+                split,
+                frame.len()
+            );
+            // nothing is consumed until the frame is complete
+            assert_eq!(src.len(), split);
+
+            src.extend_from_slice(&frame[split..]);
+            assert!(codec.decode(&mut src).unwrap().is_some());
+            assert!(src.is_empty());
+            assert!(codec.decode(&mut src).unwrap().is_none());
+        }
+    }
+
+    #[test]
+    fn test_decode_split_frame() {
+        let frame = NotifyFrame::new(1, 2)
+            .add_message("check-client-ip")
+            .arg("ip", TypedData::IPv4(std::net::Ipv4Addr::LOCALHOST))
+            .build()
+            .serialize()
+            .unwrap();
+
+        // the first read stops in the middle of the message
+        let mut codec = SpopCodec::default();
+        let mut src = BytesMut::from(&frame[..frame.len() / 2]);
+        assert!(codec.decode(&mut src).unwrap().is_none());
+
+        src.extend_from_slice(&frame[frame.len() / 2..]);
+        let decoded = codec.decode(&mut src).unwrap().unwrap();
+        assert_eq!(decoded.frame_type(), &FrameType::Notify);
+        assert!(src.is_empty());
+
+        // a complete frame too short for its fields is an error, not a partial read
+        let mut src = BytesMut::from(&[0x00, 0x00, 0x00, 0x03, 0x03, 0x00, 0x00][..]);
+        let err = codec.decode(&mut src).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_decode_with_config() {
+        // HAPROXY-DISCONNECT with a repeated "message" item
+        let mut frame = vec![FrameType::HaproxyDisconnect.to_u8(), 0x00, 0x00, 0x00, 0x01];
+        frame.extend_from_slice(&[0x00, 0x00]);
+        for (key, value) in [
+            ("status-code", TypedData::UInt32(1)),
+            ("message", TypedData::String("first".to_string())),
+            ("message", TypedData::String("last".to_string())),
+        ] {
+            frame.extend(crate::encode_varint(key.len() as u64));
+            frame.extend_from_slice(key.as_bytes());
+            value.to_bytes(&mut frame);
+        }
+        let mut input = (frame.len() as u32).to_be_bytes().to_vec();
+        input.extend(frame);
+
+        // rejected by default
+        let mut codec = SpopCodec::default();
+        assert!(codec.decode(&mut BytesMut::from(&input[..])).is_err());
+
+        let mut codec = SpopCodec::with_config(SpopParserConfig {
+            duplicate_keys: DuplicateKeyPolicy::LastWins,
+            ..SpopParserConfig::default()
+        });
+        assert_eq!(codec.config().duplicate_keys, DuplicateKeyPolicy::LastWins);
+
+        let frame = codec
+            .decode(&mut BytesMut::from(&input[..]))
+            .unwrap()
+            .unwrap();
+        let disconnect = HaproxyDisconnect::try_from(frame.payload()).unwrap();
+        assert_eq!(disconnect.message, "last");
+    }
+
+    #[test]
+    fn test_decode_tolerate_missing_fin() {
+        // HAPROXY-DISCONNECT without FIN
+        let mut no_fin = haproxy_disconnect_bytes();
+        no_fin[8] &= !0x01;
+        let frame = haproxy_disconnect_bytes();
+
+        let mut codec = SpopCodec::with_config(SpopParserConfig {
+            limits: ParseLimits {
+                tolerate_missing_fin: true,
+                ..ParseLimits::default()
+            },
+            ..SpopParserConfig::default()
+        });
+
+        // the read ends with the frame, but the next one may bring more frames: FIN is not forced
+        let mut src = BytesMut::from(&no_fin[..]);
+        let err = codec.decode(&mut src).unwrap_err();
+        assert_eq!(
+            err.into_inner().unwrap().downcast_ref::<SpopError>(),
+            Some(&SpopError::FragmentationUnsupported)
+        );
+
+        // once the stream is closed, the last frame is the implicit final fragment
+        let mut src = BytesMut::from(&frame[..]);
+        src.extend_from_slice(&no_fin);
+        assert!(
+            codec
+                .decode_eof(&mut src)
+                .unwrap()
+                .unwrap()
+                .metadata()
+                .flags
+                .is_fin()
+        );
+        assert!(
+            codec
+                .decode_eof(&mut src)
+                .unwrap()
+                .unwrap()
+                .metadata()
+                .flags
+                .is_fin()
+        );
+        assert!(codec.decode_eof(&mut src).unwrap().is_none());
+
+        // but not one followed by more frames
+        let mut src = BytesMut::from(&no_fin[..]);
+        src.extend_from_slice(&frame);
+        assert!(codec.decode_eof(&mut src).is_err());
+
+        // a truncated frame at the end of the stream is an error
+        let mut src = BytesMut::from(&frame[..frame.len() - 1]);
+        assert!(codec.decode_eof(&mut src).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_read_frame() {
+        use crate::frames::Ack;
+        use tokio::io::AsyncWriteExt;
+
+        let (mut haproxy, mut agent) = tokio::io::duplex(64);
+
+        let writer = tokio::spawn(async move {
+            let frame = haproxy_disconnect_bytes();
+            // a frame split across writes
+            haproxy.write_all(&frame[..3]).await.unwrap();
+            haproxy.flush().await.unwrap();
+            haproxy.write_all(&frame[3..]).await.unwrap();
+            haproxy
+                .write_all(&Ack::new(1, 2).serialize().unwrap())
+                .await
+                .unwrap();
+        });
+
+        let frame = read_frame(&mut agent, 16380).await.unwrap().unwrap();
+        assert_eq!(frame.frame_type(), &FrameType::HaproxyDisconnect);
+        let frame = read_frame(&mut agent, 16380).await.unwrap().unwrap();
+        assert_eq!(frame.frame_type(), &FrameType::Ack);
+
+        // the writer is dropped on a frame boundary
+        writer.await.unwrap();
+        assert!(read_frame(&mut agent, 16380).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_read_frame_errors() {
+        let frame = haproxy_disconnect_bytes();
+        let length = frame.len() as u32 - 4;
+
+        let err = read_frame(&mut &frame[..], length as usize - 1)
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(
+            err.into_inner().unwrap().downcast_ref::<SpopError>(),
+            Some(&SpopError::FrameTooLarge {
+                length,
+                max: length - 1
+            })
+        );
+
+        // the stream ends in the middle of the length prefix, then of the frame
+        for truncated in [&frame[..2], &frame[..frame.len() - 1]] {
+            let err = read_frame(&mut &truncated[..], 16380).await.unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+        }
+
+        // FRAME-TYPE = 42
+        let frame = [
+            0x00, 0x00, 0x00, 0x07, 0x2a, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00,
+        ];
+        let err = read_frame(&mut &frame[..], 16380).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_decode_concatenated_frames_byte_by_byte() {
+        let frame = haproxy_disconnect_bytes();
+        let stream = [frame.as_slice(), frame.as_slice()].concat();
+
+        let mut codec = SpopCodec::default();
+        let mut src = BytesMut::new();
+        let mut decoded_at = Vec::new();
+
+        for (i, byte) in stream.iter().enumerate() {
+            src.extend_from_slice(&[*byte]);
+            if codec.decode(&mut src).unwrap().is_some() {
+                decoded_at.push(i + 1);
+            }
+        }
+
+        // exactly one frame each time a frame is complete
+        assert_eq!(decoded_at, vec![frame.len(), frame.len() * 2]);
+        assert!(src.is_empty());
+    }
+
+    #[test]
+    fn test_decode_frame_too_large() {
+        // A u32::MAX length must fail right away instead of waiting for 4GB of data. On 32-bit
+        // targets, 4 + u32::MAX would also overflow usize when sizing the buffer.
+        let mut src = BytesMut::from(&u32::MAX.to_be_bytes()[..]);
+        src.extend_from_slice(&[0x02, 0x00, 0x00, 0x00, 0x01]);
+
+        let mut codec = SpopCodec::default();
+        let err = codec.decode(&mut src).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(
+            err.into_inner().unwrap().downcast_ref::<SpopError>(),
+            Some(&SpopError::FrameTooLarge {
+                length: u32::MAX,
+                max: MAX_FRAME_LENGTH,
+            })
+        );
+
+        // the negotiated max-frame-size is enforced as well
+        let frame = haproxy_disconnect_bytes();
+        let length = frame.len() as u32 - 4;
+
+        let mut codec = SpopCodec::new().with_max_frame_size(length - 1);
+        let err = codec.decode(&mut BytesMut::from(&frame[..])).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        let mut codec = SpopCodec::new().with_max_frame_size(length);
+        assert!(
+            codec
+                .decode(&mut BytesMut::from(&frame[..]))
+                .unwrap()
+                .is_some()
+        );
+
+        // the platform limit always applies
+        let codec = SpopCodec::new().with_max_frame_size(u32::MAX);
+        assert_eq!(codec.max_frame_size(), MAX_FRAME_LENGTH);
+    }
+
+    #[test]
+    fn test_decode_error_source() {
+        // FRAME-TYPE = 42
+        let mut src = BytesMut::from(
+            &[
+                0x00, 0x00, 0x00, 0x07, 0x2a, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00,
+            ][..],
+        );
+
+        let err = SpopCodec::default().decode(&mut src).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(
+            err.into_inner().unwrap().downcast_ref::<SpopError>(),
+            Some(&SpopError::UnknownFrameType(42))
+        );
+    }
+
+    #[cfg(feature = "tracing")]
+    #[test]
+    fn test_decode_error_event() {
+        use std::{
+            fmt::{Debug, Write},
+            sync::{Arc, Mutex},
+        };
+        use tracing::{
+            Event, Subscriber,
+            field::{Field, Visit},
+            span,
+        };
+
+        /// Records the events as `LEVEL field=value ...` lines
+        #[derive(Clone, Default)]
+        struct Capture(Arc<Mutex<Vec<String>>>);
+
+        struct Line(String);
+
+        impl Visit for Line {
+            fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+                write!(self.0, " {}={:?}", field.name(), value).unwrap();
+            }
+        }
+
+        impl Subscriber for Capture {
+            fn enabled(&self, _: &tracing::Metadata<'_>) -> bool {
+                true
+            }
+            fn new_span(&self, _: &span::Attributes<'_>) -> span::Id {
+                span::Id::from_u64(1)
+            }
+            fn record(&self, _: &span::Id, _: &span::Record<'_>) {}
+            fn record_follows_from(&self, _: &span::Id, _: &span::Id) {}
+            fn event(&self, event: &Event<'_>) {
+                let mut line = Line(event.metadata().level().to_string());
+                event.record(&mut line);
+                self.0.lock().unwrap().push(line.0);
+            }
+            fn enter(&self, _: &span::Id) {}
+            fn exit(&self, _: &span::Id) {}
+        }
 
-        dst.put_slice(&serialized);
+        let capture = Capture::default();
+        tracing::subscriber::with_default(capture.clone(), || {
+            // unknown FRAME-TYPE 0x7f
+            let mut src = BytesMut::from(&[0x00, 0x00, 0x00, 0x07, 0x7f, 0, 0, 0, 1, 0, 0][..]);
+            assert!(SpopCodec::default().decode(&mut src).is_err());
+        });
 
-        Ok(())
+        let events = capture.0.lock().unwrap();
+        assert!(
+            events.iter().any(|e| e.starts_with("DEBUG")
+                && e.contains("message=failed to decode frame")
+                && e.contains("error=Unknown frame type 127")),
+            "{:?}",
+            events
+        );
     }
 }
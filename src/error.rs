@@ -0,0 +1,232 @@
+use crate::frame::FrameType;
+use alloc::{format, string::String, vec::Vec};
+use core::fmt;
+use nom::error::{ErrorKind, ParseError};
+#[cfg(feature = "std")]
+use std::io;
+
+/// Errors raised while decoding SPOP frames
+///
+/// This is also the error type of [`crate::parser::parse_frame`], so nom combinators can be
+/// used to build parsers on top of it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SpopError {
+    /// A low-level nom parser failed, e.g. on a truncated varint
+    Parse(ErrorKind),
+
+    /// The FRAME-TYPE byte is not a known frame type
+    UnknownFrameType(u8),
+
+    /// The frame type is known but can't be decoded by this crate
+    UnsupportedFrameType(FrameType),
+
+    /// The same key appears twice in a KV-LIST
+    DuplicateKey(String),
+
+    /// A STRING is not valid UTF-8
+    InvalidUtf8,
+
+    /// Reserved bits are set in the FLAGS of a frame
+    InvalidFlags(u32),
+
+    /// The `supported-versions` item of a HELLO frame is empty
+    NoSupportedVersions,
+
+    /// A version is not in the "Major.Minor" format
+    InvalidVersion(String),
+
+    /// None of the versions announced by HAProxy, kept here, is supported by the agent
+    NoCommonVersion(Vec<semver::Version>),
+
+    /// A mandatory KV-LIST item is missing
+    MissingField(&'static str),
+
+    /// A KV-LIST item is present but its value has the wrong type
+    WrongFieldType {
+        field: &'static str,
+        expected: &'static str,
+        found: &'static str,
+    },
+
+    /// The frame payload is not of the expected kind
+    UnexpectedPayload { expected: &'static str },
+
+    /// The (STREAM-ID, FRAME-ID) of a reply do not match the ones of the frame it answers
+    ReplyIdMismatch {
+        expected: (u64, u64),
+        found: (u64, u64),
+    },
+
+    /// A NOTIFY or ACK frame is not attached to a stream, its STREAM-ID or FRAME-ID is 0
+    InvalidMetadata { stream_id: u64, frame_id: u64 },
+
+    /// The FIN flag of a frame is not set
+    MissingFin,
+
+    /// A received frame is a fragment, its FIN flag is not set
+    ///
+    /// Fragmentation is deprecated and not supported, a fragmented payload can't be reassembled.
+    FragmentationUnsupported,
+
+    /// A frame is not expected in the current state of the connection, e.g. a NOTIFY before
+    /// the HELLO handshake
+    UnexpectedFrame(FrameType),
+
+    /// The ABORT flag of a frame is set
+    UnexpectedAbort,
+
+    /// Bytes are left after a complete frame
+    TrailingBytes(usize),
+
+    /// The FRAME-LENGTH of a frame is over the accepted maximum
+    FrameTooLarge { length: u32, max: u32 },
+
+    /// The codec read buffer holds more than the accepted maximum without a complete frame
+    ///
+    /// `pending` is the number of buffered bytes, see `SpopCodec::with_max_buffer`.
+    BufferFull { pending: usize, max: usize },
+
+    /// A message, or an unknown action, has more arguments than NB-ARGS, a single byte, can count
+    ///
+    /// `message` is the name of the message, or the action type of the action.
+    TooManyArgs { message: String, count: usize },
+
+    /// The declared length of a STRING or BINARY value is over the accepted maximum
+    ValueTooLarge { length: u64, max: usize },
+
+    /// A KV-LIST key is the last item of the payload, no value follows it
+    TruncatedValue { key: String },
+
+    /// A TYPED-DATA uses one of the reserved type ids, 10 to 15
+    ///
+    /// The encoding of these types is unknown, including whether they carry a length, so such a
+    /// value can't be skipped and the rest of the frame can't be parsed.
+    ReservedType(u8),
+
+    /// The NB-ARGS of an action does not match the number of arguments of its type
+    InvalidActionArgs {
+        action: &'static str,
+        expected: u8,
+        got: u8,
+    },
+}
+
+impl fmt::Display for SpopError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Parse(kind) => write!(f, "Parse error: {}", kind.description()),
+            Self::UnknownFrameType(frame_type) => write!(f, "Unknown frame type {}", frame_type),
+            Self::UnsupportedFrameType(frame_type) => {
+                write!(f, "Unsupported frame type {}", frame_type)
+            }
+            Self::DuplicateKey(key) => write!(f, "Duplicate key {}", key),
+            Self::InvalidUtf8 => write!(f, "Invalid UTF-8 string"),
+            Self::InvalidFlags(flags) => write!(f, "Invalid flags {:#010x}", flags),
+            Self::NoSupportedVersions => write!(f, "No supported versions announced"),
+            Self::InvalidVersion(version) => write!(f, "Invalid version '{}'", version),
+            Self::NoCommonVersion(versions) => {
+                let versions: Vec<_> = versions
+                    .iter()
+                    .map(|v| format!("{}.{}", v.major, v.minor))
+                    .collect();
+                write!(
+                    f,
+                    "No common version, HAProxy supports {}",
+                    versions.join(", ")
+                )
+            }
+            Self::MissingField(field) => write!(f, "Missing field {}", field),
+            Self::WrongFieldType {
+                field,
+                expected,
+                found,
+            } => write!(
+                f,
+                "Wrong type for field {}: expected {}, found {}",
+                field, expected, found
+            ),
+            Self::UnexpectedPayload { expected } => {
+                write!(f, "Invalid FramePayload type, expected {}", expected)
+            }
+            Self::ReplyIdMismatch { expected, found } => write!(
+                f,
+                "Reply ids mismatch: expected stream {} frame {}, found stream {} frame {}",
+                expected.0, expected.1, found.0, found.1
+            ),
+            Self::InvalidMetadata {
+                stream_id,
+                frame_id,
+            } => write!(
+                f,
+                "Invalid metadata: stream {} frame {}, both must be set",
+                stream_id, frame_id
+            ),
+            Self::MissingFin => write!(f, "FIN flag not set"),
+            Self::FragmentationUnsupported => {
+                write!(f, "FIN flag not set, fragmented frames are not supported")
+            }
+            Self::UnexpectedFrame(frame_type) => write!(f, "Unexpected {} frame", frame_type),
+            Self::UnexpectedAbort => write!(f, "ABORT flag set"),
+            Self::TrailingBytes(len) => write!(f, "{} trailing bytes after the frame", len),
+            Self::FrameTooLarge { length, max } => write!(
+                f,
+                "Frame of {} bytes exceeds the maximum of {} bytes",
+                length, max
+            ),
+            Self::BufferFull { pending, max } => write!(
+                f,
+                "Pending buffer of {} bytes exceeds the maximum of {} bytes",
+                pending, max
+            ),
+            Self::TooManyArgs { message, count } => write!(
+                f,
+                "Too many args for {}: {}, NB-ARGS is limited to 255",
+                message, count
+            ),
+            Self::ValueTooLarge { length, max } => write!(
+                f,
+                "Value of {} bytes exceeds the maximum of {} bytes",
+                length, max
+            ),
+            Self::ReservedType(type_id) => write!(f, "Reserved data type {}", type_id),
+            Self::TruncatedValue { key } => write!(f, "Truncated value for key {}", key),
+            Self::InvalidActionArgs {
+                action,
+                expected,
+                got,
+            } => write!(
+                f,
+                "Action {} expects {} args, NB-ARGS is {}",
+                action, expected, got
+            ),
+        }
+    }
+}
+
+impl core::error::Error for SpopError {}
+
+impl<I> ParseError<I> for SpopError {
+    fn from_error_kind(_input: I, kind: ErrorKind) -> Self {
+        Self::Parse(kind)
+    }
+
+    /// Keeps the innermost error, which is the most precise one
+    fn append(_input: I, _kind: ErrorKind, other: Self) -> Self {
+        other
+    }
+}
+
+/// Converts the errors of the parsers using the default nom error type
+impl<I> From<nom::error::Error<I>> for SpopError {
+    fn from(error: nom::error::Error<I>) -> Self {
+        Self::Parse(error.code)
+    }
+}
+
+/// Decoding errors are reported as `InvalidData` by the codec
+#[cfg(feature = "std")]
+impl From<SpopError> for io::Error {
+    fn from(error: SpopError) -> Self {
+        Self::new(io::ErrorKind::InvalidData, error)
+    }
+}
@@ -1,6 +1,14 @@
-use crate::{actions::Action, types::TypedData, varint::encode_varint};
+use crate::HashMap;
+use crate::{
+    actions::Action,
+    types::TypedData,
+    varint::{encode_varint, put_varint},
+};
+use alloc::{string::String, string::ToString, vec, vec::Vec};
+use bytes::BufMut;
+use core::fmt;
+use core::net::IpAddr;
 use nom::error::ErrorKind;
-use std::collections::HashMap;
 
 /// <https://github.com/haproxy/haproxy/blob/master/doc/SPOE.txt#L751>
 ///
@@ -33,6 +41,7 @@ use std::collections::HashMap;
 ///   -----------------------------+-----+-------------------------------------
 /// ```
 #[derive(Debug, Eq, PartialEq, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum FrameType {
     HaproxyHello = 1,
     HaproxyDisconnect = 2,
@@ -55,6 +64,19 @@ impl FrameType {
         }
     }
 
+    /// Returns `true` for the frames sent by HAProxy (types 1 to 3)
+    pub const fn is_from_haproxy(&self) -> bool {
+        matches!(
+            self,
+            Self::HaproxyHello | Self::HaproxyDisconnect | Self::Notify
+        )
+    }
+
+    /// Returns `true` for the frames sent by agents (types 101 to 103)
+    pub const fn is_from_agent(&self) -> bool {
+        matches!(self, Self::AgentHello | Self::AgentDisconnect | Self::Ack)
+    }
+
     /// Converts FrameType to its corresponding u8 value
     pub const fn to_u8(&self) -> u8 {
         match self {
@@ -66,15 +88,101 @@ impl FrameType {
             Self::Ack => 103,
         }
     }
+
+    /// Returns the smallest spec-conformant serialized frame of this type, length prefix
+    /// included
+    ///
+    /// HELLO and DISCONNECT frames only carry their mandatory KV-LIST items, with the smallest
+    /// allowed max-frame-size (256). A NOTIFY holds a single message without arguments and an
+    /// ACK has no actions, both attached to stream 1, frame 1. The output is deterministic, which
+    /// makes it suitable as a fuzzing seed.
+    pub fn minimal_valid_frame(&self) -> Vec<u8> {
+        let (stream_id, frame_id) = match self {
+            Self::Notify | Self::Ack => (1, 1),
+            _ => (0, 0),
+        };
+
+        let metadata = Metadata {
+            flags: FrameFlags::new(true, false),
+            stream_id,
+            frame_id,
+        };
+
+        let mut frame = vec![self.to_u8()];
+        frame.extend(metadata.serialize());
+
+        let kv_list = match self {
+            Self::HaproxyHello => vec![
+                ("supported-versions", TypedData::String("2.0".to_string())),
+                ("max-frame-size", TypedData::UInt32(256)),
+                ("capabilities", TypedData::String(String::new())),
+            ],
+            Self::AgentHello => vec![
+                ("version", TypedData::String("2.0".to_string())),
+                ("max-frame-size", TypedData::UInt32(256)),
+                ("capabilities", TypedData::String(String::new())),
+            ],
+            Self::HaproxyDisconnect | Self::AgentDisconnect => vec![
+                ("status-code", TypedData::UInt32(0)),
+                ("message", TypedData::String(String::new())),
+            ],
+            Self::Notify => {
+                // <MESSAGE-NAME> <NB-ARGS:1 byte>
+                let name = "msg";
+                frame.extend(encode_varint(name.len() as u64));
+                frame.extend_from_slice(name.as_bytes());
+                frame.push(0);
+                vec![]
+            }
+            Self::Ack => vec![],
+        };
+
+        for (key, value) in kv_list {
+            frame.extend(encode_varint(key.len() as u64));
+            frame.extend_from_slice(key.as_bytes());
+            value.to_bytes(&mut frame);
+        }
+
+        let mut output = (frame.len() as u32).to_be_bytes().to_vec();
+        output.extend(frame);
+
+        output
+    }
 }
 
+/// Displays the frame type using its name from the spec, e.g. `HAPROXY-HELLO`
+impl fmt::Display for FrameType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Self::HaproxyHello => "HAPROXY-HELLO",
+            Self::HaproxyDisconnect => "HAPROXY-DISCONNECT",
+            Self::Notify => "NOTIFY",
+            Self::AgentHello => "AGENT-HELLO",
+            Self::AgentDisconnect => "AGENT-DISCONNECT",
+            Self::Ack => "ACK",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// Largest FRAME-LENGTH accepted by the readers, whatever the configured max-frame-size
+///
+/// Lengths are read as `u32` but buffered and sliced as `usize`. Capping them to `i32::MAX`
+/// keeps the frame and its length prefix addressable on 32-bit targets, where allocations are
+/// limited to `isize::MAX` bytes.
+pub const MAX_FRAME_LENGTH: u32 = i32::MAX as u32;
+
 ///  metadata contanis flags, on 4 bytes and a two variable-length integer representing the
 ///  stream identifier and the frame identifier inside the stream:
 ///
 /// ```text
 /// METADATA    : <FLAGS:4 bytes> <STREAM-ID:varint> <FRAME-ID:varint>
 /// ```
-#[derive(Debug, Clone, Default)]
+///
+/// Build it with [`Metadata::new`]. `Metadata::default()` has no flag set, like
+/// [`FrameFlags::default`], and a frame without FIN is rejected by the parser.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Metadata {
     pub flags: FrameFlags,
     pub stream_id: u64,
@@ -82,16 +190,29 @@ pub struct Metadata {
 }
 
 impl Metadata {
+    /// Creates the metadata of a frame with the FIN flag set, as every frame must have
+    pub const fn new(stream_id: u64, frame_id: u64) -> Self {
+        Self {
+            flags: FrameFlags::new(true, false),
+            stream_id,
+            frame_id,
+        }
+    }
+
     pub fn serialize(&self) -> Vec<u8> {
         let mut serialized = Vec::new();
+        self.to_bytes(&mut serialized);
+        serialized
+    }
+
+    /// Writes the metadata at the end of `buf`, see [`Metadata::serialize`]
+    pub(crate) fn to_bytes<B: BufMut + ?Sized>(&self, buf: &mut B) {
         // Serialize flags (4 bytes)
-        serialized.extend_from_slice(&self.flags.to_be_bytes());
+        buf.put_slice(&self.flags.to_be_bytes());
 
         // Serialize stream_id and frame_id (both encoded as varint)
-        serialized.extend(encode_varint(self.stream_id));
-        serialized.extend(encode_varint(self.frame_id));
-
-        serialized
+        put_varint(buf, self.stream_id);
+        put_varint(buf, self.frame_id);
     }
 }
 
@@ -112,24 +233,101 @@ impl Metadata {
 ///     KV-NAME          : <STRING>
 ///     KV-VALUE         : <TYPED-DATA>
 /// ```
-#[derive(Debug)]
+#[derive(Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum FramePayload {
     ListOfMessages(Vec<Message>),
     ListOfActions(Vec<Action>),
     KVList(HashMap<String, TypedData>),
 }
 
+impl FramePayload {
+    /// Returns the number of messages, actions or key/value pairs in the payload
+    pub fn len(&self) -> usize {
+        match self {
+            Self::ListOfMessages(messages) => messages.len(),
+            Self::ListOfActions(actions) => actions.len(),
+            Self::KVList(kv_list) => kv_list.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the name of the items held by the payload, e.g. `actions`
+    pub const fn kind(&self) -> &'static str {
+        match self {
+            Self::ListOfMessages(_) => "messages",
+            Self::ListOfActions(_) => "actions",
+            Self::KVList(_) => "items",
+        }
+    }
+
+    /// Returns the key/value pairs of a KV-LIST payload
+    pub const fn as_kv_list(&self) -> Option<&HashMap<String, TypedData>> {
+        match self {
+            Self::KVList(kv_list) => Some(kv_list),
+            _ => None,
+        }
+    }
+
+    /// Returns the actions of a LIST-OF-ACTIONS payload
+    pub fn as_actions(&self) -> Option<&[Action]> {
+        match self {
+            Self::ListOfActions(actions) => Some(actions),
+            _ => None,
+        }
+    }
+
+    /// Returns the messages of a LIST-OF-MESSAGES payload
+    pub fn as_messages(&self) -> Option<&[Message]> {
+        match self {
+            Self::ListOfMessages(messages) => Some(messages),
+            _ => None,
+        }
+    }
+}
+
 /// Represents a message in the list of messages.
 /// ```text
 ///     LIST-OF-MESSAGES : [ <MESSAGE-NAME> <NB-ARGS:1 byte> <KV-LIST> ... ]
 ///     MESSAGE-NAME     : <STRING>
 /// ```
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Message {
     pub name: String,
     pub args: HashMap<String, TypedData>,
 }
 
+impl Message {
+    /// Argument names looked up by [`Message::client_ip`], in order of preference
+    pub const CLIENT_IP_ARGS: [&'static str; 2] = ["ip", "src"];
+
+    /// Returns the client IP address carried by the message, if any
+    ///
+    /// By convention the client address is passed using the `src` sample fetch, either named
+    /// `ip` or `src`:
+    ///
+    /// ```text
+    /// spoe-message check-client-ip
+    ///     args ip=src
+    ///     event on-client-session
+    /// ```
+    ///
+    /// The first of [`Message::CLIENT_IP_ARGS`] holding an IPv4 or IPv6 value is returned.
+    pub fn client_ip(&self) -> Option<IpAddr> {
+        Self::CLIENT_IP_ARGS
+            .iter()
+            .find_map(|name| match self.args.get(*name) {
+                Some(TypedData::IPv4(addr)) => Some(IpAddr::V4(*addr)),
+                Some(TypedData::IPv6(addr)) => Some(IpAddr::V6(*addr)),
+                _ => None,
+            })
+    }
+}
+
 /// Flags are a 32 bits field. They are encoded on 4 bytes in network byte
 /// order, where the bit 0 is the LSB.
 ///
@@ -153,7 +351,11 @@ pub struct Message {
 /// ABORT: Indicates that the processing of the current frame must be
 ///        cancelled.
 /// ```
-#[derive(Debug, Clone, Default)]
+///
+/// `FrameFlags::default()` has no flag set, which is not valid on the wire since FIN is
+/// mandatory. Use `FrameFlags::new(true, false)`, or [`Metadata::new`] for the whole metadata.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct FrameFlags(u32);
 
 impl FrameFlags {
@@ -180,6 +382,9 @@ impl FrameFlags {
     }
 
     /// Parses FrameFlags from a 4-byte network order field
+    ///
+    /// Fails with `ErrorKind::Verify` when FIN is not set, i.e. on a fragment, and with
+    /// `ErrorKind::Alt` when reserved bits are set.
     pub const fn from_u32(value: u32) -> Result<Self, ErrorKind> {
         // Ensure FIN is always set (per protocol spec)
         if value & 0x00000001 == 0 {
@@ -194,6 +399,24 @@ impl FrameFlags {
         Ok(Self(value))
     }
 
+    /// Builds FrameFlags from raw bits without any check, reserved bits are kept as is
+    ///
+    /// Unlike [`FrameFlags::from_u32`], a missing FIN or reserved bits are accepted, which a
+    /// relay needs to forward the flags of a frame unchanged.
+    pub const fn from_bits_retain(bits: u32) -> Self {
+        Self(bits)
+    }
+
+    /// Builds FrameFlags from raw bits, dropping the reserved ones
+    pub const fn from_bits_truncate(bits: u32) -> Self {
+        Self(bits & 0x00000003)
+    }
+
+    /// Returns the raw bits, including the reserved ones
+    pub const fn bits(&self) -> u32 {
+        self.0
+    }
+
     pub const fn to_be_bytes(&self) -> [u8; 4] {
         self.0.to_be_bytes()
     }
@@ -202,6 +425,8 @@ impl FrameFlags {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::actions::VarScope;
+    use std::net::{Ipv4Addr, Ipv6Addr};
 
     #[test]
     fn test_frame_type_from_u8() {
@@ -224,6 +449,70 @@ mod tests {
         assert_eq!(FrameType::Ack.to_u8(), 103);
     }
 
+    #[test]
+    fn test_frame_type_direction() {
+        let frame_types = [
+            (FrameType::HaproxyHello, true),
+            (FrameType::HaproxyDisconnect, true),
+            (FrameType::Notify, true),
+            (FrameType::AgentHello, false),
+            (FrameType::AgentDisconnect, false),
+            (FrameType::Ack, false),
+        ];
+
+        for (frame_type, from_haproxy) in frame_types {
+            assert_eq!(frame_type.is_from_haproxy(), from_haproxy, "{}", frame_type);
+            assert_eq!(frame_type.is_from_agent(), !from_haproxy, "{}", frame_type);
+        }
+    }
+
+    #[test]
+    fn test_minimal_valid_frame() {
+        let frame_types = [
+            FrameType::HaproxyHello,
+            FrameType::HaproxyDisconnect,
+            FrameType::Notify,
+            FrameType::AgentHello,
+            FrameType::AgentDisconnect,
+            FrameType::Ack,
+        ];
+
+        for frame_type in frame_types {
+            let bytes = frame_type.minimal_valid_frame();
+
+            // deterministic output
+            assert_eq!(bytes, frame_type.minimal_valid_frame());
+
+            // length prefix matches the frame body
+            let length = u32::from_be_bytes(bytes[..4].try_into().unwrap());
+            assert_eq!(length as usize, bytes.len() - 4);
+            assert_eq!(FrameType::from_u8(bytes[4]), Ok(frame_type));
+            assert_eq!(bytes[5..9], FrameFlags::new(true, false).to_be_bytes());
+        }
+
+        // every frame can be parsed back
+        for frame_type in frame_types {
+            let bytes = frame_type.minimal_valid_frame();
+            let (rest, frame) = crate::parser::parse_frame(&bytes)
+                .unwrap_or_else(|e| panic!("Failed to parse {}: {:?}", frame_type, e));
+            assert!(rest.is_empty());
+            assert_eq!(frame.frame_type(), &frame_type);
+        }
+    }
+
+    #[test]
+    fn test_frame_type_display() {
+        assert_eq!(FrameType::HaproxyHello.to_string(), "HAPROXY-HELLO");
+        assert_eq!(
+            FrameType::HaproxyDisconnect.to_string(),
+            "HAPROXY-DISCONNECT"
+        );
+        assert_eq!(FrameType::Notify.to_string(), "NOTIFY");
+        assert_eq!(FrameType::AgentHello.to_string(), "AGENT-HELLO");
+        assert_eq!(FrameType::AgentDisconnect.to_string(), "AGENT-DISCONNECT");
+        assert_eq!(FrameType::Ack.to_string(), "ACK");
+    }
+
     #[test]
     fn test_frameflags() {
         let flags = FrameFlags(0x00000001);
@@ -240,6 +529,30 @@ mod tests {
         assert!(flags.is_abort());
     }
 
+    #[test]
+    fn test_frameflags_bits() {
+        // reserved bits are rejected by the strict constructor only
+        let bits = 0x80000005;
+        assert_eq!(FrameFlags::from_u32(bits), Err(ErrorKind::Alt));
+
+        let flags = FrameFlags::from_bits_retain(bits);
+        assert!(flags.is_fin());
+        assert!(!flags.is_abort());
+        assert_eq!(flags.bits(), bits);
+        assert_eq!(flags.to_be_bytes(), [0x80, 0x00, 0x00, 0x05]);
+        assert_eq!(
+            FrameFlags::from_bits_retain(u32::from_be_bytes(flags.to_be_bytes())),
+            flags
+        );
+
+        let flags = FrameFlags::from_bits_truncate(bits);
+        assert_eq!(flags.bits(), 0x00000001);
+        assert_eq!(FrameFlags::from_u32(flags.bits()), Ok(flags));
+
+        // FIN is not required either
+        assert_eq!(FrameFlags::from_bits_retain(0x00000002).bits(), 0x00000002);
+    }
+
     #[test]
     fn test_frameflags_new() {
         let flags = FrameFlags::new(true, false);
@@ -251,4 +564,163 @@ mod tests {
         let flags = FrameFlags::new(true, true);
         assert_eq!(flags.0, 0x00000003);
     }
+
+    #[test]
+    fn test_metadata_new() {
+        use crate::{SpopError, SpopFrame, frames::notify::NotifyFrame, parser::parse_frame};
+
+        let metadata = Metadata::new(3, 7);
+        assert!(metadata.flags.is_fin());
+        assert!(!metadata.flags.is_abort());
+
+        let notify = NotifyFrame {
+            metadata: metadata.clone(),
+            messages: vec![],
+        };
+        let (_, parsed) = parse_frame(&notify.serialize().unwrap()).unwrap();
+        assert_eq!(parsed.metadata(), metadata);
+
+        // FIN is not set by default
+        let notify = NotifyFrame {
+            metadata: Metadata {
+                stream_id: 3,
+                frame_id: 7,
+                ..Metadata::default()
+            },
+            messages: vec![],
+        };
+        assert_eq!(
+            parse_frame(&notify.serialize().unwrap()).unwrap_err(),
+            nom::Err::Error(SpopError::FragmentationUnsupported)
+        );
+    }
+
+    #[test]
+    fn test_message_client_ip() {
+        let message = Message {
+            name: "check-client-ip".to_string(),
+            args: HashMap::from([(
+                "src".to_string(),
+                TypedData::IPv4(Ipv4Addr::new(192, 168, 0, 1)),
+            )]),
+        };
+        assert_eq!(
+            message.client_ip(),
+            Some(IpAddr::V4(Ipv4Addr::new(192, 168, 0, 1)))
+        );
+
+        // "ip" takes precedence over "src"
+        let message = Message {
+            name: "check-client-ip".to_string(),
+            args: HashMap::from([
+                ("ip".to_string(), TypedData::IPv6(Ipv6Addr::LOCALHOST)),
+                (
+                    "src".to_string(),
+                    TypedData::IPv4(Ipv4Addr::new(10, 0, 0, 1)),
+                ),
+            ]),
+        };
+        assert_eq!(message.client_ip(), Some(IpAddr::V6(Ipv6Addr::LOCALHOST)));
+
+        // wrong type or missing argument
+        let message = Message {
+            name: "check-client-ip".to_string(),
+            args: HashMap::from([(
+                "ip".to_string(),
+                TypedData::String("192.168.0.1".to_string()),
+            )]),
+        };
+        assert_eq!(message.client_ip(), None);
+    }
+
+    #[test]
+    fn test_frame_payload_len() {
+        let payload = FramePayload::ListOfMessages(vec![]);
+        assert_eq!(payload.len(), 0);
+        assert!(payload.is_empty());
+
+        let payload = FramePayload::ListOfMessages(vec![
+            Message {
+                name: "check-client-ip".to_string(),
+                args: HashMap::new(),
+            },
+            Message {
+                name: "log-request".to_string(),
+                args: HashMap::new(),
+            },
+        ]);
+        assert_eq!(payload.len(), 2);
+        assert!(!payload.is_empty());
+
+        let payload = FramePayload::ListOfActions(vec![
+            Action::SetVar {
+                scope: VarScope::Session,
+                name: "ip_score".to_string(),
+                value: TypedData::UInt32(42),
+            },
+            Action::UnSetVar {
+                scope: VarScope::Transaction,
+                name: "my_var".to_string(),
+            },
+            Action::UnSetVar {
+                scope: VarScope::Request,
+                name: "other".to_string(),
+            },
+        ]);
+        assert_eq!(payload.len(), 3);
+        assert!(!payload.is_empty());
+
+        let payload = FramePayload::KVList(HashMap::from([
+            ("status-code".to_string(), TypedData::UInt32(0)),
+            ("message".to_string(), TypedData::String("ok".to_string())),
+        ]));
+        assert_eq!(payload.len(), 2);
+        assert!(!payload.is_empty());
+
+        let payload = FramePayload::KVList(HashMap::new());
+        assert!(payload.is_empty());
+    }
+
+    #[test]
+    fn test_frame_payload_as_kv_list() {
+        let payload = FramePayload::KVList(HashMap::from([(
+            "status-code".to_string(),
+            TypedData::UInt32(0),
+        )]));
+        let kv_list = payload.as_kv_list().unwrap();
+        assert_eq!(kv_list["status-code"], TypedData::UInt32(0));
+        assert!(payload.as_actions().is_none());
+        assert!(payload.as_messages().is_none());
+    }
+
+    #[test]
+    fn test_frame_payload_as_actions() {
+        let payload = FramePayload::ListOfActions(vec![Action::UnSetVar {
+            scope: VarScope::Transaction,
+            name: "my_var".to_string(),
+        }]);
+        assert_eq!(payload.as_actions().unwrap().len(), 1);
+        assert!(payload.as_kv_list().is_none());
+        assert!(payload.as_messages().is_none());
+    }
+
+    #[test]
+    fn test_frame_payload_as_messages() {
+        let payload = FramePayload::ListOfMessages(vec![Message {
+            name: "check-client-ip".to_string(),
+            args: HashMap::new(),
+        }]);
+        assert_eq!(payload.as_messages().unwrap()[0].name, "check-client-ip");
+        assert!(payload.as_kv_list().is_none());
+        assert!(payload.as_actions().is_none());
+    }
+
+    proptest::proptest! {
+        #[test]
+        fn prop_frame_type_round_trip(byte: u8) {
+            if let Ok(frame_type) = FrameType::from_u8(byte) {
+                proptest::prop_assert_eq!(frame_type.to_u8(), byte);
+            }
+        }
+    }
 }
@@ -1,9 +1,15 @@
 use crate::{
-    SpopFrame,
-    actions::{Action, VarScope},
+    FrameVisitor, SpopError, SpopFrame,
+    actions::{
+        Action, VarScope,
+        scope::{RequestScope, ResponseScope},
+    },
     frame::{FrameFlags, FramePayload, FrameType, Metadata},
+    frames::notify::NotifyFrame,
     types::TypedData,
 };
+use alloc::{string::String, string::ToString, vec::Vec};
+use bytes::BufMut;
 
 /// Frame Ack
 ///
@@ -17,17 +23,20 @@ use crate::{
 /// FRAME-ID found in a NOTIFY frame must be reuse in the corresponding ACK
 /// frame. The payload of ACK frames is a LIST-OF-ACTIONS.
 /// ```
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Ack {
+    pub(crate) flags: FrameFlags,
     pub stream_id: u64,
     pub frame_id: u64,
     pub actions: Vec<Action>,
 }
 
 impl Ack {
-    /// Creates a new ACK frame with no actions
+    /// Creates a new ACK frame with no actions and the FIN flag set
     pub const fn new(stream_id: u64, frame_id: u64) -> Self {
         Self {
+            flags: FrameFlags::new(true, false),
             stream_id,
             frame_id,
             actions: Vec::new(),
@@ -35,23 +44,125 @@ impl Ack {
     }
 
     /// Adds a set-var action to the ACK frame
-    pub fn set_var(mut self, scope: VarScope, name: &str, value: TypedData) -> Self {
+    pub fn set_var(self, scope: VarScope, name: &str, value: impl Into<TypedData>) -> Self {
+        self.set_var_owned(scope, name.to_string(), value)
+    }
+
+    /// Same as [`Ack::set_var`], moving the name in instead of copying it
+    pub fn set_var_owned(
+        mut self,
+        scope: VarScope,
+        name: String,
+        value: impl Into<TypedData>,
+    ) -> Self {
         self.actions.push(Action::SetVar {
             scope,
-            name: name.to_string(),
-            value,
+            name,
+            value: value.into(),
         });
         self
     }
 
     // Adds an unset-var action to the ACK frame
-    pub fn unset_var(mut self, scope: VarScope, name: &str) -> Self {
-        self.actions.push(Action::UnSetVar {
-            scope,
-            name: name.to_string(),
-        });
+    pub fn unset_var(self, scope: VarScope, name: &str) -> Self {
+        self.unset_var_owned(scope, name.to_string())
+    }
+
+    /// Same as [`Ack::unset_var`], moving the name in instead of copying it
+    pub fn unset_var_owned(mut self, scope: VarScope, name: String) -> Self {
+        self.actions.push(Action::UnSetVar { scope, name });
         self
     }
+
+    /// Returns the flags of the frame, FIN set and ABORT clear unless changed with
+    /// [`Ack::with_flags`]
+    pub const fn flags(&self) -> &FrameFlags {
+        &self.flags
+    }
+
+    /// Replaces the flags of the frame, e.g. to check how HAProxy handles an ACK without FIN
+    pub fn with_flags(mut self, flags: FrameFlags) -> Self {
+        self.flags = flags;
+        self
+    }
+
+    /// Returns the actions added so far
+    pub fn actions(&self) -> &[Action] {
+        &self.actions
+    }
+
+    /// Keeps only the actions for which `f` returns true, preserving their order
+    pub fn retain_actions(&mut self, f: impl FnMut(&Action) -> bool) {
+        self.actions.retain(f);
+    }
+
+    /// Checks that this ACK is a legal reply to the given NOTIFY frame
+    ///
+    /// The STREAM-ID and FRAME-ID must be the ones of the NOTIFY frame, the FIN flag must be set
+    /// and the ABORT flag must be clear.
+    pub fn is_valid_reply_to(&self, notify: &NotifyFrame) -> Result<(), SpopError> {
+        let expected = (notify.metadata.stream_id, notify.metadata.frame_id);
+        let found = (self.stream_id, self.frame_id);
+        if expected != found {
+            return Err(SpopError::ReplyIdMismatch { expected, found });
+        }
+
+        if !self.flags.is_fin() {
+            return Err(SpopError::MissingFin);
+        }
+
+        if self.flags.is_abort() {
+            return Err(SpopError::UnexpectedAbort);
+        }
+
+        Ok(())
+    }
+
+    /// Lists the variables this ACK would set or unset, in order, for audit logging
+    ///
+    /// [`Action::Unknown`] actions are skipped, the variable they act on is not known.
+    pub fn audit_log(&self) -> Vec<AuditEntry> {
+        self.actions
+            .iter()
+            .filter_map(|action| match action {
+                Action::SetVar { scope, name, value } => Some(AuditEntry {
+                    action: "set-var",
+                    scope: scope.clone(),
+                    name: name.clone(),
+                    value: Some(value.clone()),
+                    expr: None,
+                }),
+                Action::UnSetVar { scope, name } => Some(AuditEntry {
+                    action: "unset-var",
+                    scope: scope.clone(),
+                    name: name.clone(),
+                    value: None,
+                    expr: None,
+                }),
+                Action::SetVarExpr { scope, name, expr } => Some(AuditEntry {
+                    action: "set-var-expr",
+                    scope: scope.clone(),
+                    name: name.clone(),
+                    value: None,
+                    expr: Some(expr.clone()),
+                }),
+                Action::Unknown { .. } => None,
+            })
+            .collect()
+    }
+}
+
+/// A variable change made by an ACK frame, see [`Ack::audit_log`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuditEntry {
+    /// Name of the action, e.g. `set-var`
+    pub action: &'static str,
+    pub scope: VarScope,
+    pub name: String,
+    /// Value set by `set-var`, `None` for the other actions
+    pub value: Option<TypedData>,
+    /// Expression evaluated by HAProxy for `set-var-expr`, `None` for the other actions
+    pub expr: Option<String>,
 }
 
 /// Serializes the ACK frame into a `Frame` structure
@@ -62,7 +173,7 @@ impl SpopFrame for Ack {
 
     fn metadata(&self) -> Metadata {
         Metadata {
-            flags: FrameFlags::new(true, false), // FIN flag set, ABORT flag not set
+            flags: self.flags.clone(),
             stream_id: self.stream_id,
             frame_id: self.frame_id,
         }
@@ -71,4 +182,347 @@ impl SpopFrame for Ack {
     fn payload(&self) -> FramePayload {
         FramePayload::ListOfActions(self.actions.clone())
     }
+
+    fn payload_len(&self) -> usize {
+        crate::actions_len(&self.actions)
+    }
+
+    fn write_payload(
+        &self,
+        buf: &mut dyn BufMut,
+        _order: Option<&[&str]>,
+    ) -> Result<(), SpopError> {
+        crate::encode_actions(&self.actions, buf)
+    }
+
+    fn accept(&self, visitor: &mut dyn FrameVisitor) {
+        visitor.visit_ack(self);
+    }
+}
+
+/// Builds an `Ack` from the metadata and payload of a parsed ACK frame
+impl TryFrom<(Metadata, FramePayload)> for Ack {
+    type Error = SpopError;
+
+    fn try_from((metadata, payload): (Metadata, FramePayload)) -> Result<Self, Self::Error> {
+        // Ensure that the payload is a ListOfActions
+        if let FramePayload::ListOfActions(actions) = payload {
+            Ok(Self {
+                flags: metadata.flags,
+                stream_id: metadata.stream_id,
+                frame_id: metadata.frame_id,
+                actions,
+            })
+        } else {
+            Err(SpopError::UnexpectedPayload {
+                expected: "ListOfActions",
+            })
+        }
+    }
+}
+
+/// ACK builder for the request processing phase
+///
+/// Only `Process`, `Session`, `Transaction` and `Request` scoped variables can be set, setting a
+/// `res.` variable while processing a request has no effect and is rejected at compile time
+/// (see `tests/ui`):
+///
+/// ```
+/// use spop::{actions::scope, frames::RequestAck, types::TypedData};
+///
+/// let ack = RequestAck::new(1, 1)
+///     .set_var(scope::Transaction, "my_var", TypedData::UInt32(1))
+///     .into_ack();
+/// assert_eq!(ack.actions.len(), 1);
+/// ```
+#[derive(Debug, Clone)]
+pub struct RequestAck(Ack);
+
+impl RequestAck {
+    pub const fn new(stream_id: u64, frame_id: u64) -> Self {
+        Self(Ack::new(stream_id, frame_id))
+    }
+
+    /// Adds a set-var action to the ACK frame
+    pub fn set_var<S: RequestScope>(
+        self,
+        _scope: S,
+        name: &str,
+        value: impl Into<TypedData>,
+    ) -> Self {
+        Self(self.0.set_var(S::SCOPE, name, value))
+    }
+
+    /// Adds an unset-var action to the ACK frame
+    pub fn unset_var<S: RequestScope>(self, _scope: S, name: &str) -> Self {
+        Self(self.0.unset_var(S::SCOPE, name))
+    }
+
+    pub fn into_ack(self) -> Ack {
+        self.0
+    }
+}
+
+/// ACK builder for the response processing phase
+///
+/// Only `Process`, `Session`, `Transaction` and `Response` scoped variables can be set, a `req.`
+/// variable is rejected at compile time (see `tests/ui`).
+#[derive(Debug, Clone)]
+pub struct ResponseAck(Ack);
+
+impl ResponseAck {
+    pub const fn new(stream_id: u64, frame_id: u64) -> Self {
+        Self(Ack::new(stream_id, frame_id))
+    }
+
+    /// Adds a set-var action to the ACK frame
+    pub fn set_var<S: ResponseScope>(
+        self,
+        _scope: S,
+        name: &str,
+        value: impl Into<TypedData>,
+    ) -> Self {
+        Self(self.0.set_var(S::SCOPE, name, value))
+    }
+
+    /// Adds an unset-var action to the ACK frame
+    pub fn unset_var<S: ResponseScope>(self, _scope: S, name: &str) -> Self {
+        Self(self.0.unset_var(S::SCOPE, name))
+    }
+
+    pub fn into_ack(self) -> Ack {
+        self.0
+    }
+}
+
+impl From<RequestAck> for Ack {
+    fn from(ack: RequestAck) -> Self {
+        ack.0
+    }
+}
+
+impl From<ResponseAck> for Ack {
+    fn from(ack: ResponseAck) -> Self {
+        ack.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::actions::scope;
+
+    #[test]
+    fn test_ack_round_trip() {
+        let ack = Ack::new(3, 7)
+            .set_var(VarScope::Session, "ip_score", TypedData::UInt32(42))
+            .set_var(
+                VarScope::Transaction,
+                "my_var",
+                TypedData::String("tequila".to_string()),
+            )
+            .unset_var(VarScope::Request, "other");
+
+        let serialized = ack.serialize().unwrap();
+        let (rest, frame) = crate::parser::parse_frame(&serialized).unwrap();
+        assert!(rest.is_empty());
+        assert_eq!(frame.frame_type(), &FrameType::Ack);
+
+        let parsed = Ack::try_from((frame.metadata(), frame.payload())).unwrap();
+        assert_eq!(parsed, ack);
+
+        // wrong payload type
+        let payload = FramePayload::ListOfMessages(vec![]);
+        assert_eq!(
+            Ack::try_from((Metadata::default(), payload)).unwrap_err(),
+            SpopError::UnexpectedPayload {
+                expected: "ListOfActions"
+            }
+        );
+    }
+
+    #[test]
+    fn test_ack_owned_names() {
+        let names = vec!["score".to_string(), "tmp".to_string()];
+
+        let borrowed = Ack::new(1, 2)
+            .set_var(VarScope::Session, &names[0], TypedData::UInt32(42))
+            .unset_var(VarScope::Transaction, &names[1]);
+
+        let mut names = names.into_iter();
+        let owned = Ack::new(1, 2)
+            .set_var_owned(VarScope::Session, names.next().unwrap(), 42u32)
+            .unset_var_owned(VarScope::Transaction, names.next().unwrap());
+
+        assert_eq!(owned.actions(), borrowed.actions());
+        assert_eq!(owned.serialize().unwrap(), borrowed.serialize().unwrap());
+    }
+
+    #[test]
+    fn test_ack_summary() {
+        let ack = Ack::new(3, 7)
+            .set_var(VarScope::Session, "ip_score", TypedData::UInt32(42))
+            .unset_var(VarScope::Transaction, "my_var");
+        assert_eq!(ack.summary(), "ACK stream=3 frame=7 actions=2");
+
+        let ack = Ack::new(1, 1);
+        assert_eq!(ack.summary(), "ACK stream=1 frame=1 actions=0");
+    }
+
+    #[test]
+    fn test_request_ack() {
+        let ack = RequestAck::new(3, 7)
+            .set_var(scope::Request, "path", TypedData::String("/".to_string()))
+            .set_var(scope::Session, "ip_score", TypedData::UInt32(42))
+            .unset_var(scope::Transaction, "my_var")
+            .into_ack();
+
+        assert_eq!(ack.stream_id, 3);
+        assert_eq!(ack.frame_id, 7);
+        assert_eq!(ack.actions.len(), 3);
+
+        match &ack.actions[0] {
+            Action::SetVar { scope, name, .. } => {
+                assert_eq!(scope.to_u8(), VarScope::Request.to_u8());
+                assert_eq!(name, "path");
+            }
+            action => panic!("Unexpected action: {:?}", action),
+        }
+
+        match &ack.actions[2] {
+            Action::UnSetVar { scope, name } => {
+                assert_eq!(scope.to_u8(), VarScope::Transaction.to_u8());
+                assert_eq!(name, "my_var");
+            }
+            action => panic!("Unexpected action: {:?}", action),
+        }
+    }
+
+    #[test]
+    fn test_response_ack() {
+        let ack: Ack = ResponseAck::new(1, 2)
+            .set_var(scope::Response, "status", TypedData::UInt32(200))
+            .into();
+
+        // same bytes as the untyped builder
+        let expected = Ack::new(1, 2).set_var(VarScope::Response, "status", TypedData::UInt32(200));
+        assert_eq!(ack.serialize().unwrap(), expected.serialize().unwrap());
+    }
+
+    #[test]
+    fn test_ack_is_valid_reply_to() {
+        let notify = NotifyFrame {
+            metadata: Metadata {
+                flags: FrameFlags::new(true, false),
+                stream_id: 3,
+                frame_id: 7,
+            },
+            messages: vec![],
+        };
+
+        assert_eq!(Ack::new(3, 7).is_valid_reply_to(&notify), Ok(()));
+
+        assert_eq!(
+            Ack::new(3, 8).is_valid_reply_to(&notify),
+            Err(SpopError::ReplyIdMismatch {
+                expected: (3, 7),
+                found: (3, 8),
+            })
+        );
+
+        let ack = Ack::new(3, 7).with_flags(FrameFlags::new(false, false));
+        assert!(!ack.flags().is_fin());
+        assert_eq!(ack.is_valid_reply_to(&notify), Err(SpopError::MissingFin));
+
+        let ack = Ack::new(3, 7).with_flags(FrameFlags::new(true, true));
+        assert_eq!(
+            ack.is_valid_reply_to(&notify),
+            Err(SpopError::UnexpectedAbort)
+        );
+    }
+
+    #[test]
+    fn test_ack_audit_log() {
+        let mut ack = Ack::new(1, 1)
+            .set_var(VarScope::Session, "ip_score", TypedData::UInt32(42))
+            .unset_var(VarScope::Transaction, "my_var")
+            .set_var(
+                VarScope::Request,
+                "path",
+                TypedData::String("/".to_string()),
+            );
+        ack.actions.push(Action::SetVarExpr {
+            scope: VarScope::Transaction,
+            name: "host".to_string(),
+            expr: "req.hdr(host)".to_string(),
+        });
+
+        assert_eq!(
+            ack.audit_log(),
+            vec![
+                AuditEntry {
+                    action: "set-var",
+                    scope: VarScope::Session,
+                    name: "ip_score".to_string(),
+                    value: Some(TypedData::UInt32(42)),
+                    expr: None,
+                },
+                AuditEntry {
+                    action: "unset-var",
+                    scope: VarScope::Transaction,
+                    name: "my_var".to_string(),
+                    value: None,
+                    expr: None,
+                },
+                AuditEntry {
+                    action: "set-var",
+                    scope: VarScope::Request,
+                    name: "path".to_string(),
+                    value: Some(TypedData::String("/".to_string())),
+                    expr: None,
+                },
+                AuditEntry {
+                    action: "set-var-expr",
+                    scope: VarScope::Transaction,
+                    name: "host".to_string(),
+                    value: None,
+                    expr: Some("req.hdr(host)".to_string()),
+                },
+            ]
+        );
+
+        assert!(Ack::new(1, 1).audit_log().is_empty());
+    }
+
+    #[test]
+    fn test_ack_retain_actions() {
+        let mut ack = Ack::new(1, 1)
+            .set_var(VarScope::Session, "ip_score", TypedData::UInt32(42))
+            .unset_var(VarScope::Transaction, "my_var")
+            .set_var(
+                VarScope::Request,
+                "path",
+                TypedData::String("/".to_string()),
+            )
+            .unset_var(VarScope::Session, "other");
+        assert_eq!(ack.actions().len(), 4);
+
+        ack.retain_actions(|action| matches!(action, Action::SetVar { .. }));
+
+        assert_eq!(
+            ack.actions(),
+            &[
+                Action::SetVar {
+                    scope: VarScope::Session,
+                    name: "ip_score".to_string(),
+                    value: TypedData::UInt32(42),
+                },
+                Action::SetVar {
+                    scope: VarScope::Request,
+                    name: "path".to_string(),
+                    value: TypedData::String("/".to_string()),
+                },
+            ]
+        );
+    }
 }
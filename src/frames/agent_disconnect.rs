@@ -1,9 +1,14 @@
 use crate::{
-    SpopFrame,
+    FrameVisitor, SpopError, SpopFrame,
     frame::{FrameFlags, FramePayload, FrameType, Metadata},
+    frames::{
+        DisconnectStatus, KvItem, KvValue, kv_items_len, kv_items_to_map, mandatory,
+        write_kv_items, wrong_type,
+    },
     types::TypedData,
 };
-use std::collections::HashMap;
+use alloc::{string::String, string::ToString};
+use bytes::BufMut;
 
 /// Frame AGENT-DISCONNECT
 ///
@@ -33,12 +38,64 @@ use std::collections::HashMap;
 ///
 /// For more information about known errors, see section "Errors & timeouts"
 /// ```
-#[derive(Debug)]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct AgentDisconnect {
     pub status_code: u32,
     pub message: String,
 }
 
+impl AgentDisconnect {
+    /// Returns the status code as one of the documented SPOE errors
+    pub const fn status(&self) -> DisconnectStatus {
+        DisconnectStatus::from_u32(self.status_code)
+    }
+
+    /// Builds the AGENT-DISCONNECT reply to a failed handshake or an invalid frame
+    ///
+    /// The status code is the one of the spec matching the error, e.g. "unsupported version"
+    /// when no version is common to both peers, and "invalid frame received" when there is no
+    /// better match. The message is the description of the error.
+    ///
+    /// ```
+    /// use spop::{SpopError, frames::{AgentDisconnect, DisconnectStatus}};
+    ///
+    /// let disconnect = AgentDisconnect::from_error(&SpopError::NoSupportedVersions);
+    /// assert_eq!(disconnect.status(), DisconnectStatus::VersionNotFound);
+    /// ```
+    pub fn from_error(error: &SpopError) -> Self {
+        let status = match error {
+            SpopError::MissingField("supported-versions") | SpopError::NoSupportedVersions => {
+                DisconnectStatus::VersionNotFound
+            }
+            SpopError::MissingField("max-frame-size") => DisconnectStatus::MaxFrameSizeNotFound,
+            SpopError::MissingField("capabilities") => DisconnectStatus::CapabilitiesNotFound,
+            SpopError::NoCommonVersion(_) | SpopError::InvalidVersion(_) => {
+                DisconnectStatus::UnsupportedVersion
+            }
+            SpopError::FrameTooLarge { .. } | SpopError::ValueTooLarge { .. } => {
+                DisconnectStatus::FrameTooBig
+            }
+            SpopError::FragmentationUnsupported => DisconnectStatus::FragmentationNotSupported,
+            SpopError::ReplyIdMismatch { .. } => DisconnectStatus::FrameIdNotFound,
+            _ => DisconnectStatus::InvalidFrame,
+        };
+
+        Self {
+            status_code: status.to_u32(),
+            message: error.to_string(),
+        }
+    }
+
+    /// Calls `f` with the KV-LIST items of the frame
+    fn with_kv_items<R>(&self, f: impl FnOnce(&[KvItem<'_>]) -> R) -> R {
+        f(&[
+            Some(("status-code", KvValue::UInt32(self.status_code))),
+            Some(("message", KvValue::String(&self.message))),
+        ])
+    }
+}
+
 impl SpopFrame for AgentDisconnect {
     fn frame_type(&self) -> &FrameType {
         &FrameType::AgentDisconnect
@@ -53,18 +110,114 @@ impl SpopFrame for AgentDisconnect {
     }
 
     fn payload(&self) -> FramePayload {
-        let mut map = HashMap::new();
+        FramePayload::KVList(self.with_kv_items(kv_items_to_map))
+    }
+
+    fn payload_len(&self) -> usize {
+        self.with_kv_items(kv_items_len)
+    }
 
-        map.insert(
+    fn write_payload(&self, buf: &mut dyn BufMut, order: Option<&[&str]>) -> Result<(), SpopError> {
+        self.with_kv_items(|items| write_kv_items(items, buf, order));
+        Ok(())
+    }
+
+    fn accept(&self, visitor: &mut dyn FrameVisitor) {
+        visitor.visit_agent_disconnect(self);
+    }
+}
+
+impl TryFrom<FramePayload> for AgentDisconnect {
+    type Error = SpopError;
+
+    fn try_from(payload: FramePayload) -> Result<Self, Self::Error> {
+        // Ensure that the payload is a KVList
+        if let FramePayload::KVList(kv_list) = payload {
+            let status_code = match mandatory(&kv_list, "status-code")? {
+                TypedData::UInt32(val) => *val,
+                other => return Err(wrong_type("status-code", "UINT32", other)),
+            };
+
+            let message = match mandatory(&kv_list, "message")? {
+                TypedData::String(val) => val.clone(),
+                other => return Err(wrong_type("message", "STRING", other)),
+            };
+
+            Ok(Self {
+                status_code,
+                message,
+            })
+        } else {
+            Err(SpopError::UnexpectedPayload { expected: "KVList" })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::HashMap;
+    use crate::parser::parse_frame;
+
+    #[test]
+    fn test_agent_disconnect_decode() {
+        let disconnect = AgentDisconnect {
+            status_code: 3,
+            message: "frame is too big".to_string(),
+        };
+
+        let serialized = disconnect.serialize().unwrap();
+        let (rest, frame) = parse_frame(&serialized).unwrap();
+        assert!(rest.is_empty());
+        assert_eq!(frame.frame_type(), &FrameType::AgentDisconnect);
+
+        let decoded = AgentDisconnect::try_from(frame.payload()).unwrap();
+        assert_eq!(decoded.status_code, 3);
+        assert_eq!(decoded.status(), DisconnectStatus::FrameTooBig);
+        assert_eq!(decoded.message, "frame is too big");
+
+        let payload = FramePayload::KVList(HashMap::from([(
             "status-code".to_string(),
-            TypedData::UInt32(self.status_code),
+            TypedData::UInt32(0),
+        )]));
+        assert_eq!(
+            AgentDisconnect::try_from(payload).unwrap_err(),
+            SpopError::MissingField("message")
         );
+    }
 
-        map.insert(
-            "message".to_string(),
-            TypedData::String(self.message.clone()),
-        );
+    #[test]
+    fn test_agent_disconnect_from_error() {
+        let cases = [
+            (
+                SpopError::NoCommonVersion(vec![semver::Version::new(1, 0, 0)]),
+                DisconnectStatus::UnsupportedVersion,
+            ),
+            (
+                SpopError::MissingField("max-frame-size"),
+                DisconnectStatus::MaxFrameSizeNotFound,
+            ),
+            (
+                SpopError::FrameTooLarge {
+                    length: 65536,
+                    max: 16380,
+                },
+                DisconnectStatus::FrameTooBig,
+            ),
+            (
+                SpopError::FragmentationUnsupported,
+                DisconnectStatus::FragmentationNotSupported,
+            ),
+            (
+                SpopError::UnknownFrameType(42),
+                DisconnectStatus::InvalidFrame,
+            ),
+        ];
 
-        FramePayload::KVList(map)
+        for (error, status) in cases {
+            let disconnect = AgentDisconnect::from_error(&error);
+            assert_eq!(disconnect.status(), status, "{:?}", error);
+            assert_eq!(disconnect.message, error.to_string());
+        }
     }
 }
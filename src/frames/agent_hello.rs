@@ -1,11 +1,17 @@
 use crate::{
-    SpopFrame,
+    FrameVisitor, SpopError, SpopFrame,
     frame::{FrameFlags, FramePayload, FrameType, Metadata},
-    frames::capabilities::FrameCapabilities,
+    frames::{
+        KvItem, KvValue,
+        capabilities::{FrameCapabilities, JoinedCapabilities},
+        haproxy_hello::{MajorMinor, parse_capabilities_item, parse_version},
+        kv_items_len, kv_items_to_map, mandatory, write_kv_items, wrong_type,
+    },
     types::TypedData,
 };
+use alloc::vec::Vec;
+use bytes::BufMut;
 use semver::Version;
-use std::collections::HashMap;
 
 /// Frame AGENT-HELLO
 ///
@@ -46,13 +52,35 @@ use std::collections::HashMap;
 /// safely close the connection without DISCONNECT frame. In all cases, HAProxy
 /// will close the connection at the end of the health check.
 /// ```
-#[derive(Debug)]
+///
+/// An agent must only advertise the capabilities it implements, never blindly mirror the ones
+/// announced by HAProxy. `FrameCapabilities::Unknown` entries are therefore dropped when the
+/// frame is serialized.
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct AgentHello {
     pub version: Version,
     pub max_frame_size: u32,
     pub capabilities: Vec<FrameCapabilities>,
 }
 
+impl AgentHello {
+    /// Calls `f` with the KV-LIST items of the frame
+    fn with_kv_items<R>(&self, f: impl FnOnce(&[KvItem<'_>]) -> R) -> R {
+        let version = MajorMinor(&self.version);
+        let capabilities = JoinedCapabilities {
+            capabilities: &self.capabilities,
+            known_only: true,
+        };
+
+        f(&[
+            Some(("version", KvValue::String(&version))),
+            Some(("max-frame-size", KvValue::UInt32(self.max_frame_size))),
+            Some(("capabilities", KvValue::String(&capabilities))),
+        ])
+    }
+}
+
 impl SpopFrame for AgentHello {
     fn frame_type(&self) -> &FrameType {
         &FrameType::AgentHello
@@ -67,24 +95,141 @@ impl SpopFrame for AgentHello {
     }
 
     fn payload(&self) -> FramePayload {
-        let mut map = HashMap::new();
+        FramePayload::KVList(self.with_kv_items(kv_items_to_map))
+    }
+
+    fn payload_len(&self) -> usize {
+        self.with_kv_items(kv_items_len)
+    }
+
+    fn write_payload(&self, buf: &mut dyn BufMut, order: Option<&[&str]>) -> Result<(), SpopError> {
+        self.with_kv_items(|items| write_kv_items(items, buf, order));
+        Ok(())
+    }
+
+    fn accept(&self, visitor: &mut dyn FrameVisitor) {
+        visitor.visit_agent_hello(self);
+    }
+}
+
+impl TryFrom<FramePayload> for AgentHello {
+    type Error = SpopError;
+
+    fn try_from(payload: FramePayload) -> Result<Self, Self::Error> {
+        // Ensure that the payload is a KVList
+        if let FramePayload::KVList(kv_list) = payload {
+            let version = match mandatory(&kv_list, "version")? {
+                TypedData::String(v) => parse_version(v.trim())?,
+                other => return Err(wrong_type("version", "STRING", other)),
+            };
+
+            let max_frame_size = match mandatory(&kv_list, "max-frame-size")? {
+                TypedData::UInt32(val) => *val,
+                other => return Err(wrong_type("max-frame-size", "UINT32", other)),
+            };
+
+            let capabilities = parse_capabilities_item(mandatory(&kv_list, "capabilities")?)?;
+
+            Ok(Self {
+                version,
+                max_frame_size,
+                capabilities,
+            })
+        } else {
+            Err(SpopError::UnexpectedPayload { expected: "KVList" })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_agent_hello_drops_unknown_capabilities() {
+        let hello = AgentHello {
+            version: Version::new(2, 0, 0),
+            max_frame_size: 16380,
+            capabilities: vec![
+                FrameCapabilities::Unknown("frobnicate".to_string()),
+                FrameCapabilities::Pipelining,
+            ],
+        };
+
+        match hello.payload() {
+            FramePayload::KVList(kv_list) => {
+                assert_eq!(
+                    kv_list.get("capabilities"),
+                    Some(&TypedData::String("pipelining".to_string()))
+                );
+            }
+            _ => panic!("Wrong type of payload"),
+        }
+
+        let hello = AgentHello {
+            version: Version::new(2, 0, 0),
+            max_frame_size: 16380,
+            capabilities: vec![FrameCapabilities::Unknown("fragmentation".to_string())],
+        };
+
+        match hello.payload() {
+            FramePayload::KVList(kv_list) => {
+                assert_eq!(
+                    kv_list.get("capabilities"),
+                    Some(&TypedData::String("".to_string()))
+                );
+            }
+            _ => panic!("Wrong type of payload"),
+        }
+    }
+
+    #[test]
+    fn test_agent_hello_conversion() {
+        let hello = AgentHello {
+            version: Version::new(2, 0, 0),
+            max_frame_size: 16380,
+            capabilities: vec![FrameCapabilities::Pipelining, FrameCapabilities::Async],
+        };
+
+        let serialized = hello.serialize().unwrap();
+        let (_, frame) = crate::parser::parse_frame(&serialized).unwrap();
+        assert_eq!(frame.frame_type(), &FrameType::AgentHello);
+
+        let converted = AgentHello::try_from(frame.payload()).unwrap();
+        assert_eq!(converted.version, hello.version);
+        assert_eq!(converted.max_frame_size, hello.max_frame_size);
+        assert_eq!(converted.capabilities, hello.capabilities);
 
-        let version_str = format!("{}.{}", self.version.major, self.version.minor);
-        map.insert("version".to_string(), TypedData::String(version_str));
+        let mut kv_list = match hello.payload() {
+            FramePayload::KVList(kv_list) => kv_list,
+            _ => panic!("Wrong type of payload"),
+        };
 
-        map.insert(
-            "max-frame-size".to_string(),
-            TypedData::UInt32(self.max_frame_size),
+        kv_list.insert("version".to_string(), TypedData::String("two".to_string()));
+        assert_eq!(
+            AgentHello::try_from(FramePayload::KVList(kv_list.clone())).unwrap_err(),
+            SpopError::InvalidVersion("two".to_string())
         );
 
-        let caps_string = self
-            .capabilities
-            .iter()
-            .map(|c| c.to_string())
-            .collect::<Vec<_>>()
-            .join(",");
-        map.insert("capabilities".into(), TypedData::String(caps_string));
+        kv_list.insert("version".to_string(), TypedData::UInt32(2));
+        assert_eq!(
+            AgentHello::try_from(FramePayload::KVList(kv_list.clone())).unwrap_err(),
+            SpopError::WrongFieldType {
+                field: "version",
+                expected: "STRING",
+                found: "UINT32"
+            }
+        );
+
+        kv_list.remove("version");
+        assert_eq!(
+            AgentHello::try_from(FramePayload::KVList(kv_list)).unwrap_err(),
+            SpopError::MissingField("version")
+        );
 
-        FramePayload::KVList(map)
+        assert_eq!(
+            AgentHello::try_from(FramePayload::ListOfActions(vec![])).unwrap_err(),
+            SpopError::UnexpectedPayload { expected: "KVList" }
+        );
     }
 }
@@ -1,4 +1,5 @@
-use std::{fmt, str::FromStr};
+use alloc::{string::String, string::ToString, vec::Vec};
+use core::{convert::Infallible, fmt, str::FromStr};
 
 /// Frame capabilities
 ///
@@ -20,9 +21,63 @@ use std::{fmt, str::FromStr};
 ///
 /// NOTE: Fragmentation and async capabilities were deprecated and are now ignored.
 /// ```
+///
+/// `Async` is deprecated but still advertised by older HAProxy versions and agents, it is kept so
+/// the capabilities announced by a peer are not lost.
+///
+/// `Unknown` holds a capability token this crate does not implement. It is never advertised by
+/// an agent, see [`crate::frames::AgentHello`].
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum FrameCapabilities {
     Pipelining,
+    Async,
+    Unknown(String),
+}
+
+impl FrameCapabilities {
+    /// Returns true if the capability is implemented by this crate
+    pub const fn is_known(&self) -> bool {
+        !matches!(self, Self::Unknown(_))
+    }
+
+    /// Returns the capability token, as written in HELLO frames
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::Pipelining => "pipelining",
+            Self::Async => "async",
+            // Add more capabilities here when needed
+            Self::Unknown(s) => s,
+        }
+    }
+}
+
+/// Displays the comma-separated "capabilities" item of a HELLO frame
+///
+/// The tokens are written one by one, straight into the serialized frame, so the joined string
+/// is never built.
+pub(crate) struct JoinedCapabilities<'a> {
+    pub(crate) capabilities: &'a [FrameCapabilities],
+    /// Skips the `FrameCapabilities::Unknown` entries
+    pub(crate) known_only: bool,
+}
+
+impl fmt::Display for JoinedCapabilities<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let capabilities = self
+            .capabilities
+            .iter()
+            .filter(|c| !self.known_only || c.is_known());
+
+        for (i, capability) in capabilities.enumerate() {
+            if i > 0 {
+                f.write_str(",")?;
+            }
+            f.write_str(capability.as_str())?;
+        }
+
+        Ok(())
+    }
 }
 
 impl FromStr for FrameCapabilities {
@@ -31,19 +86,161 @@ impl FromStr for FrameCapabilities {
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s.to_lowercase().as_str() {
             "pipelining" => Ok(Self::Pipelining),
+            "async" => Ok(Self::Async),
             // Add more capabilities as needed
-            _ => Err(format!("Unknown capability: {}", s)),
+            "" => Err("Empty capability".to_string()),
+            // kept as announced, for diagnostics
+            _ => Ok(Self::Unknown(s.to_string())),
         }
     }
 }
 
 impl fmt::Display for FrameCapabilities {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let s = match self {
-            Self::Pipelining => "pipelining",
-            // Add more capabilities here when needed
-        };
-        write!(f, "{}", s)
+        f.write_str(self.as_str())
+    }
+}
+
+/// Parses the comma-separated capabilities, ignoring spaces and empty tokens
+///
+/// Unknown tokens are kept as [`FrameCapabilities::Unknown`].
+pub(crate) fn parse_capabilities(capabilities: &str) -> Vec<FrameCapabilities> {
+    // HAProxy sends an empty string when it has no capability to announce
+    if capabilities.trim().is_empty() {
+        return Vec::new();
+    }
+
+    capabilities
+        .split(',')
+        .map(|s| s.trim())
+        .filter_map(|s| FrameCapabilities::from_str(s).ok())
+        .collect()
+}
+
+/// Set of capabilities announced by a peer
+///
+/// Capabilities are kept in the order they were first inserted, duplicates are dropped. The
+/// [`Display`](fmt::Display) and [`FromStr`] implementations use the comma-separated form of the
+/// "capabilities" item of HELLO frames:
+///
+/// ```
+/// use spop::frames::{Capabilities, FrameCapabilities};
+///
+/// let haproxy: Capabilities = "pipelining,async".parse().unwrap();
+/// let agent = Capabilities::from_iter([FrameCapabilities::Pipelining]);
+///
+/// let common = haproxy.intersect(&agent);
+/// assert!(common.contains(&FrameCapabilities::Pipelining));
+/// assert_eq!(common.to_string(), "pipelining");
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Capabilities(Vec<FrameCapabilities>);
+
+impl Capabilities {
+    /// Creates an empty set
+    pub const fn new() -> Self {
+        Self(Vec::new())
+    }
+
+    /// Adds a capability, returns false if it was already in the set
+    pub fn insert(&mut self, capability: FrameCapabilities) -> bool {
+        if self.contains(&capability) {
+            return false;
+        }
+
+        self.0.push(capability);
+        true
+    }
+
+    /// Returns true if the capability is in the set
+    pub fn contains(&self, capability: &FrameCapabilities) -> bool {
+        self.0.contains(capability)
+    }
+
+    /// Returns the capabilities of this set also in `other`, in the order of this set
+    pub fn intersect(&self, other: &Capabilities) -> Capabilities {
+        Self(
+            self.0
+                .iter()
+                .filter(|c| other.contains(c))
+                .cloned()
+                .collect(),
+        )
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn iter(&self) -> core::slice::Iter<'_, FrameCapabilities> {
+        self.0.iter()
+    }
+
+    /// Returns the capabilities as a slice, e.g. to pass them to [`crate::AgentConfig`]
+    pub fn as_slice(&self) -> &[FrameCapabilities] {
+        &self.0
+    }
+}
+
+impl FromIterator<FrameCapabilities> for Capabilities {
+    fn from_iter<I: IntoIterator<Item = FrameCapabilities>>(iter: I) -> Self {
+        let mut capabilities = Self::new();
+        for capability in iter {
+            capabilities.insert(capability);
+        }
+        capabilities
+    }
+}
+
+impl<'a> IntoIterator for &'a Capabilities {
+    type Item = &'a FrameCapabilities;
+    type IntoIter = core::slice::Iter<'a, FrameCapabilities>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.iter()
+    }
+}
+
+/// Builds the set from the `capabilities` field of a HELLO frame
+impl From<Vec<FrameCapabilities>> for Capabilities {
+    fn from(capabilities: Vec<FrameCapabilities>) -> Self {
+        capabilities.into_iter().collect()
+    }
+}
+
+impl From<&[FrameCapabilities]> for Capabilities {
+    fn from(capabilities: &[FrameCapabilities]) -> Self {
+        capabilities.iter().cloned().collect()
+    }
+}
+
+/// Returns the capabilities in the form of the `capabilities` field of a HELLO frame
+impl From<Capabilities> for Vec<FrameCapabilities> {
+    fn from(capabilities: Capabilities) -> Self {
+        capabilities.0
+    }
+}
+
+/// Parses the comma-separated "capabilities" item, unknown tokens are kept
+impl FromStr for Capabilities {
+    type Err = Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(parse_capabilities(s).into())
+    }
+}
+
+impl fmt::Display for Capabilities {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        JoinedCapabilities {
+            capabilities: &self.0,
+            known_only: false,
+        }
+        .fmt(f)
     }
 }
 
@@ -58,6 +255,79 @@ mod tests {
             FrameCapabilities::Pipelining
         );
         assert_eq!(FrameCapabilities::Pipelining.to_string(), "pipelining");
-        assert!(FrameCapabilities::from_str("unknown").is_err());
+        assert_eq!(
+            FrameCapabilities::from_str("async").unwrap(),
+            FrameCapabilities::Async
+        );
+        assert_eq!(FrameCapabilities::Async.to_string(), "async");
+        assert!(FrameCapabilities::from_str("").is_err());
+        assert_eq!(
+            FrameCapabilities::from_str("Frobnicate").unwrap(),
+            FrameCapabilities::Unknown("Frobnicate".to_string())
+        );
+
+        let unknown = FrameCapabilities::Unknown("frobnicate".to_string());
+        assert_eq!(unknown.to_string(), "frobnicate");
+        assert!(!unknown.is_known());
+        assert!(FrameCapabilities::Pipelining.is_known());
+    }
+
+    #[test]
+    fn test_join_capabilities() {
+        let capabilities = [
+            FrameCapabilities::Pipelining,
+            FrameCapabilities::Unknown("frobnicate".to_string()),
+        ];
+
+        let joined = |capabilities, known_only| {
+            JoinedCapabilities {
+                capabilities,
+                known_only,
+            }
+            .to_string()
+        };
+
+        assert_eq!(joined(&capabilities, false), "pipelining,frobnicate");
+        assert_eq!(joined(&capabilities, true), "pipelining");
+        assert_eq!(joined(&capabilities[1..], true), "");
+        assert_eq!(joined(&capabilities[..1], false), "pipelining");
+        assert_eq!(joined(&[], false), "");
+    }
+
+    #[test]
+    fn test_capabilities_intersect() {
+        let haproxy = Capabilities::from(vec![
+            FrameCapabilities::Pipelining,
+            FrameCapabilities::Async,
+        ]);
+        let agent = Capabilities::from(vec![FrameCapabilities::Pipelining]);
+
+        let common = haproxy.intersect(&agent);
+        assert_eq!(
+            common,
+            Capabilities::from(vec![FrameCapabilities::Pipelining])
+        );
+        assert_eq!(agent.intersect(&haproxy), common);
+        assert!(common.contains(&FrameCapabilities::Pipelining));
+        assert!(!common.contains(&FrameCapabilities::Async));
+        assert!(haproxy.intersect(&Capabilities::new()).is_empty());
+    }
+
+    #[test]
+    fn test_capabilities_wire_form() {
+        let capabilities: Capabilities =
+            " pipelining, async,,pipelining,frobnicate".parse().unwrap();
+        assert_eq!(capabilities.len(), 3);
+        assert_eq!(capabilities.to_string(), "pipelining,async,frobnicate");
+        assert!(capabilities.contains(&FrameCapabilities::Unknown("frobnicate".to_string())));
+
+        let empty: Capabilities = "".parse().unwrap();
+        assert!(empty.is_empty());
+        assert_eq!(empty.to_string(), "");
+
+        let mut capabilities = Capabilities::new();
+        assert!(capabilities.insert(FrameCapabilities::Async));
+        assert!(!capabilities.insert(FrameCapabilities::Async));
+        assert_eq!(Vec::from(capabilities), vec![FrameCapabilities::Async]);
     }
 }
@@ -0,0 +1,97 @@
+use alloc::{string::String, string::ToString};
+use core::{fmt, str::FromStr};
+
+/// Identifier of an SPOE engine, the "engine-id" item of HAPROXY-HELLO frames
+///
+/// All the connections opened by one HAProxy process for a given SPOE engine announce the same
+/// engine-id, so an agent can share state between them. HAProxy generates it as a random UUID,
+/// but any non-empty string is accepted.
+///
+/// ```
+/// use spop::frames::EngineId;
+///
+/// let engine_id: EngineId = "2f58d3c8-8a1c-4e51-9a4b-63e4f4c6d9a7".parse().unwrap();
+/// assert_eq!(engine_id.to_string(), "2f58d3c8-8a1c-4e51-9a4b-63e4f4c6d9a7");
+/// assert!("".parse::<EngineId>().is_err());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EngineId(String);
+
+impl EngineId {
+    /// Generates a random engine-id, formatted as a version 4 UUID like the HAProxy ones
+    #[cfg(feature = "rand")]
+    pub fn generate() -> Self {
+        use core::fmt::Write;
+
+        let mut bytes: [u8; 16] = rand::random();
+        // version 4, variant RFC 4122
+        bytes[6] = (bytes[6] & 0x0f) | 0x40;
+        bytes[8] = (bytes[8] & 0x3f) | 0x80;
+
+        let mut id = String::with_capacity(36);
+        for (i, byte) in bytes.iter().enumerate() {
+            if matches!(i, 4 | 6 | 8 | 10) {
+                id.push('-');
+            }
+            let _ = write!(id, "{:02x}", byte);
+        }
+
+        Self(id)
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl FromStr for EngineId {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.is_empty() {
+            return Err("Empty engine-id".to_string());
+        }
+
+        Ok(Self(s.to_string()))
+    }
+}
+
+impl fmt::Display for EngineId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(feature = "rand")]
+    fn test_engine_id_generate() {
+        use std::collections::HashSet;
+
+        let ids: HashSet<_> = (0..100).map(|_| EngineId::generate()).collect();
+        assert_eq!(ids.len(), 100);
+
+        for id in &ids {
+            let s = id.to_string();
+            assert_eq!(s.len(), 36);
+            assert_eq!(s.as_bytes()[14], b'4');
+            assert_eq!(
+                s.split('-').map(str::len).collect::<Vec<_>>(),
+                [8, 4, 4, 4, 12]
+            );
+            assert_eq!(&s.parse::<EngineId>().unwrap(), id);
+        }
+    }
+
+    #[test]
+    fn test_engine_id_from_str() {
+        let id = EngineId::from_str("engine-1").unwrap();
+        assert_eq!(id.as_str(), "engine-1");
+        assert_eq!(id.to_string(), "engine-1");
+        assert!(EngineId::from_str("").is_err());
+    }
+}
@@ -1,9 +1,16 @@
+use crate::HashMap;
 use crate::{
-    SpopFrame,
-    frame::{FramePayload, FrameType, Metadata},
+    FrameVisitor, SpopError, SpopFrame,
+    frame::{FrameFlags, FramePayload, FrameType, Metadata},
+    frames::{
+        DisconnectStatus, KvItem, KvValue, kv_items_len, kv_items_to_map, mandatory,
+        write_kv_items, wrong_type,
+    },
     types::TypedData,
 };
-use std::{collections::HashMap, convert::TryFrom};
+use alloc::{string::String, string::ToString};
+use bytes::BufMut;
+use core::convert::TryFrom;
 
 /// Frame HAPROXY-DISCONNECT
 ///
@@ -31,31 +38,57 @@ use std::{collections::HashMap, convert::TryFrom};
 ///
 ///     This is a textual message describing the error.
 /// ```
-#[derive(Debug)]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct HaproxyDisconnect {
     pub status_code: u32,
     pub message: String,
 }
 
 impl HaproxyDisconnect {
-    pub fn to_kv_list(&self) -> HashMap<String, TypedData> {
-        let mut map = HashMap::new();
+    /// Creates a HAPROXY-DISCONNECT payload
+    pub fn new(status: DisconnectStatus, message: &str) -> Self {
+        Self {
+            status_code: status.to_u32(),
+            message: message.to_string(),
+        }
+    }
 
-        map.insert(
-            "status-code".to_string(),
-            TypedData::UInt32(self.status_code),
-        );
+    /// Wraps the payload in a frame with the FIN flag set, ready to be serialized
+    ///
+    /// The spec requires STREAM-ID and FRAME-ID to be 0, other values are only useful to
+    /// simulate a misbehaving peer.
+    pub const fn into_frame(self, stream_id: u64, frame_id: u64) -> HaproxyDisconnectFrame {
+        HaproxyDisconnectFrame {
+            metadata: Metadata {
+                flags: FrameFlags::new(true, false),
+                stream_id,
+                frame_id,
+            },
+            payload: self,
+        }
+    }
 
-        map.insert(
-            "message".to_string(),
-            TypedData::String(self.message.clone()),
-        );
+    /// Returns the status code as one of the documented SPOE errors
+    pub const fn status(&self) -> DisconnectStatus {
+        DisconnectStatus::from_u32(self.status_code)
+    }
 
-        map
+    pub fn to_kv_list(&self) -> HashMap<String, TypedData> {
+        self.with_kv_items(kv_items_to_map)
+    }
+
+    /// Calls `f` with the KV-LIST items of the frame
+    fn with_kv_items<R>(&self, f: impl FnOnce(&[KvItem<'_>]) -> R) -> R {
+        f(&[
+            Some(("status-code", KvValue::UInt32(self.status_code))),
+            Some(("message", KvValue::String(&self.message))),
+        ])
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct HaproxyDisconnectFrame {
     pub metadata: Metadata,
     pub payload: HaproxyDisconnect,
@@ -73,36 +106,78 @@ impl SpopFrame for HaproxyDisconnectFrame {
     fn payload(&self) -> FramePayload {
         FramePayload::KVList(self.payload.to_kv_list())
     }
+
+    fn payload_len(&self) -> usize {
+        self.payload.with_kv_items(kv_items_len)
+    }
+
+    fn write_payload(&self, buf: &mut dyn BufMut, order: Option<&[&str]>) -> Result<(), SpopError> {
+        self.payload
+            .with_kv_items(|items| write_kv_items(items, buf, order));
+        Ok(())
+    }
+
+    fn accept(&self, visitor: &mut dyn FrameVisitor) {
+        visitor.visit_haproxy_disconnect(self);
+    }
 }
 
 impl TryFrom<FramePayload> for HaproxyDisconnect {
-    type Error = String;
+    type Error = SpopError;
 
     fn try_from(payload: FramePayload) -> Result<Self, Self::Error> {
         // Ensure that the payload is a KVList
         if let FramePayload::KVList(kv_list) = payload {
-            let status_code = kv_list
-                .get("status-code")
-                .and_then(|v| match v {
-                    TypedData::UInt32(val) => Some(*val),
-                    _ => None,
-                })
-                .ok_or_else(|| "Missing or invalid status_code".to_string())?;
-
-            let message = kv_list
-                .get("message")
-                .and_then(|v| match v {
-                    TypedData::String(val) => Some(val.clone()),
-                    _ => None,
-                })
-                .ok_or_else(|| "Missing message".to_string())?;
+            let status_code = match mandatory(&kv_list, "status-code")? {
+                TypedData::UInt32(val) => *val,
+                other => return Err(wrong_type("status-code", "UINT32", other)),
+            };
+
+            let message = match mandatory(&kv_list, "message")? {
+                TypedData::String(val) => val.clone(),
+                other => return Err(wrong_type("message", "STRING", other)),
+            };
 
             Ok(Self {
                 status_code,
                 message,
             })
         } else {
-            Err("Invalid FramePayload type, expected KVList.".to_string())
+            Err(SpopError::UnexpectedPayload { expected: "KVList" })
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_haproxy_disconnect_status() {
+        let payload = FramePayload::KVList(HashMap::from([
+            ("status-code".to_string(), TypedData::UInt32(2)),
+            (
+                "message".to_string(),
+                TypedData::String("a timeout occurred".to_string()),
+            ),
+        ]));
+
+        let disconnect = HaproxyDisconnect::try_from(payload).unwrap();
+        assert_eq!(disconnect.status(), DisconnectStatus::Timeout);
+        assert_eq!(disconnect.status().to_string(), disconnect.message);
+    }
+
+    #[test]
+    fn test_haproxy_disconnect_into_frame() {
+        let disconnect = HaproxyDisconnect::new(DisconnectStatus::Timeout, "a timeout occurred");
+
+        let serialized = disconnect.into_frame(0, 0).serialize().unwrap();
+        let (rest, frame) = crate::parser::parse_frame(&serialized).unwrap();
+        assert!(rest.is_empty());
+        assert_eq!(frame.frame_type(), &FrameType::HaproxyDisconnect);
+
+        let parsed = HaproxyDisconnect::try_from(frame.payload()).unwrap();
+        assert_eq!(parsed.status(), DisconnectStatus::Timeout);
+        assert_eq!(parsed.message, "a timeout occurred");
+    }
+}
@@ -1,11 +1,18 @@
+use crate::HashMap;
 use crate::{
-    SpopFrame,
-    frame::{FramePayload, FrameType, Metadata},
-    frames::capabilities::FrameCapabilities,
+    FrameVisitor, SpopError, SpopFrame,
+    frame::{FrameFlags, FramePayload, FrameType, Metadata},
+    frames::{
+        AgentHello, KvItem, KvValue,
+        capabilities::{Capabilities, FrameCapabilities, JoinedCapabilities, parse_capabilities},
+        kv_items_len, kv_items_to_map, mandatory, write_kv_items, wrong_type,
+    },
     types::TypedData,
 };
+use alloc::{format, string::String, string::ToString, vec::Vec};
+use bytes::BufMut;
+use core::{convert::TryFrom, fmt};
 use semver::Version;
-use std::{collections::HashMap, convert::TryFrom, str::FromStr};
 
 /// Frame HAPROXY-HELLO
 ///
@@ -57,7 +64,8 @@ use std::{collections::HashMap, convert::TryFrom, str::FromStr};
 /// occurs or if an incompatibility is detected with the agent configuration, an
 /// AGENT-DISCONNECT frame must be returned.
 /// ```
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct HaproxyHello {
     pub supported_versions: Vec<Version>,
     pub max_frame_size: u32,
@@ -67,50 +75,128 @@ pub struct HaproxyHello {
 }
 
 impl HaproxyHello {
+    /// Creates a HAPROXY-HELLO payload, without the optional "healthcheck" and "engine-id" items
+    pub const fn new(
+        supported_versions: Vec<Version>,
+        max_frame_size: u32,
+        capabilities: Vec<FrameCapabilities>,
+    ) -> Self {
+        Self {
+            supported_versions,
+            max_frame_size,
+            capabilities,
+            healthcheck: None,
+            engine_id: None,
+        }
+    }
+
+    /// Wraps the payload in a frame with the FIN flag set, ready to be serialized
+    ///
+    /// The spec requires STREAM-ID and FRAME-ID to be 0, other values are only useful to
+    /// simulate a misbehaving peer.
+    pub const fn into_frame(self, stream_id: u64, frame_id: u64) -> HaproxyHelloFrame {
+        HaproxyHelloFrame {
+            metadata: Metadata {
+                flags: FrameFlags::new(true, false),
+                stream_id,
+                frame_id,
+            },
+            payload: self,
+        }
+    }
+
     pub fn to_kv_list(&self) -> HashMap<String, TypedData> {
-        let mut map = HashMap::new();
+        self.with_kv_items(kv_items_to_map)
+    }
+
+    /// Calls `f` with the KV-LIST items of the frame
+    fn with_kv_items<R>(&self, f: impl FnOnce(&[KvItem<'_>]) -> R) -> R {
+        let supported_versions = SupportedVersions(&self.supported_versions);
+        let capabilities = JoinedCapabilities {
+            capabilities: &self.capabilities,
+            known_only: false,
+        };
 
-        let version_str = self
-            .supported_versions
+        f(&[
+            Some(("supported-versions", KvValue::String(&supported_versions))),
+            Some(("max-frame-size", KvValue::UInt32(self.max_frame_size))),
+            Some(("capabilities", KvValue::String(&capabilities))),
+            self.healthcheck
+                .map(|healthcheck| ("healthcheck", KvValue::Bool(healthcheck))),
+            self.engine_id
+                .as_ref()
+                .map(|engine_id| ("engine-id", KvValue::String(engine_id))),
+        ])
+    }
+
+    /// Builds the AGENT-HELLO reply from the versions, max-frame-size and capabilities of the
+    /// agent
+    ///
+    /// The version is the highest one both peers support: for each agent version, HAProxy must
+    /// announce the same major version, and the reply never goes above the highest one announced
+    /// for it. The max-frame-size is the lower of both values and the capabilities are the ones
+    /// announced by both peers.
+    ///
+    /// Fails with [`SpopError::NoCommonVersion`] when there is no common major version, the agent
+    /// should then send an AGENT-DISCONNECT frame.
+    pub fn negotiate(
+        &self,
+        agent_versions: &[Version],
+        agent_max_frame: u32,
+        agent_caps: &[FrameCapabilities],
+    ) -> Result<AgentHello, SpopError> {
+        let version = agent_versions
             .iter()
-            .map(|v| format!("{}.{}", v.major, v.minor)) // skip patch
-            .collect::<Vec<_>>()
-            .join(", ");
+            .filter_map(|agent| {
+                self.supported_versions
+                    .iter()
+                    .filter(|v| v.major == agent.major)
+                    .max()
+                    .map(|haproxy| agent.min(haproxy))
+            })
+            .max()
+            .cloned()
+            .ok_or_else(|| SpopError::NoCommonVersion(self.supported_versions.clone()))?;
 
-        map.insert(
-            "supported-versions".to_string(),
-            TypedData::String(version_str),
-        );
+        let capabilities = Capabilities::from(agent_caps)
+            .intersect(&Capabilities::from(&self.capabilities[..]))
+            .into();
 
-        map.insert(
-            "max-frame-size".to_string(),
-            TypedData::UInt32(self.max_frame_size),
-        );
+        Ok(AgentHello {
+            version,
+            max_frame_size: agent_max_frame.min(self.max_frame_size),
+            capabilities,
+        })
+    }
+}
 
-        let caps_string = self
-            .capabilities
-            .iter()
-            .map(|c| c.to_string())
-            .collect::<Vec<_>>()
-            .join(",");
-        map.insert("capabilities".into(), TypedData::String(caps_string));
+/// Displays a version as "Major.Minor", the patch is not part of SPOP versions
+pub(crate) struct MajorMinor<'a>(pub(crate) &'a Version);
 
-        if let Some(healthcheck) = self.healthcheck {
-            map.insert("healthcheck".to_string(), TypedData::Bool(healthcheck));
-        }
+impl fmt::Display for MajorMinor<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.0.major, self.0.minor)
+    }
+}
 
-        if let Some(ref engine_id) = self.engine_id {
-            map.insert(
-                "engine-id".to_string(),
-                TypedData::String(engine_id.clone()),
-            );
+/// Displays the comma-separated "supported-versions" item, e.g. "2.0, 1.5"
+struct SupportedVersions<'a>(&'a [Version]);
+
+impl fmt::Display for SupportedVersions<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, version) in self.0.iter().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            MajorMinor(version).fmt(f)?;
         }
 
-        map
+        Ok(())
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct HaproxyHelloFrame {
     pub metadata: Metadata,
     pub payload: HaproxyHello,
@@ -128,89 +214,108 @@ impl SpopFrame for HaproxyHelloFrame {
     fn payload(&self) -> FramePayload {
         FramePayload::KVList(self.payload.to_kv_list())
     }
+
+    fn payload_len(&self) -> usize {
+        self.payload.with_kv_items(kv_items_len)
+    }
+
+    fn write_payload(&self, buf: &mut dyn BufMut, order: Option<&[&str]>) -> Result<(), SpopError> {
+        self.payload
+            .with_kv_items(|items| write_kv_items(items, buf, order));
+        Ok(())
+    }
+
+    fn accept(&self, visitor: &mut dyn FrameVisitor) {
+        visitor.visit_haproxy_hello(self);
+    }
 }
 
 impl TryFrom<FramePayload> for HaproxyHello {
-    type Error = String;
+    type Error = SpopError;
 
     fn try_from(payload: FramePayload) -> Result<Self, Self::Error> {
         // Ensure that the payload is a KVList
         if let FramePayload::KVList(kv_list) = payload {
-            let supported_versions = kv_list
-                .get("supported-versions")
-                .and_then(|v| match v {
-                    TypedData::String(v) => Some(
-                        v.split(',')
-                            .map(|s| {
-                                let trimmed = s.trim();
-                                let padded = if trimmed.matches('.').count() == 1 {
-                                    format!("{}.0", trimmed)
-                                } else {
-                                    trimmed.to_string()
-                                };
-                                Version::parse(&padded)
-                                    .map_err(|e| format!("Invalid version '{}': {}", trimmed, e))
-                            })
-                            .collect::<Result<Vec<_>, _>>(),
-                    ),
-                    _ => None,
-                })
-                .ok_or_else(|| "Missing or invalid supported_versions".to_string())?;
+            let supported_versions = match mandatory(&kv_list, "supported-versions")? {
+                TypedData::String(v) => parse_supported_versions(v)?,
+                other => return Err(wrong_type("supported-versions", "STRING", other)),
+            };
 
-            let max_frame_size = kv_list
-                .get("max-frame-size")
-                .and_then(|v| match v {
-                    TypedData::UInt32(val) => Some(*val),
-                    _ => None,
-                })
-                .ok_or_else(|| "Missing or invalid max_frame_size".to_string())?;
-
-            let capabilities = kv_list
-                .get("capabilities")
-                .and_then(|v| match v {
-                    TypedData::String(v) => Some(
-                        v.split(',')
-                            .map(|s| s.trim())
-                            .filter_map(|s| FrameCapabilities::from_str(s).ok())
-                            .collect::<Vec<FrameCapabilities>>(),
-                    ),
-                    _ => None,
-                })
-                .ok_or_else(|| "Missing or invalid capabilities".to_string())?;
-
-            let healthcheck = kv_list.get("healthcheck").and_then(|v| {
-                if let TypedData::Bool(val) = v {
-                    Some(*val)
-                } else {
-                    None
-                }
-            });
-
-            let engine_id = kv_list.get("engine-id").and_then(|v| {
-                if let TypedData::String(val) = v {
-                    Some(val.clone())
-                } else {
-                    None
-                }
-            });
+            let max_frame_size = match mandatory(&kv_list, "max-frame-size")? {
+                TypedData::UInt32(val) => *val,
+                other => return Err(wrong_type("max-frame-size", "UINT32", other)),
+            };
+
+            let capabilities = parse_capabilities_item(mandatory(&kv_list, "capabilities")?)?;
+
+            let healthcheck = kv_list.get("healthcheck").and_then(TypedData::as_bool);
+
+            let engine_id = kv_list
+                .get("engine-id")
+                .and_then(TypedData::as_str)
+                .map(str::to_string);
 
             Ok(Self {
-                supported_versions: supported_versions?,
+                supported_versions,
                 max_frame_size,
                 capabilities,
                 healthcheck,
                 engine_id,
             })
         } else {
-            Err("Invalid FramePayload type, expected KVList.".to_string())
+            Err(SpopError::UnexpectedPayload { expected: "KVList" })
         }
     }
 }
 
+/// Parses the value of a "capabilities" item, shared by the HELLO frames of both peers
+pub(crate) fn parse_capabilities_item(
+    value: &TypedData,
+) -> Result<Vec<FrameCapabilities>, SpopError> {
+    match value {
+        TypedData::String(v) => Ok(parse_capabilities(v)),
+        // not conformant, but accepted as long as the bytes are valid UTF-8
+        TypedData::Binary(v) => core::str::from_utf8(v)
+            .map(parse_capabilities)
+            .map_err(|_| wrong_type("capabilities", "STRING", value)),
+        // a null capabilities field means "none"
+        TypedData::Null => Ok(Vec::new()),
+        other => Err(wrong_type("capabilities", "STRING", other)),
+    }
+}
+
+/// Parses the comma-separated "Major.Minor" list of the supported-versions item
+fn parse_supported_versions(versions: &str) -> Result<Vec<Version>, SpopError> {
+    let versions = versions
+        .split(',')
+        .map(|s| s.trim())
+        .filter(|s| !s.is_empty())
+        .map(parse_version)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    if versions.is_empty() {
+        return Err(SpopError::NoSupportedVersions);
+    }
+
+    Ok(versions)
+}
+
+/// Parses a "Major.Minor" version, a full semver version is accepted too
+pub(crate) fn parse_version(version: &str) -> Result<Version, SpopError> {
+    let padded = if version.matches('.').count() == 1 {
+        format!("{}.0", version)
+    } else {
+        version.to_string()
+    };
+
+    Version::parse(&padded).map_err(|_| SpopError::InvalidVersion(version.to_string()))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use semver::Version;
+    use std::str::FromStr;
 
     #[test]
     fn test_haproxy_hello_frame() {
@@ -278,4 +383,346 @@ mod tests {
         assert_eq!(haproxy_hello.healthcheck, Some(true));
         assert_eq!(haproxy_hello.engine_id, Some("engine-123".to_string()));
     }
+
+    #[test]
+    fn test_haproxy_hello_unknown_capabilities() {
+        let kv_list = HashMap::from([
+            (
+                "supported-versions".to_string(),
+                TypedData::String("2.0".to_string()),
+            ),
+            ("max-frame-size".to_string(), TypedData::UInt32(16380)),
+            (
+                "capabilities".to_string(),
+                TypedData::String("pipelining,frobnicate".to_string()),
+            ),
+        ]);
+
+        let hello = HaproxyHello::try_from(FramePayload::KVList(kv_list)).unwrap();
+        assert_eq!(
+            hello.capabilities,
+            vec![
+                FrameCapabilities::Pipelining,
+                FrameCapabilities::Unknown("frobnicate".to_string())
+            ]
+        );
+
+        // unknown tokens are serialized back as announced
+        assert_eq!(
+            hello.to_kv_list()["capabilities"],
+            TypedData::String("pipelining,frobnicate".to_string())
+        );
+    }
+
+    #[test]
+    fn test_haproxy_hello_async_capability() {
+        let kv_list = HashMap::from([
+            (
+                "supported-versions".to_string(),
+                TypedData::String("2.0".to_string()),
+            ),
+            ("max-frame-size".to_string(), TypedData::UInt32(16380)),
+            (
+                "capabilities".to_string(),
+                TypedData::String("pipelining, async".to_string()),
+            ),
+        ]);
+
+        let hello = HaproxyHello::try_from(FramePayload::KVList(kv_list)).unwrap();
+        assert_eq!(
+            hello.capabilities,
+            vec![FrameCapabilities::Pipelining, FrameCapabilities::Async]
+        );
+    }
+
+    #[test]
+    fn test_haproxy_hello_binary_capabilities() {
+        let kv_list = |capabilities: &[u8]| {
+            HashMap::from([
+                (
+                    "supported-versions".to_string(),
+                    TypedData::String("2.0".to_string()),
+                ),
+                ("max-frame-size".to_string(), TypedData::UInt32(16380)),
+                (
+                    "capabilities".to_string(),
+                    TypedData::Binary(capabilities.to_vec()),
+                ),
+            ])
+        };
+
+        let hello =
+            HaproxyHello::try_from(FramePayload::KVList(kv_list(b"pipelining, async"))).unwrap();
+        assert_eq!(
+            hello.capabilities,
+            vec![FrameCapabilities::Pipelining, FrameCapabilities::Async]
+        );
+
+        let err =
+            HaproxyHello::try_from(FramePayload::KVList(kv_list(b"pipe\xfflining"))).unwrap_err();
+        assert_eq!(
+            err,
+            SpopError::WrongFieldType {
+                field: "capabilities",
+                expected: "STRING",
+                found: "BINARY",
+            }
+        );
+    }
+
+    #[test]
+    fn test_haproxy_hello_null_capabilities() {
+        let kv_list = HashMap::from([
+            (
+                "supported-versions".to_string(),
+                TypedData::String("2.0".to_string()),
+            ),
+            ("max-frame-size".to_string(), TypedData::UInt32(16380)),
+            ("capabilities".to_string(), TypedData::Null),
+        ]);
+
+        let haproxy_hello = HaproxyHello::try_from(FramePayload::KVList(kv_list)).unwrap();
+        assert!(haproxy_hello.capabilities.is_empty());
+
+        // a wrong type is still rejected
+        let kv_list = HashMap::from([
+            (
+                "supported-versions".to_string(),
+                TypedData::String("2.0".to_string()),
+            ),
+            ("max-frame-size".to_string(), TypedData::UInt32(16380)),
+            ("capabilities".to_string(), TypedData::UInt32(1)),
+        ]);
+
+        assert!(HaproxyHello::try_from(FramePayload::KVList(kv_list)).is_err());
+    }
+
+    #[test]
+    fn test_haproxy_hello_empty_capabilities() {
+        let cases = [
+            ("", vec![]),
+            ("   ", vec![]),
+            (" , ", vec![]),
+            ("pipelining, ,", vec![FrameCapabilities::Pipelining]),
+        ];
+
+        for (capabilities, expected) in cases {
+            let kv_list = HashMap::from([
+                (
+                    "supported-versions".to_string(),
+                    TypedData::String("2.0".to_string()),
+                ),
+                ("max-frame-size".to_string(), TypedData::UInt32(16380)),
+                (
+                    "capabilities".to_string(),
+                    TypedData::String(capabilities.to_string()),
+                ),
+            ]);
+
+            let haproxy_hello = HaproxyHello::try_from(FramePayload::KVList(kv_list)).unwrap();
+            assert_eq!(haproxy_hello.capabilities, expected, "{:?}", capabilities);
+        }
+
+        // the minimal HAPROXY-HELLO has an empty capabilities string
+        let bytes = FrameType::HaproxyHello.minimal_valid_frame();
+        let (_, frame) = crate::parser::parse_frame(&bytes).unwrap();
+        let haproxy_hello = HaproxyHello::try_from(frame.payload()).unwrap();
+        assert!(haproxy_hello.capabilities.is_empty());
+    }
+
+    #[test]
+    fn test_haproxy_hello_empty_supported_versions() {
+        for versions in ["", "   ", " , "] {
+            let kv_list = HashMap::from([
+                (
+                    "supported-versions".to_string(),
+                    TypedData::String(versions.to_string()),
+                ),
+                ("max-frame-size".to_string(), TypedData::UInt32(16380)),
+                (
+                    "capabilities".to_string(),
+                    TypedData::String("".to_string()),
+                ),
+            ]);
+
+            assert_eq!(
+                HaproxyHello::try_from(FramePayload::KVList(kv_list)),
+                Err(SpopError::NoSupportedVersions),
+                "supported-versions: {:?}",
+                versions
+            );
+        }
+
+        let kv_list = HashMap::from([
+            (
+                "supported-versions".to_string(),
+                TypedData::String("two".to_string()),
+            ),
+            ("max-frame-size".to_string(), TypedData::UInt32(16380)),
+            (
+                "capabilities".to_string(),
+                TypedData::String("".to_string()),
+            ),
+        ]);
+
+        assert_eq!(
+            HaproxyHello::try_from(FramePayload::KVList(kv_list)),
+            Err(SpopError::InvalidVersion("two".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_haproxy_hello_missing_and_wrong_type() {
+        let valid = HashMap::from([
+            (
+                "supported-versions".to_string(),
+                TypedData::String("2.0".to_string()),
+            ),
+            ("max-frame-size".to_string(), TypedData::UInt32(16380)),
+            (
+                "capabilities".to_string(),
+                TypedData::String("".to_string()),
+            ),
+        ]);
+
+        for field in ["supported-versions", "max-frame-size", "capabilities"] {
+            let mut kv_list = valid.clone();
+            kv_list.remove(field);
+
+            assert_eq!(
+                HaproxyHello::try_from(FramePayload::KVList(kv_list)),
+                Err(SpopError::MissingField(field))
+            );
+        }
+
+        let cases = [
+            (
+                "supported-versions",
+                TypedData::UInt32(2),
+                "STRING",
+                "UINT32",
+            ),
+            (
+                "max-frame-size",
+                TypedData::String("16380".to_string()),
+                "UINT32",
+                "STRING",
+            ),
+            (
+                "max-frame-size",
+                TypedData::UInt64(16380),
+                "UINT32",
+                "UINT64",
+            ),
+            ("capabilities", TypedData::Bool(true), "STRING", "BOOLEAN"),
+        ];
+
+        for (field, value, expected, found) in cases {
+            let mut kv_list = valid.clone();
+            kv_list.insert(field.to_string(), value);
+
+            assert_eq!(
+                HaproxyHello::try_from(FramePayload::KVList(kv_list)),
+                Err(SpopError::WrongFieldType {
+                    field,
+                    expected,
+                    found
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn test_haproxy_hello_negotiate() {
+        let hello = HaproxyHello {
+            supported_versions: vec![Version::new(1, 0, 0), Version::new(2, 0, 0)],
+            max_frame_size: 16380,
+            capabilities: vec![FrameCapabilities::Pipelining, FrameCapabilities::Async],
+            healthcheck: None,
+            engine_id: None,
+        };
+
+        let agent_hello = hello
+            .negotiate(
+                &[Version::new(1, 0, 0), Version::new(2, 0, 0)],
+                65536,
+                &[FrameCapabilities::Pipelining],
+            )
+            .unwrap();
+        assert_eq!(agent_hello.version, Version::new(2, 0, 0));
+        assert_eq!(agent_hello.max_frame_size, 16380);
+        assert_eq!(
+            agent_hello.capabilities,
+            vec![FrameCapabilities::Pipelining]
+        );
+
+        // a newer minor version of the agent replies with the one of HAProxy
+        let agent_hello = hello
+            .negotiate(&[Version::new(2, 1, 0)], 4096, &[])
+            .unwrap();
+        assert_eq!(agent_hello.version, Version::new(2, 0, 0));
+        assert_eq!(agent_hello.max_frame_size, 4096);
+        assert!(agent_hello.capabilities.is_empty());
+
+        // an older agent keeps its own version
+        let agent_hello = hello
+            .negotiate(&[Version::new(1, 0, 0)], 4096, &[])
+            .unwrap();
+        assert_eq!(agent_hello.version, Version::new(1, 0, 0));
+
+        let err = hello
+            .negotiate(&[Version::new(3, 0, 0)], 4096, &[])
+            .unwrap_err();
+        assert_eq!(
+            err,
+            SpopError::NoCommonVersion(hello.supported_versions.clone())
+        );
+        assert!(hello.negotiate(&[], 4096, &[]).is_err());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_haproxy_hello_serde_json() {
+        let bytes = FrameType::HaproxyHello.minimal_valid_frame();
+        let (_, frame) = crate::parser::parse_frame(&bytes).unwrap();
+        let frame = HaproxyHelloFrame {
+            metadata: frame.metadata(),
+            payload: HaproxyHello::try_from(frame.payload()).unwrap(),
+        };
+
+        let json = serde_json::to_value(&frame).unwrap();
+        assert_eq!(json["payload"]["max_frame_size"], 256);
+        assert_eq!(json["payload"]["supported_versions"][0], "2.0.0");
+
+        let decoded: HaproxyHelloFrame = serde_json::from_value(json).unwrap();
+        assert_eq!(decoded.metadata, frame.metadata);
+        assert_eq!(decoded.payload, frame.payload);
+
+        // typed data keeps its type name
+        let value = serde_json::to_string(&frame.payload.to_kv_list()["max-frame-size"]).unwrap();
+        assert_eq!(value, r#"{"UInt32":256}"#);
+    }
+
+    #[test]
+    fn test_haproxy_hello_into_frame() {
+        let mut hello = HaproxyHello::new(
+            vec![Version::new(2, 0, 0)],
+            16380,
+            vec![FrameCapabilities::Pipelining],
+        );
+        hello.engine_id = Some("engine-1".to_string());
+
+        let serialized = hello.into_frame(0, 0).serialize().unwrap();
+        let (rest, frame) = crate::parser::parse_frame(&serialized).unwrap();
+        assert!(rest.is_empty());
+        assert_eq!(frame.frame_type(), &FrameType::HaproxyHello);
+        assert!(frame.metadata().flags.is_fin());
+
+        let parsed = HaproxyHello::try_from(frame.payload()).unwrap();
+        assert_eq!(parsed.supported_versions, vec![Version::new(2, 0, 0)]);
+        assert_eq!(parsed.max_frame_size, 16380);
+        assert_eq!(parsed.capabilities, vec![FrameCapabilities::Pipelining]);
+        assert_eq!(parsed.healthcheck, None);
+        assert_eq!(parsed.engine_id.as_deref(), Some("engine-1"));
+    }
 }
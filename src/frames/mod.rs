@@ -1,5 +1,15 @@
+use crate::HashMap;
+use crate::{
+    SpopError,
+    types::{TypedData, display_len, put_display},
+    varint::{put_varint, varint_len},
+};
+use alloc::{string::String, string::ToString, vec::Vec};
+use bytes::BufMut;
+use core::fmt;
+
 pub mod ack;
-pub use self::ack::Ack;
+pub use self::ack::{Ack, AuditEntry, RequestAck, ResponseAck};
 
 pub mod agent_disconnect;
 pub use self::agent_disconnect::AgentDisconnect;
@@ -8,7 +18,10 @@ pub mod agent_hello;
 pub use self::agent_hello::AgentHello;
 
 pub mod capabilities;
-pub use self::capabilities::FrameCapabilities;
+pub use self::capabilities::{Capabilities, FrameCapabilities};
+
+pub mod engine_id;
+pub use self::engine_id::EngineId;
 
 pub mod haproxy_disconnect;
 pub use self::haproxy_disconnect::HaproxyDisconnect;
@@ -17,3 +30,117 @@ pub mod haproxy_hello;
 pub use self::haproxy_hello::HaproxyHello;
 
 pub mod notify;
+
+pub mod status;
+pub use self::status::DisconnectStatus;
+
+/// Returns the value of a mandatory KV-LIST item
+pub(crate) fn mandatory<'a>(
+    kv_list: &'a HashMap<String, TypedData>,
+    field: &'static str,
+) -> Result<&'a TypedData, SpopError> {
+    kv_list.get(field).ok_or(SpopError::MissingField(field))
+}
+
+/// Builds the error of a KV-LIST item with an unexpected type
+pub(crate) fn wrong_type(
+    field: &'static str,
+    expected: &'static str,
+    found: &TypedData,
+) -> SpopError {
+    SpopError::WrongFieldType {
+        field,
+        expected,
+        found: found.type_name(),
+    }
+}
+
+/// A KV-LIST value borrowed from a frame
+///
+/// The HELLO and DISCONNECT frames describe their KV-LIST with these values, so it is measured and
+/// written from the fields of the frame, without building the map of `SpopFrame::payload`.
+pub(crate) enum KvValue<'a> {
+    UInt32(u32),
+    Bool(bool),
+    /// A STRING, written from its `Display` output
+    String(&'a dyn fmt::Display),
+}
+
+/// A KV-LIST item, `None` for an optional item which is not set
+pub(crate) type KvItem<'a> = Option<(&'static str, KvValue<'a>)>;
+
+impl KvValue<'_> {
+    fn encoded_len(&self) -> usize {
+        match self {
+            Self::UInt32(val) => TypedData::UInt32(*val).encoded_len(),
+            Self::Bool(val) => TypedData::Bool(*val).encoded_len(),
+            Self::String(val) => {
+                let len = display_len(*val);
+                1 + varint_len(len as u64) + len
+            }
+        }
+    }
+
+    fn to_bytes(&self, buf: &mut dyn BufMut) {
+        match self {
+            Self::UInt32(val) => TypedData::UInt32(*val).to_bytes(buf),
+            Self::Bool(val) => TypedData::Bool(*val).to_bytes(buf),
+            Self::String(val) => put_display(buf, *val),
+        }
+    }
+
+    fn to_typed_data(&self) -> TypedData {
+        match self {
+            Self::UInt32(val) => TypedData::UInt32(*val),
+            Self::Bool(val) => TypedData::Bool(*val),
+            Self::String(val) => TypedData::String(val.to_string()),
+        }
+    }
+}
+
+/// Returns the encoded length of the KV-LIST made of `items`
+pub(crate) fn kv_items_len(items: &[KvItem<'_>]) -> usize {
+    items
+        .iter()
+        .flatten()
+        .map(|(key, value)| varint_len(key.len() as u64) + key.len() + value.encoded_len())
+        .sum()
+}
+
+/// Builds the KV-LIST map made of `items`
+pub(crate) fn kv_items_to_map(items: &[KvItem<'_>]) -> HashMap<String, TypedData> {
+    items
+        .iter()
+        .flatten()
+        .map(|(key, value)| (key.to_string(), value.to_typed_data()))
+        .collect()
+}
+
+/// Writes the KV-LIST made of `items` at the end of `buf`
+///
+/// Without `order`, the items are written as listed. With it, the keys of `order` come first and
+/// the other ones follow in alphabetical order, as for any other KV-LIST.
+pub(crate) fn write_kv_items(items: &[KvItem<'_>], buf: &mut dyn BufMut, order: Option<&[&str]>) {
+    let mut write_item = |key: &str, value: &KvValue<'_>| {
+        put_varint(buf, key.len() as u64);
+        buf.put_slice(key.as_bytes());
+        value.to_bytes(buf);
+    };
+
+    let Some(order) = order else {
+        for (key, value) in items.iter().flatten() {
+            write_item(key, value);
+        }
+        return;
+    };
+
+    let mut items: Vec<_> = items.iter().flatten().collect();
+    items.sort_by_key(|(key, _)| match order.iter().position(|k| k == key) {
+        Some(position) => (position, ""),
+        None => (order.len(), *key),
+    });
+
+    for (key, value) in items {
+        write_item(key, value);
+    }
+}
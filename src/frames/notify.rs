@@ -1,7 +1,11 @@
+use crate::HashMap;
 use crate::{
-    SpopFrame,
-    frame::{FramePayload, FrameType, Message, Metadata},
+    FrameVisitor, SpopError, SpopFrame,
+    frame::{FrameFlags, FramePayload, FrameType, Message, Metadata},
+    types::TypedData,
 };
+use alloc::{string::ToString, vec::Vec};
+use bytes::BufMut;
 
 /// Frame Notify
 ///
@@ -18,12 +22,100 @@ use crate::{
 /// NOTIFY frames must be acknowledge by agents sending an ACK frame, repeating
 /// right STREAM-ID and FRAME-ID.
 /// ```
-#[derive(Debug)]
+#[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct NotifyFrame {
     pub metadata: Metadata,
     pub messages: Vec<Message>,
 }
 
+impl NotifyFrame {
+    /// Creates a new NOTIFY frame with no messages and the FIN flag set
+    ///
+    /// ```
+    /// use spop::{frames::notify::NotifyFrame, types::TypedData};
+    /// use std::net::Ipv4Addr;
+    ///
+    /// let notify = NotifyFrame::new(3, 7)
+    ///     .add_message("check-client-ip")
+    ///     .arg("ip", TypedData::IPv4(Ipv4Addr::LOCALHOST))
+    ///     .add_message("log-request")
+    ///     .arg("method", TypedData::String("GET".to_string()))
+    ///     .arg("path", TypedData::String("/".to_string()))
+    ///     .build();
+    /// assert_eq!(notify.messages.len(), 2);
+    /// ```
+    pub const fn new(stream_id: u64, frame_id: u64) -> Self {
+        Self {
+            metadata: Metadata::new(stream_id, frame_id),
+            messages: Vec::new(),
+        }
+    }
+
+    /// Creates a NOTIFY frame carrying the given messages, with the FIN flag set
+    ///
+    /// Useful when relaying messages, e.g. the ones of [`crate::frame::FramePayload::as_messages`].
+    pub const fn from_messages(stream_id: u64, frame_id: u64, messages: Vec<Message>) -> Self {
+        Self {
+            metadata: Metadata::new(stream_id, frame_id),
+            messages,
+        }
+    }
+
+    /// Creates a NOTIFY frame cancelling the processing of the given frame
+    ///
+    /// Both FIN and ABORT are set and the frame carries no message. Such frames are rejected by
+    /// [`crate::parser::parse_frame`], they are returned when
+    /// [`crate::parser::SpopParserConfig::surface_abort`] is set.
+    pub const fn abort(stream_id: u64, frame_id: u64) -> Self {
+        Self {
+            metadata: Metadata {
+                flags: FrameFlags::new(true, true),
+                stream_id,
+                frame_id,
+            },
+            messages: Vec::new(),
+        }
+    }
+
+    /// Starts a new message, its arguments are added on the returned builder
+    pub fn add_message(self, name: &str) -> MessageBuilder {
+        MessageBuilder {
+            notify: self,
+            message: Message {
+                name: name.to_string(),
+                args: HashMap::new(),
+            },
+        }
+    }
+}
+
+/// Builder of a message of a NOTIFY frame, see [`NotifyFrame::add_message`]
+#[derive(Debug)]
+pub struct MessageBuilder {
+    notify: NotifyFrame,
+    message: Message,
+}
+
+impl MessageBuilder {
+    /// Adds an argument to the current message
+    pub fn arg(mut self, name: &str, value: impl Into<TypedData>) -> Self {
+        self.message.args.insert(name.to_string(), value.into());
+        self
+    }
+
+    /// Ends the current message and starts a new one
+    pub fn add_message(self, name: &str) -> Self {
+        self.build().add_message(name)
+    }
+
+    /// Ends the current message and returns the NOTIFY frame
+    pub fn build(mut self) -> NotifyFrame {
+        self.notify.messages.push(self.message);
+        self.notify
+    }
+}
+
 impl SpopFrame for NotifyFrame {
     fn frame_type(&self) -> &FrameType {
         &FrameType::Notify
@@ -36,4 +128,177 @@ impl SpopFrame for NotifyFrame {
     fn payload(&self) -> FramePayload {
         FramePayload::ListOfMessages(self.messages.clone())
     }
+
+    fn payload_len(&self) -> usize {
+        crate::messages_len(&self.messages)
+    }
+
+    fn write_payload(&self, buf: &mut dyn BufMut, order: Option<&[&str]>) -> Result<(), SpopError> {
+        crate::encode_messages(&self.messages, buf, order)
+    }
+
+    fn accept(&self, visitor: &mut dyn FrameVisitor) {
+        visitor.visit_notify(self);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    #[test]
+    fn test_notify_nb_args_per_message() {
+        let notify = NotifyFrame {
+            metadata: Metadata {
+                flags: FrameFlags::new(true, false),
+                stream_id: 1,
+                frame_id: 2,
+            },
+            messages: vec![
+                Message {
+                    name: "check-client-ip".to_string(),
+                    args: HashMap::from([(
+                        "ip".to_string(),
+                        TypedData::IPv4(Ipv4Addr::new(10, 0, 0, 1)),
+                    )]),
+                },
+                Message {
+                    name: "log-request".to_string(),
+                    args: HashMap::from([
+                        (
+                            "ip".to_string(),
+                            TypedData::IPv4(Ipv4Addr::new(10, 0, 0, 1)),
+                        ),
+                        ("country".to_string(), TypedData::String("xx".to_string())),
+                        (
+                            "user_agent".to_string(),
+                            TypedData::String("curl".to_string()),
+                        ),
+                    ]),
+                },
+            ],
+        };
+
+        let serialized = notify
+            .serialize_with_order(&["ip", "country", "user_agent"])
+            .unwrap();
+
+        #[rustfmt::skip]
+        let mut expected = vec![
+            0x03,                   // FRAME-TYPE = NOTIFY
+            0x00, 0x00, 0x00, 0x01, // FLAGS = FIN
+            0x01,                   // STREAM-ID = 1
+            0x02,                   // FRAME-ID = 2
+            0x0f,
+        ];
+        expected.extend_from_slice(b"check-client-ip");
+        expected.push(0x01); // NB-ARGS = 1
+        expected.extend_from_slice(&[0x02, b'i', b'p', 0x06, 10, 0, 0, 1]);
+        expected.push(0x0b);
+        expected.extend_from_slice(b"log-request");
+        expected.push(0x03); // NB-ARGS = 3
+        expected.extend_from_slice(&[0x02, b'i', b'p', 0x06, 10, 0, 0, 1]);
+        expected.push(0x07);
+        expected.extend_from_slice(b"country");
+        expected.extend_from_slice(&[0x08, 0x02, b'x', b'x']);
+        expected.push(0x0a);
+        expected.extend_from_slice(b"user_agent");
+        expected.extend_from_slice(&[0x08, 0x04, b'c', b'u', b'r', b'l']);
+
+        assert_eq!(serialized[4..], expected);
+        assert_eq!(
+            u32::from_be_bytes(serialized[..4].try_into().unwrap()) as usize,
+            expected.len()
+        );
+    }
+
+    #[test]
+    fn test_notify_too_many_args() {
+        let args = |count: u32| {
+            (0..count)
+                .map(|i| (format!("arg{}", i), TypedData::UInt32(i)))
+                .collect::<HashMap<_, _>>()
+        };
+
+        let mut notify = NotifyFrame {
+            metadata: Metadata {
+                flags: FrameFlags::new(true, false),
+                stream_id: 1,
+                frame_id: 1,
+            },
+            messages: vec![Message {
+                name: "many-args".to_string(),
+                args: args(255),
+            }],
+        };
+
+        let serialized = notify.serialize().unwrap();
+        let (_, frame) = crate::parser::parse_frame(&serialized).unwrap();
+        assert_eq!(frame.payload(), notify.payload());
+
+        notify.messages[0].args = args(256);
+        #[cfg(feature = "std")]
+        assert_eq!(
+            notify.serialize().unwrap_err().kind(),
+            std::io::ErrorKind::InvalidInput
+        );
+        assert_eq!(
+            notify.serialize_bounded(u32::MAX).unwrap_err(),
+            crate::SpopError::TooManyArgs {
+                message: "many-args".to_string(),
+                count: 256
+            }
+        );
+    }
+
+    #[test]
+    fn test_notify_from_messages() {
+        let messages = vec![
+            Message {
+                name: "check-client-ip".to_string(),
+                args: HashMap::from([(
+                    "ip".to_string(),
+                    TypedData::IPv4(Ipv4Addr::new(10, 0, 0, 1)),
+                )]),
+            },
+            Message {
+                name: "log-request".to_string(),
+                args: HashMap::from([("status".to_string(), TypedData::UInt32(200))]),
+            },
+        ];
+
+        let notify = NotifyFrame::from_messages(3, 7, messages.clone());
+        assert!(notify.metadata.flags.is_fin());
+
+        let serialized = notify.serialize().unwrap();
+        let (rest, frame) = crate::parser::parse_frame(&serialized).unwrap();
+        assert!(rest.is_empty());
+        assert_eq!(frame.metadata(), notify.metadata());
+        assert_eq!(frame.payload().as_messages(), Some(messages.as_slice()));
+    }
+
+    #[test]
+    fn test_notify_builder() {
+        let notify = NotifyFrame::new(3, 7)
+            .add_message("check-client-ip")
+            .arg("ip", TypedData::IPv4(Ipv4Addr::new(10, 0, 0, 1)))
+            .add_message("log-request")
+            .arg("method", TypedData::String("GET".to_string()))
+            .arg("path", TypedData::String("/".to_string()))
+            .arg("status", TypedData::UInt32(200))
+            .build();
+
+        let metadata = notify.metadata();
+        assert!(metadata.flags.is_fin());
+        assert!(!metadata.flags.is_abort());
+        assert_eq!((metadata.stream_id, metadata.frame_id), (3, 7));
+
+        assert_eq!(notify.messages.len(), 2);
+        assert_eq!(notify.messages[0].name, "check-client-ip");
+        assert_eq!(notify.messages[0].args.len(), 1);
+        assert_eq!(notify.messages[1].name, "log-request");
+        assert_eq!(notify.messages[1].args.len(), 3);
+        assert_eq!(notify.messages[1].args["status"], TypedData::UInt32(200));
+    }
 }
@@ -0,0 +1,193 @@
+use core::fmt;
+
+/// Status code of DISCONNECT frames
+///
+/// <https://github.com/haproxy/haproxy/blob/master/doc/SPOE.txt>, section "Errors & timeouts"
+///
+/// ```text
+/// Here is the list of all known errors:
+///
+///     STATUS CODE | DESCRIPTION
+///     ----------------------------------------------------------------------
+///         0       |  normal (no error occurred)
+///         1       |  I/O error
+///         2       |  A timeout occurred
+///         3       |  frame is too big
+///         4       |  invalid frame received
+///         5       |  version value not found
+///         6       |  max-frame-size value not found
+///         7       |  capabilities value not found
+///         8       |  unsupported version
+///         9       |  max-frame-size too big or too small
+///        10       |  payload fragmentation is not supported
+///        11       |  invalid interlaced frames
+///        12       |  frame-id not found (it does not match any referenced frame)
+///        13       |  resource allocation error
+///        99       |  an unknown error occurrde
+///     ----------------------------------------------------------------------
+/// ```
+///
+/// Codes outside of this table are kept as `Unknown`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DisconnectStatus {
+    Normal,
+    IoError,
+    Timeout,
+    FrameTooBig,
+    InvalidFrame,
+    VersionNotFound,
+    MaxFrameSizeNotFound,
+    CapabilitiesNotFound,
+    UnsupportedVersion,
+    InvalidMaxFrameSize,
+    FragmentationNotSupported,
+    InvalidInterlacedFrames,
+    FrameIdNotFound,
+    ResourceAllocation,
+    UnknownError,
+    Unknown(u32),
+}
+
+impl DisconnectStatus {
+    pub const fn from_u32(value: u32) -> Self {
+        match value {
+            0 => Self::Normal,
+            1 => Self::IoError,
+            2 => Self::Timeout,
+            3 => Self::FrameTooBig,
+            4 => Self::InvalidFrame,
+            5 => Self::VersionNotFound,
+            6 => Self::MaxFrameSizeNotFound,
+            7 => Self::CapabilitiesNotFound,
+            8 => Self::UnsupportedVersion,
+            9 => Self::InvalidMaxFrameSize,
+            10 => Self::FragmentationNotSupported,
+            11 => Self::InvalidInterlacedFrames,
+            12 => Self::FrameIdNotFound,
+            13 => Self::ResourceAllocation,
+            99 => Self::UnknownError,
+            other => Self::Unknown(other),
+        }
+    }
+
+    pub const fn to_u32(self) -> u32 {
+        match self {
+            Self::Normal => 0,
+            Self::IoError => 1,
+            Self::Timeout => 2,
+            Self::FrameTooBig => 3,
+            Self::InvalidFrame => 4,
+            Self::VersionNotFound => 5,
+            Self::MaxFrameSizeNotFound => 6,
+            Self::CapabilitiesNotFound => 7,
+            Self::UnsupportedVersion => 8,
+            Self::InvalidMaxFrameSize => 9,
+            Self::FragmentationNotSupported => 10,
+            Self::InvalidInterlacedFrames => 11,
+            Self::FrameIdNotFound => 12,
+            Self::ResourceAllocation => 13,
+            Self::UnknownError => 99,
+            Self::Unknown(code) => code,
+        }
+    }
+}
+
+impl fmt::Display for DisconnectStatus {
+    /// Writes the description of the spec, e.g. `frame is too big`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Normal => write!(f, "normal (no error occurred)"),
+            Self::IoError => write!(f, "I/O error"),
+            Self::Timeout => write!(f, "a timeout occurred"),
+            Self::FrameTooBig => write!(f, "frame is too big"),
+            Self::InvalidFrame => write!(f, "invalid frame received"),
+            Self::VersionNotFound => write!(f, "version value not found"),
+            Self::MaxFrameSizeNotFound => write!(f, "max-frame-size value not found"),
+            Self::CapabilitiesNotFound => write!(f, "capabilities value not found"),
+            Self::UnsupportedVersion => write!(f, "unsupported version"),
+            Self::InvalidMaxFrameSize => write!(f, "max-frame-size too big or too small"),
+            Self::FragmentationNotSupported => {
+                write!(f, "payload fragmentation is not supported")
+            }
+            Self::InvalidInterlacedFrames => write!(f, "invalid interlaced frames"),
+            Self::FrameIdNotFound => write!(f, "frame-id not found"),
+            Self::ResourceAllocation => write!(f, "resource allocation error"),
+            Self::UnknownError => write!(f, "an unknown error occurred"),
+            Self::Unknown(code) => write!(f, "unknown status code {}", code),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_disconnect_status_codes() {
+        let documented = [
+            (0, DisconnectStatus::Normal, "normal (no error occurred)"),
+            (1, DisconnectStatus::IoError, "I/O error"),
+            (2, DisconnectStatus::Timeout, "a timeout occurred"),
+            (3, DisconnectStatus::FrameTooBig, "frame is too big"),
+            (4, DisconnectStatus::InvalidFrame, "invalid frame received"),
+            (
+                5,
+                DisconnectStatus::VersionNotFound,
+                "version value not found",
+            ),
+            (
+                6,
+                DisconnectStatus::MaxFrameSizeNotFound,
+                "max-frame-size value not found",
+            ),
+            (
+                7,
+                DisconnectStatus::CapabilitiesNotFound,
+                "capabilities value not found",
+            ),
+            (
+                8,
+                DisconnectStatus::UnsupportedVersion,
+                "unsupported version",
+            ),
+            (
+                9,
+                DisconnectStatus::InvalidMaxFrameSize,
+                "max-frame-size too big or too small",
+            ),
+            (
+                10,
+                DisconnectStatus::FragmentationNotSupported,
+                "payload fragmentation is not supported",
+            ),
+            (
+                11,
+                DisconnectStatus::InvalidInterlacedFrames,
+                "invalid interlaced frames",
+            ),
+            (12, DisconnectStatus::FrameIdNotFound, "frame-id not found"),
+            (
+                13,
+                DisconnectStatus::ResourceAllocation,
+                "resource allocation error",
+            ),
+            (
+                99,
+                DisconnectStatus::UnknownError,
+                "an unknown error occurred",
+            ),
+        ];
+
+        for (code, status, description) in documented {
+            assert_eq!(DisconnectStatus::from_u32(code), status);
+            assert_eq!(status.to_u32(), code);
+            assert_eq!(status.to_string(), description);
+        }
+
+        let status = DisconnectStatus::from_u32(42);
+        assert_eq!(status, DisconnectStatus::Unknown(42));
+        assert_eq!(status.to_u32(), 42);
+        assert_eq!(status.to_string(), "unknown status code 42");
+    }
+}
@@ -6,24 +6,82 @@
 //! including the ability to serialize/deserialize frames and handle various frame types such as
 //! `AgentHello`, `HaproxyHello`, and `Ack`. It supports both Unix and TCP-based transports
 //! and provides utilities for creating, parsing, and manipulating SPOP frames.
+//!
+//! # Features
+//!
+//! - `std` (default): the `SpopCodec` for `tokio_util::codec::Framed`, the in-memory
+//!   [`testing::loopback`] and the blocking readers and writers of [`sync`]. Without it the crate
+//!   is `no_std` and only needs `alloc`: parsing and encoding frames use `nom`, `bytes` and
+//!   `hashbrown`. KV-LISTs are then `hashbrown` maps, see [`HashMap`], and serialization reports
+//!   a [`SpopError`] instead of a `std::io::Error`, see [`SerializeError`]. IP addresses are the
+//!   `core::net` types either way.
+//! - `rand`: `EngineId::generate`, random engine-ids formatted as UUIDs.
+//! - `serde`: `Serialize` and `Deserialize` for frames and their payloads.
+//! - `tracing`: `tracing` events from the parser and the codec, with the frame type, the ids and
+//!   the decoding errors as fields.
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
+
+use alloc::{boxed::Box, format, string::String, vec::Vec};
+use bytes::{BufMut, BytesMut};
+use core::fmt::Write;
+
+#[cfg(not(feature = "std"))]
+pub use hashbrown::HashMap;
+/// The map holding KV-LISTs and message arguments
+///
+/// This is `std::collections::HashMap` with the `std` feature and `hashbrown::HashMap` without it.
+#[cfg(feature = "std")]
+pub use std::collections::HashMap;
+
 pub mod frames;
 pub mod parser;
 
+pub mod agent;
+pub use self::agent::{AgentConfig, AgentSession, Handshake, SessionState};
+
+pub mod build;
+
+pub mod error;
+pub use self::error::SpopError;
+
 pub mod actions;
 pub use self::actions::{Action, VarScope};
 
 pub mod frame;
+use self::frame::Message;
 pub use self::frame::{FrameFlags, FramePayload, FrameType, Metadata};
 
 pub mod types;
-pub use self::types::TypedData;
+pub use self::types::{KvListExt, TypedData};
 
 pub mod varint;
-pub use self::varint::{decode_varint, encode_varint};
+use self::varint::put_varint;
+pub use self::varint::{decode_varint, encode_varint, varint_len};
 
+#[cfg(feature = "std")]
 pub mod codec;
+#[cfg(feature = "std")]
 pub use self::codec::SpopCodec;
 
+#[cfg(feature = "std")]
+pub mod sync;
+
+pub mod testing;
+
+pub mod visitor;
+pub use self::visitor::FrameVisitor;
+
+/// The error of [`SpopFrame::serialize`] and its variants
+///
+/// With the `std` feature this is a `std::io::Error` of kind `InvalidInput`, wrapping the
+/// [`SpopError`]. Without it, the [`SpopError`] is returned as is.
+#[cfg(feature = "std")]
+pub type SerializeError = std::io::Error;
+#[cfg(not(feature = "std"))]
+pub type SerializeError = SpopError;
+
 /// core trait for the SPOP frame
 ///
 /// <https://github.com/haproxy/haproxy/blob/master/doc/SPOE.txt#L673>
@@ -92,117 +150,761 @@ pub use self::codec::SpopCodec;
 ///             buffer size minus 4 bytes, reserved for the frame length
 ///             (tune.bufsize - 4). It is the default value announced by HAproxy.
 /// ```
-pub trait SpopFrame: std::fmt::Debug + Send {
+pub trait SpopFrame: core::any::Any + CloneFrame + core::fmt::Debug + Send {
     fn frame_type(&self) -> &FrameType;
     fn metadata(&self) -> Metadata;
     fn payload(&self) -> FramePayload;
 
-    fn serialize(&self) -> std::io::Result<Vec<u8>> {
+    /// Dispatch to the [`FrameVisitor`] method matching the concrete frame type
+    ///
+    /// The visitor has no method for frame types defined outside this crate, the default does
+    /// nothing.
+    fn accept(&self, _visitor: &mut dyn FrameVisitor) {}
+
+    /// Returns a one-line summary of the frame, e.g. `ACK stream=3 frame=7 actions=2`
+    fn summary(&self) -> String {
+        let metadata = self.metadata();
+        let payload = self.payload();
+
+        format!(
+            "{} stream={} frame={} {}={}",
+            self.frame_type(),
+            metadata.stream_id,
+            metadata.frame_id,
+            payload.kind(),
+            payload.len()
+        )
+    }
+
+    /// Returns a one-line description of the frame and its content, for tracing
+    ///
+    /// Unlike [`SpopFrame::summary`], every message argument, action and KV-LIST item is shown,
+    /// KV-LIST items in alphabetical order, e.g.
+    /// `NOTIFY stream=3 frame=7 messages=[check-client-ip(ip=1.2.3.4)]`.
+    fn describe(&self) -> String {
+        let metadata = self.metadata();
+        let payload = self.payload();
+
+        let mut out = format!(
+            "{} stream={} frame={} {}=[",
+            self.frame_type(),
+            metadata.stream_id,
+            metadata.frame_id,
+            payload.kind()
+        );
+
+        match &payload {
+            FramePayload::ListOfMessages(messages) => {
+                for (i, message) in messages.iter().enumerate() {
+                    let sep = if i > 0 { ", " } else { "" };
+                    let _ = write!(out, "{}{}(", sep, message.name);
+                    describe_kv_list(&message.args, &mut out);
+                    out.push(')');
+                }
+            }
+            FramePayload::ListOfActions(actions) => {
+                for (i, action) in actions.iter().enumerate() {
+                    let sep = if i > 0 { ", " } else { "" };
+                    let _ = write!(out, "{}{}", sep, action);
+                }
+            }
+            FramePayload::KVList(kv_list) => describe_kv_list(kv_list, &mut out),
+        }
+
+        out.push(']');
+        out
+    }
+
+    /// Returns the encoded length of the payload of the frame
+    ///
+    /// The default measures [`SpopFrame::payload`], the frames of this crate measure their fields
+    /// directly instead, so [`SpopFrame::encoded_len`] does not copy the frame content.
+    fn payload_len(&self) -> usize {
+        encoded_payload_len(&self.payload())
+    }
+
+    /// Writes the payload of the frame at the end of `buf`
+    ///
+    /// `order` is the KV-LIST key order of [`SpopFrame::serialize_with_order`]. The default
+    /// encodes [`SpopFrame::payload`], the frames of this crate write their fields directly
+    /// instead, so serializing a frame does not copy its content into a [`FramePayload`] first.
+    fn write_payload(&self, buf: &mut dyn BufMut, order: Option<&[&str]>) -> Result<(), SpopError> {
+        encode_payload(&self.payload(), buf, order)
+    }
+
+    fn serialize(&self) -> Result<Vec<u8>, SerializeError> {
+        serialize_frame(self, None)
+    }
+
+    /// Serializes the frame, emitting KV-LIST items in the given key order
+    ///
+    /// Keys listed in `order` come first, in that order, followed by any remaining keys sorted
+    /// alphabetically. This applies to KV-LIST payloads and to the arguments of each message
+    /// in a LIST-OF-MESSAGES, so the exact byte layout of a reference frame can be reproduced.
+    fn serialize_with_order(&self, order: &[&str]) -> Result<Vec<u8>, SerializeError> {
+        serialize_frame(self, Some(order))
+    }
+
+    /// Returns the FRAME-LENGTH of the frame, computed without encoding it
+    ///
+    /// This is the length of the frame, 4-byte FRAME-LENGTH prefix excluded, as the negotiated
+    /// max-frame-size counts it: a frame fits when `encoded_len()` is not over it, which tells an
+    /// agent to split its actions across several ACK frames before serializing them.
+    fn encoded_len(&self) -> usize {
+        let metadata = self.metadata();
+
+        // <FRAME-TYPE:1 byte> <FLAGS:4 bytes> <STREAM-ID> <FRAME-ID>
+        1 + 4 + varint_len(metadata.stream_id) + varint_len(metadata.frame_id) + self.payload_len()
+    }
+
+    /// Returns the number of bytes the serialized frame takes on the wire
+    ///
+    /// This is [`SpopFrame::encoded_len`] plus the 4-byte FRAME-LENGTH prefix, e.g. to reserve a
+    /// buffer before [`SpopFrame::serialize_into`].
+    fn wire_len(&self) -> usize {
+        4 + self.encoded_len()
+    }
+
+    /// Serializes the frame at the end of `dst`, prefixed with its length
+    ///
+    /// The frame is written in place: the 4-byte FRAME-LENGTH is reserved first and patched once
+    /// the frame is written, so no intermediate buffer is allocated. On error, `dst` is left
+    /// unchanged.
+    fn serialize_into(&self, dst: &mut BytesMut) -> Result<(), SerializeError> {
+        let start = dst.len();
+
+        write_frame(self, dst, None)
+            .inspect_err(|_| dst.truncate(start))
+            .map_err(invalid_input)
+    }
+
+    /// Serializes the frame, failing when its FRAME-LENGTH is over `max`
+    ///
+    /// `max` is the max-frame-size negotiated during the HELLO handshake, which does not count
+    /// the 4-byte FRAME-LENGTH prefix. An oversized frame would be dropped by HAProxy, this
+    /// reports it with [`SpopError::FrameTooLarge`] instead.
+    fn serialize_bounded(&self, max: u32) -> Result<Vec<u8>, SpopError> {
         let mut serialized = Vec::new();
 
-        // frame type (1 byte)
-        serialized.push(self.frame_type().to_u8());
+        write_frame(self, &mut serialized, None)?;
+
+        let length = u32::try_from(serialized.len() - 4).unwrap_or(u32::MAX);
+        if length > max {
+            return Err(SpopError::FrameTooLarge { length, max });
+        }
+
+        Ok(serialized)
+    }
+}
+
+/// Clones a frame behind a trait object, see `impl Clone for Box<dyn SpopFrame>`
+///
+/// It is implemented for every `Clone` frame, a frame which is not `Clone` implements it by hand.
+pub trait CloneFrame {
+    fn clone_box(&self) -> Box<dyn SpopFrame>;
+}
 
-        // Metadata
-        serialized.extend(self.metadata().serialize());
+impl<T: SpopFrame + Clone> CloneFrame for T {
+    fn clone_box(&self) -> Box<dyn SpopFrame> {
+        Box::new(self.clone())
+    }
+}
 
-        // payload
-        encode_payload(&self.payload(), &mut serialized)?;
+impl Clone for Box<dyn SpopFrame> {
+    fn clone(&self) -> Self {
+        self.clone_box()
+    }
+}
 
-        // Prepend frame length
-        let frame_len = serialized.len() as u32;
-        let mut output = frame_len.to_be_bytes().to_vec();
-        output.extend(serialized);
+impl dyn SpopFrame {
+    /// Returns the frame as [`core::any::Any`], to downcast it to its concrete type
+    ///
+    /// ```
+    /// use spop::{SpopFrame, frames::notify::NotifyFrame, parser::parse_frame};
+    ///
+    /// let bytes = NotifyFrame::new(3, 7).add_message("check-client-ip").build().serialize().unwrap();
+    /// let (_, frame) = parse_frame(&bytes).unwrap();
+    ///
+    /// let notify = frame.as_any().downcast_ref::<NotifyFrame>().unwrap();
+    /// assert_eq!(notify.messages[0].name, "check-client-ip");
+    /// ```
+    pub fn as_any(&self) -> &dyn core::any::Any {
+        self
+    }
 
-        Ok(output)
+    /// Parses exactly one complete frame, the counterpart of [`SpopFrame::serialize`]
+    ///
+    /// `bytes` must hold the whole frame, including its 4-byte FRAME-LENGTH prefix, and nothing
+    /// else: a truncated frame fails with `ErrorKind::Complete` and trailing bytes with
+    /// [`SpopError::TrailingBytes`]. Use [`parser::parse_frame`] to read frames from a stream.
+    ///
+    /// ```
+    /// use spop::{SpopFrame, frames::Ack};
+    ///
+    /// let bytes = Ack::new(1, 2).serialize().unwrap();
+    /// let frame = <dyn SpopFrame>::from_bytes(&bytes).unwrap();
+    /// assert_eq!(frame.summary(), "ACK stream=1 frame=2 actions=0");
+    /// ```
+    pub fn from_bytes(bytes: &[u8]) -> Result<Box<dyn SpopFrame>, SpopError> {
+        match parser::parse_frame(bytes) {
+            Ok(([], frame)) => Ok(frame),
+            Ok((remaining, _)) => Err(SpopError::TrailingBytes(remaining.len())),
+            Err(nom::Err::Incomplete(_)) => Err(SpopError::Parse(nom::error::ErrorKind::Complete)),
+            Err(nom::Err::Error(e) | nom::Err::Failure(e)) => Err(e),
+        }
     }
 }
 
+/// Helper function to compute the encoded length of a payload
+fn encoded_payload_len(payload: &FramePayload) -> usize {
+    match payload {
+        FramePayload::ListOfActions(actions) => actions_len(actions),
+        FramePayload::KVList(kv_list) => kv_list_len(kv_list),
+        FramePayload::ListOfMessages(messages) => messages_len(messages),
+    }
+}
+
+/// Helper function to compute the encoded length of a LIST-OF-ACTIONS
+fn actions_len(actions: &[Action]) -> usize {
+    actions.iter().map(Action::encoded_len).sum()
+}
+
+/// Helper function to compute the encoded length of a LIST-OF-MESSAGES
+fn messages_len(messages: &[Message]) -> usize {
+    messages
+        .iter()
+        .map(|message| {
+            // <MESSAGE-NAME> <NB-ARGS:1 byte> <KV-LIST>
+            varint_len(message.name.len() as u64)
+                + message.name.len()
+                + 1
+                + kv_list_len(&message.args)
+        })
+        .sum()
+}
+
+/// Helper function to compute the encoded length of a KV-LIST
+fn kv_list_len(kv_list: &HashMap<String, TypedData>) -> usize {
+    kv_list
+        .iter()
+        .map(|(key, value)| varint_len(key.len() as u64) + key.len() + value.encoded_len())
+        .sum()
+}
+
+/// Helper function to describe a KV-LIST, keys are written in alphabetical order
+fn describe_kv_list(kv_list: &HashMap<String, TypedData>, out: &mut String) {
+    let mut items: Vec<_> = kv_list.iter().collect();
+    items.sort_unstable_by_key(|(key, _)| *key);
+
+    for (i, (key, value)) in items.into_iter().enumerate() {
+        let sep = if i > 0 { ", " } else { "" };
+        let _ = write!(out, "{}{}={}", sep, key, value);
+    }
+}
+
+/// Helper function to serialize a frame, prefixed with its length
+fn serialize_frame<F: SpopFrame + ?Sized>(
+    frame: &F,
+    order: Option<&[&str]>,
+) -> Result<Vec<u8>, SerializeError> {
+    let mut serialized = Vec::new();
+
+    write_frame(frame, &mut serialized, order).map_err(invalid_input)?;
+
+    Ok(serialized)
+}
+
+/// Serialization errors come from the frame content, not from the data received
+#[cfg(feature = "std")]
+fn invalid_input(e: SpopError) -> SerializeError {
+    std::io::Error::new(std::io::ErrorKind::InvalidInput, e)
+}
+
+#[cfg(not(feature = "std"))]
+const fn invalid_input(e: SpopError) -> SerializeError {
+    e
+}
+
+/// Helper function to write a frame at the end of `buf`, prefixed with its length
+fn write_frame<F, B>(frame: &F, buf: &mut B, order: Option<&[&str]>) -> Result<(), SpopError>
+where
+    F: SpopFrame + ?Sized,
+    B: BufMut + AsMut<[u8]>,
+{
+    let start = buf.as_mut().len();
+
+    // frame length (4 bytes), patched once the frame is written
+    buf.put_u32(0);
+
+    // frame type (1 byte)
+    buf.put_u8(frame.frame_type().to_u8());
+
+    // Metadata
+    frame.metadata().to_bytes(buf);
+
+    // payload
+    frame.write_payload(buf, order)?;
+
+    let frame = &mut buf.as_mut()[start..];
+    let frame_len = (frame.len() - 4) as u32;
+    frame[..4].copy_from_slice(&frame_len.to_be_bytes());
+
+    Ok(())
+}
+
 /// Helper function to encode the payload.
-/// It supports ListOfActions and KVList payloads.
-fn encode_payload(payload: &FramePayload, buf: &mut Vec<u8>) -> std::io::Result<()> {
+/// It supports ListOfActions, ListOfMessages and KVList payloads.
+fn encode_payload<B: BufMut + ?Sized>(
+    payload: &FramePayload,
+    buf: &mut B,
+    order: Option<&[&str]>,
+) -> Result<(), SpopError> {
     match payload {
-        FramePayload::ListOfActions(actions) => {
-            // ACTION-SET-VAR  : <SET-VAR:1 byte><NB-ARGS:1 byte><VAR-SCOPE:1 byte><VAR-NAME><VAR-VALUE>
+        FramePayload::ListOfActions(actions) => encode_actions(actions, buf),
+        FramePayload::KVList(kv_pairs) => {
+            encode_kv_list(kv_pairs, buf, order);
+            Ok(())
+        }
+        FramePayload::ListOfMessages(messages) => encode_messages(messages, buf, order),
+    }
+}
 
-            for action in actions {
-                match action {
-                    Action::SetVar { scope, name, value } => {
-                        // Action type: SET-VAR (1 byte)
-                        buf.push(0x01);
+/// Helper function to encode a LIST-OF-ACTIONS
+fn encode_actions<B: BufMut + ?Sized>(actions: &[Action], buf: &mut B) -> Result<(), SpopError> {
+    // LIST-OF-ACTIONS  : [ <ACTION-TYPE:1 byte> <NB-ARGS:1 byte> <ACTION-ARGS> ... ]
+    for action in actions {
+        action.to_bytes(buf)?;
+    }
 
-                        // Number of arguments: 3 (1 byte)
-                        buf.push(0x03);
+    Ok(())
+}
 
-                        // Scope (1 byte)
-                        buf.push(scope.to_u8());
+/// Helper function to encode a LIST-OF-MESSAGES, optionally following a key order for the
+/// arguments
+fn encode_messages<B: BufMut + ?Sized>(
+    messages: &[Message],
+    buf: &mut B,
+    order: Option<&[&str]>,
+) -> Result<(), SpopError> {
+    for message in messages {
+        // use encode_varint for the length of the name
+        put_varint(buf, message.name.len() as u64);
+
+        // serialize the name
+        buf.put_slice(message.name.as_bytes());
+
+        // message.args count, NB-ARGS is a single byte
+        let count = u8::try_from(message.args.len()).map_err(|_| SpopError::TooManyArgs {
+            message: message.name.clone(),
+            count: message.args.len(),
+        })?;
+        buf.put_u8(count);
+
+        encode_kv_list(&message.args, buf, order);
+    }
 
-                        // Serialize variable name (length + bytes)
-                        buf.extend(encode_varint(name.len() as u64));
-                        buf.extend_from_slice(name.as_bytes());
+    Ok(())
+}
 
-                        // Serialize variable value based on type
-                        value.to_bytes(buf);
-                    }
+/// Helper function to encode a KV-LIST, optionally following a key order
+fn encode_kv_list<B: BufMut + ?Sized>(
+    kv_pairs: &HashMap<String, TypedData>,
+    buf: &mut B,
+    order: Option<&[&str]>,
+) {
+    let Some(order) = order else {
+        for (key, value) in kv_pairs {
+            encode_kv_pair(key, value, buf);
+        }
+        return;
+    };
 
-                    Action::UnSetVar { scope, name } => {
-                        // Action type: UNSET-VAR (1 byte)
-                        buf.push(0x02);
+    for (key, value) in order.iter().filter_map(|key| kv_pairs.get_key_value(*key)) {
+        encode_kv_pair(key, value, buf);
+    }
 
-                        // Number of arguments: 2 (1 byte)
-                        buf.push(0x02);
+    let mut remaining: Vec<_> = kv_pairs
+        .iter()
+        .filter(|(key, _)| !order.contains(&key.as_str()))
+        .collect();
+    remaining.sort_by(|a, b| a.0.cmp(b.0));
 
-                        // Scope (1 byte)
-                        buf.push(scope.to_u8());
+    for (key, value) in remaining {
+        encode_kv_pair(key, value, buf);
+    }
+}
 
-                        // Serialize variable name (length + bytes)
-                        buf.extend(encode_varint(name.len() as u64));
-                        buf.extend_from_slice(name.as_bytes());
-                    }
-                }
-            }
+/// Helper function to encode a KV-LIST item
+fn encode_kv_pair<B: BufMut + ?Sized>(key: &str, value: &TypedData, buf: &mut B) {
+    // <KEY-LENGTH><KEY><VALUE-TYPE><VALUE-LNGTH><VALUE>
+
+    // use encode_varint for the length of the key
+    put_varint(buf, key.len() as u64);
+
+    // serialize the key
+    buf.put_slice(key.as_bytes());
+
+    // serialize variable value based on type
+    value.to_bytes(buf);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::frames::Ack;
+
+    #[test]
+    fn test_from_bytes() {
+        let ack = Ack::new(3, 7).set_var(VarScope::Session, "score", TypedData::UInt32(42));
+        let bytes = ack.serialize().unwrap();
+
+        let frame = <dyn SpopFrame>::from_bytes(&bytes).unwrap();
+        assert_eq!(
+            Ack::try_from((frame.metadata(), frame.payload())).unwrap(),
+            ack
+        );
+
+        let mut trailing = bytes.clone();
+        trailing.extend_from_slice(&bytes[..2]);
+        assert_eq!(
+            <dyn SpopFrame>::from_bytes(&trailing).unwrap_err(),
+            SpopError::TrailingBytes(2)
+        );
+
+        assert_eq!(
+            <dyn SpopFrame>::from_bytes(&bytes[..bytes.len() - 1]).unwrap_err(),
+            SpopError::Parse(nom::error::ErrorKind::Complete)
+        );
+    }
+
+    #[test]
+    fn test_serialize_into() {
+        let notify = frames::notify::NotifyFrame::new(3, 7)
+            .add_message("check-client-ip")
+            .arg("ip", TypedData::IPv4(std::net::Ipv4Addr::LOCALHOST))
+            .build();
+        let ack = Ack::new(3, 7).set_var(VarScope::Session, "score", TypedData::UInt32(42));
+
+        let mut dst = BytesMut::from(&b"prefix"[..]);
+        notify.serialize_into(&mut dst).unwrap();
+        ack.serialize_into(&mut dst).unwrap();
+
+        let expected = [
+            &b"prefix"[..],
+            &notify.serialize().unwrap(),
+            &ack.serialize().unwrap(),
+        ]
+        .concat();
+        assert_eq!(dst, expected);
+
+        // NB-ARGS overflow, nothing is written
+        let mut too_many = notify;
+        too_many.messages[0].args = (0..256)
+            .map(|i| (format!("arg{}", i), TypedData::UInt32(i)))
+            .collect();
+        assert!(too_many.serialize_into(&mut dst).is_err());
+        assert_eq!(dst, expected);
+    }
+
+    #[test]
+    fn test_serialize_bounded() {
+        let ack = Ack::new(3, 7).set_var(
+            VarScope::Transaction,
+            "body",
+            TypedData::Binary(vec![0xab; 1024]),
+        );
+        let serialized = ack.serialize().unwrap();
+        let length = serialized.len() as u32 - 4;
+
+        assert_eq!(ack.serialize_bounded(length).unwrap(), serialized);
+        assert_eq!(
+            ack.serialize_bounded(256).unwrap_err(),
+            SpopError::FrameTooLarge { length, max: 256 }
+        );
+    }
+
+    #[test]
+    fn test_describe() {
+        use crate::{
+            build::{agent, haproxy},
+            frames::{FrameCapabilities, notify::NotifyFrame},
+        };
+        use semver::Version;
+
+        let notify = NotifyFrame::new(3, 7)
+            .add_message("check-client-ip")
+            .arg("ip", std::net::Ipv4Addr::new(1, 2, 3, 4))
+            .add_message("log")
+            .arg("path", "/")
+            .arg("body", vec![0x00, 0xff])
+            .build();
+
+        let ack = Ack::new(3, 7)
+            .set_var(VarScope::Session, "score", 42u32)
+            .unset_var(VarScope::Transaction, "tmp");
+
+        let frames: [(Box<dyn SpopFrame>, &str); 6] = [
+            (
+                Box::new(notify),
+                "NOTIFY stream=3 frame=7 messages=[check-client-ip(ip=1.2.3.4), \
+                 log(body=<2 bytes>, path=\"/\")]",
+            ),
+            (
+                Box::new(ack),
+                "ACK stream=3 frame=7 actions=[set-var(sess.score=42), unset-var(txn.tmp)]",
+            ),
+            (
+                Box::new(haproxy::hello(
+                    vec![Version::new(2, 0, 0)],
+                    16380,
+                    vec![FrameCapabilities::Pipelining],
+                )),
+                "HAPROXY-HELLO stream=0 frame=0 items=[capabilities=\"pipelining\", \
+                 max-frame-size=16380, supported-versions=\"2.0\"]",
+            ),
+            (
+                Box::new(agent::hello(Version::new(2, 0, 0), 16380, vec![])),
+                "AGENT-HELLO stream=0 frame=0 items=[capabilities=\"\", max-frame-size=16380, \
+                 version=\"2.0\"]",
+            ),
+            (
+                Box::new(haproxy::disconnect(2, "timeout")),
+                "HAPROXY-DISCONNECT stream=0 frame=0 items=[message=\"timeout\", status-code=2]",
+            ),
+            (
+                Box::new(agent::disconnect(0, "bye")),
+                "AGENT-DISCONNECT stream=0 frame=0 items=[message=\"bye\", status-code=0]",
+            ),
+        ];
+
+        for (frame, expected) in frames {
+            assert_eq!(frame.describe(), expected);
         }
+    }
 
-        FramePayload::KVList(kv_pairs) => {
-            for (key, value) in kv_pairs {
-                // <KEY-LENGTH><KEY><VALUE-TYPE><VALUE-LNGTH><VALUE>
+    #[test]
+    fn test_encoded_len_and_wire_len() {
+        use crate::build::{agent, haproxy};
+        use crate::frames::{FrameCapabilities, notify::NotifyFrame};
+        use semver::Version;
+
+        let frames: Vec<Box<dyn SpopFrame>> = vec![
+            Box::new(haproxy::hello(
+                vec![Version::new(2, 0, 0), Version::new(1, 0, 0)],
+                16380,
+                vec![FrameCapabilities::Pipelining],
+            )),
+            Box::new(haproxy::disconnect(2, "a timeout occurred")),
+            Box::new(
+                NotifyFrame::new(300, 70000)
+                    .add_message("check-client-ip")
+                    .arg("ip", std::net::Ipv6Addr::LOCALHOST)
+                    .arg("len", -1i64)
+                    .arg("body", vec![0xab; 300])
+                    .build(),
+            ),
+            Box::new(agent::hello(Version::new(2, 0, 0), 16380, vec![])),
+            Box::new(agent::disconnect(0, "normal")),
+            Box::new(
+                Ack::new(3, 7)
+                    .set_var(VarScope::Session, "score", u64::MAX)
+                    .unset_var(VarScope::Transaction, "tmp"),
+            ),
+        ];
+
+        for frame in frames {
+            let serialized = frame.serialize().unwrap();
+            assert_eq!(frame.wire_len(), serialized.len(), "{}", frame.summary());
+            assert_eq!(
+                frame.encoded_len(),
+                u32::from_be_bytes(serialized[..4].try_into().unwrap()) as usize
+            );
+
+            // the max-frame-size does not count the prefix
+            let frame_length = frame.encoded_len() as u32;
+            assert!(frame.serialize_bounded(frame_length).is_ok());
+            assert!(frame.serialize_bounded(frame_length - 1).is_err());
+        }
+    }
+
+    #[test]
+    fn test_clone_box() {
+        let ack = Ack::new(3, 7).set_var(VarScope::Session, "score", 42u32);
+        let (_, frame) = parser::parse_frame(&ack.serialize().unwrap()).unwrap();
+
+        let cloned = frame.clone();
+        assert_eq!(cloned.serialize().unwrap(), frame.serialize().unwrap());
+        assert_eq!(cloned.serialize().unwrap(), ack.serialize().unwrap());
+    }
 
-                // use encode_varint for the length of the key
-                buf.extend(encode_varint(key.len() as u64));
+    #[test]
+    fn test_frame_defined_outside() {
+        // only the frame type, metadata and payload are required
+        #[derive(Debug, Clone)]
+        struct EmptyAck;
 
-                // serialize the key
-                buf.extend_from_slice(key.as_bytes());
+        impl SpopFrame for EmptyAck {
+            fn frame_type(&self) -> &FrameType {
+                &FrameType::Ack
+            }
+
+            fn metadata(&self) -> Metadata {
+                Metadata::default()
+            }
 
-                // serialize variable value based on type
-                value.to_bytes(buf);
+            fn payload(&self) -> FramePayload {
+                FramePayload::ListOfActions(Vec::new())
             }
         }
 
-        FramePayload::ListOfMessages(messages) => {
-            for message in messages {
-                // use encode_varint for the length of the name
-                buf.extend(encode_varint(message.name.len() as u64));
+        struct Visitor;
+        impl FrameVisitor for Visitor {}
+
+        let frame: Box<dyn SpopFrame> = Box::new(EmptyAck);
+        frame.accept(&mut Visitor);
+        assert!(frame.clone().as_any().downcast_ref::<EmptyAck>().is_some());
+        assert_eq!(frame.encoded_len() + 4, frame.serialize().unwrap().len());
+    }
+
+    #[test]
+    fn test_as_any() {
+        use crate::frames::{HaproxyHello, haproxy_hello::HaproxyHelloFrame, notify::NotifyFrame};
+        use semver::Version;
+
+        let hello = HaproxyHello::new(vec![Version::new(2, 0, 0)], 16380, vec![]).into_frame(0, 0);
+        let (_, frame) = parser::parse_frame(&hello.serialize().unwrap()).unwrap();
+
+        let parsed = frame
+            .as_any()
+            .downcast_ref::<HaproxyHelloFrame>()
+            .expect("not a HAPROXY-HELLO frame");
+        assert_eq!(
+            parsed.payload.supported_versions,
+            vec![Version::new(2, 0, 0)]
+        );
+        assert_eq!(parsed.payload.max_frame_size, 16380);
+
+        assert!(frame.as_any().downcast_ref::<NotifyFrame>().is_none());
+    }
+
+    mod proptests {
+        use super::*;
+        use crate::{
+            build::{agent, haproxy},
+            frame::Message,
+            frames::FrameCapabilities,
+            parser::parse_frame,
+            testing::frames_equal,
+            types::arb_typed_data,
+        };
+        use proptest::prelude::*;
+        use semver::Version;
+
+        fn arb_scope() -> impl Strategy<Value = VarScope> {
+            prop_oneof![
+                Just(VarScope::Process),
+                Just(VarScope::Session),
+                Just(VarScope::Transaction),
+                Just(VarScope::Request),
+                Just(VarScope::Response),
+            ]
+        }
+
+        fn arb_action() -> impl Strategy<Value = Action> {
+            prop_oneof![
+                (arb_scope(), ".{0,16}", arb_typed_data())
+                    .prop_map(|(scope, name, value)| Action::SetVar { scope, name, value }),
+                (arb_scope(), ".{0,16}").prop_map(|(scope, name)| Action::UnSetVar { scope, name }),
+                (arb_scope(), ".{0,16}", ".{0,32}")
+                    .prop_map(|(scope, name, expr)| Action::SetVarExpr { scope, name, expr }),
+                (4..=u8::MAX, prop::collection::vec(arb_typed_data(), 0..4)).prop_map(
+                    |(action_type, raw_args)| Action::Unknown {
+                        action_type,
+                        raw_args,
+                    }
+                ),
+            ]
+        }
 
-                // serialize the name
-                buf.extend_from_slice(message.name.as_bytes());
+        fn arb_capabilities() -> impl Strategy<Value = Vec<FrameCapabilities>> {
+            prop::collection::vec(
+                prop_oneof![
+                    Just(FrameCapabilities::Pipelining),
+                    Just(FrameCapabilities::Async),
+                    "[a-z-]{1,12}".prop_map(FrameCapabilities::Unknown),
+                ],
+                0..4,
+            )
+        }
 
-                // message.args count
-                let count = message.args.len();
-                buf.push(count as u8);
+        /// Serializes the frame, parses it back and checks nothing was lost
+        fn assert_round_trip(frame: &dyn SpopFrame) -> Result<(), TestCaseError> {
+            let serialized = frame.serialize().unwrap();
+            prop_assert_eq!(serialized.len(), frame.wire_len());
+
+            let (rest, parsed) = parse_frame(&serialized).unwrap();
+            prop_assert!(rest.is_empty());
+            prop_assert!(
+                frames_equal(frame, &*parsed),
+                "{} != {}",
+                frame.describe(),
+                parsed.describe()
+            );
+            Ok(())
+        }
 
-                for (key, value) in &message.args {
-                    // use encode_varint for the length of the key
-                    buf.extend(encode_varint(key.len() as u64));
+        proptest! {
+            #[test]
+            fn prop_notify_round_trip(
+                stream_id: u64,
+                frame_id: u64,
+                messages in prop::collection::vec(
+                    (".{0,16}", prop::collection::hash_map(".{0,16}", arb_typed_data(), 0..8)),
+                    0..4,
+                ),
+            ) {
+                let messages = messages
+                    .into_iter()
+                    .map(|(name, args)| Message {
+                        name,
+                        args: args.into_iter().collect(),
+                    })
+                    .collect();
+                assert_round_trip(&haproxy::notify(stream_id, frame_id, messages))?;
+            }
 
-                    // serialize the key
-                    buf.extend_from_slice(key.as_bytes());
+            #[test]
+            fn prop_ack_round_trip(
+                stream_id: u64,
+                frame_id: u64,
+                actions in prop::collection::vec(arb_action(), 0..8),
+            ) {
+                let mut ack = Ack::new(stream_id, frame_id);
+                ack.actions = actions;
+                assert_round_trip(&ack)?;
+            }
 
-                    // serialize variable value based on type
-                    value.to_bytes(buf);
-                }
+            #[test]
+            fn prop_hello_round_trip(
+                major: u8,
+                minor: u8,
+                max_frame_size: u32,
+                capabilities in arb_capabilities(),
+            ) {
+                let version = Version::new(major.into(), minor.into(), 0);
+                assert_round_trip(&haproxy::hello(
+                    vec![version.clone()],
+                    max_frame_size,
+                    capabilities.clone(),
+                ))?;
+                assert_round_trip(&agent::hello(version, max_frame_size, capabilities))?;
+            }
+
+            #[test]
+            fn prop_disconnect_round_trip(status_code: u32, message in ".{0,64}") {
+                assert_round_trip(&haproxy::disconnect(status_code, &message))?;
+                assert_round_trip(&agent::disconnect(status_code, &message))?;
             }
         }
     }
-
-    Ok(())
 }
@@ -1,42 +1,365 @@
+use crate::HashMap;
 use crate::{
-    SpopFrame,
+    SpopError, SpopFrame,
+    actions::{Action, parse_action, parse_action_with_config},
     frame::{FrameFlags, FramePayload, FrameType, Message, Metadata},
     frames::haproxy_disconnect::{HaproxyDisconnect, HaproxyDisconnectFrame},
     frames::haproxy_hello::{HaproxyHello, HaproxyHelloFrame},
     frames::notify::NotifyFrame,
-    types::{TypedData, typed_data},
+    frames::{Ack, AgentDisconnect, AgentHello},
+    types::{
+        TypedData, TypedDataRef, declared_value_len, reserved_type_id, typed_data, typed_data_ref,
+        typed_data_strict,
+    },
     varint::decode_varint,
 };
+use alloc::{boxed::Box, string::String, vec::Vec};
 use nom::{
     Err, IResult, Parser,
     bytes::complete::take,
     combinator::{all_consuming, complete},
-    error::{Error, ErrorKind},
+    error::ErrorKind,
     multi::{many_m_n, many0},
     number::streaming::{be_u8, be_u32},
 };
-use std::collections::HashMap;
+
+/// Limits applied while parsing frames
+///
+/// The default value applies no limit, which is what [`parse_frame`] does.
+#[derive(Debug, Clone, Default)]
+pub struct ParseLimits {
+    /// Maximum length, in bytes, of the STRING and BINARY values of KV-LISTs, messages and actions
+    ///
+    /// Longer values are truncated instead of rejected, and the truncation is reported alongside
+    /// the parsed frame. Only the first bytes are copied out of the input, the rest of the
+    /// declared length is skipped. Strings are cut on a character boundary.
+    pub truncate_values: Option<usize>,
+
+    /// Accepts a frame without the FIN flag when it is the last one of the input
+    ///
+    /// Some buggy producers forget to set FIN on the final frame of a short exchange. When
+    /// enabled, such a frame is treated as the implicit final fragment and parsed with FIN set,
+    /// as long as no bytes follow it. A missing FIN on any other frame is still rejected.
+    ///
+    /// The input must be the whole exchange: `SpopCodec` only applies it once the stream is
+    /// closed, in `decode_eof`, as the end of a read says nothing about the next one.
+    pub tolerate_missing_fin: bool,
+
+    /// Maximum length, in bytes, of the STRING and BINARY values of KV-LISTs, messages and actions
+    ///
+    /// The declared length of a value is checked before the value is read, a longer one fails
+    /// with [`SpopError::ValueTooLarge`]. This bounds the allocation made for a single value,
+    /// whatever the size of the frame.
+    pub max_value_len: Option<usize>,
+}
+
+/// How repeated keys in a KV-LIST are handled
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DuplicateKeyPolicy {
+    /// The frame is rejected with [`SpopError::DuplicateKey`]
+    #[default]
+    RejectDuplicates,
+
+    /// The first value of a key is kept, the next ones are ignored
+    FirstWins,
+
+    /// The last value of a key is kept
+    LastWins,
+}
+
+/// How STRING values that are not valid UTF-8 are handled
+///
+/// This applies to the values of KV-LISTs, message arguments and actions. Keys, message names
+/// and variable names are always checked, an invalid one fails with [`SpopError::InvalidUtf8`]
+/// whatever the policy.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Utf8Policy {
+    /// Invalid sequences are replaced with U+FFFD, see [`String::from_utf8_lossy`]
+    #[default]
+    Lossy,
+
+    /// The frame is rejected with [`SpopError::InvalidUtf8`]
+    Strict,
+}
+
+/// Parser settings, the default value matches [`parse_frame`]
+#[derive(Debug, Clone, Default)]
+pub struct SpopParserConfig {
+    /// Handling of repeated keys in KV-LISTs and message arguments
+    pub duplicate_keys: DuplicateKeyPolicy,
+
+    /// Handling of STRING values of KV-LISTs, messages and actions that are not valid UTF-8
+    pub utf8: Utf8Policy,
+
+    /// Limits applied while parsing, see [`parse_frame_with_limits`]
+    pub limits: ParseLimits,
+
+    /// Rejects KV-LIST, message and action values using reserved encodings
+    ///
+    /// Lenient parsing ignores the unused flag bits of a BOOLEAN, strict parsing fails on them,
+    /// see [`typed_data_strict`]. Strict parsing also requires NOTIFY and ACK frames to be
+    /// attached to a stream, a STREAM-ID or FRAME-ID of 0 fails with
+    /// [`SpopError::InvalidMetadata`].
+    pub strict: bool,
+
+    /// Returns frames with the ABORT flag set instead of failing with
+    /// [`SpopError::UnexpectedAbort`]
+    ///
+    /// The application then decides what to do with them, checking `metadata().flags.is_abort()`.
+    /// FIN must still be set: an aborted frame is also the final one, its FLAGS are FIN | ABORT.
+    /// The payload is parsed as usual, an aborted NOTIFY usually comes without any message, see
+    /// [`NotifyFrame::abort`].
+    pub surface_abort: bool,
+}
+
+/// A parsed frame along with whether some of its values were truncated
+pub type LimitedFrame = (Box<dyn SpopFrame>, bool);
 
 /// Parse a frame from the input byte slice
-pub fn parse_frame(input: &[u8]) -> IResult<&[u8], Box<dyn SpopFrame>> {
+///
+/// Errors are reported as [`SpopError`], `nom::Err::Incomplete` means more bytes are needed.
+/// Fragmented payloads are not supported: a frame without the FIN flag is rejected with
+/// [`SpopError::FragmentationUnsupported`], unless [`ParseLimits::tolerate_missing_fin`] applies.
+pub fn parse_frame(input: &[u8]) -> IResult<&[u8], Box<dyn SpopFrame>, SpopError> {
+    let (remaining, (frame, _)) = parse_frame_with_config(input, &SpopParserConfig::default())?;
+
+    Ok((remaining, frame))
+}
+
+/// Parse a frame from the input byte slice, applying the given limits
+///
+/// The returned flag is `true` when at least one value was truncated because of
+/// [`ParseLimits::truncate_values`].
+pub fn parse_frame_with_limits<'a>(
+    input: &'a [u8],
+    limits: &ParseLimits,
+) -> IResult<&'a [u8], LimitedFrame, SpopError> {
+    let config = SpopParserConfig {
+        limits: limits.clone(),
+        ..SpopParserConfig::default()
+    };
+
+    parse_frame_with_config(input, &config)
+}
+
+/// Parse a frame from the input byte slice, using the given parser settings
+///
+/// The returned flag is `true` when at least one value was truncated because of
+/// [`ParseLimits::truncate_values`].
+pub fn parse_frame_with_config<'a>(
+    input: &'a [u8],
+    config: &SpopParserConfig,
+) -> IResult<&'a [u8], LimitedFrame, SpopError> {
+    parse_frame_at(input, config, true)
+}
+
+/// Same as [`parse_frame_with_config`], `end_of_input` telling whether more bytes may follow the
+/// input, in which case [`ParseLimits::tolerate_missing_fin`] never applies
+pub(crate) fn parse_frame_at<'a>(
+    input: &'a [u8],
+    config: &SpopParserConfig,
+    end_of_input: bool,
+) -> IResult<&'a [u8], LimitedFrame, SpopError> {
+    let (remaining, (frame, truncated)) = parse_parsed_frame(input, config, end_of_input)?;
+
+    Ok((remaining, (frame.into_boxed(), truncated)))
+}
+
+/// A parsed frame, as its concrete type
+///
+/// Returned by [`parse_frame_owned`], so the fields of a frame are reached by matching on its
+/// type instead of going through the [`SpopFrame`] trait.
+#[derive(Debug, Clone)]
+pub enum ParsedFrame {
+    HaproxyHello(HaproxyHelloFrame),
+    HaproxyDisconnect(HaproxyDisconnectFrame),
+    Notify(NotifyFrame),
+    AgentHello(AgentHello),
+    AgentDisconnect(AgentDisconnect),
+    Ack(Ack),
+}
+
+impl ParsedFrame {
+    /// Returns the frame as a trait object
+    pub fn as_frame(&self) -> &dyn SpopFrame {
+        match self {
+            Self::HaproxyHello(frame) => frame,
+            Self::HaproxyDisconnect(frame) => frame,
+            Self::Notify(frame) => frame,
+            Self::AgentHello(frame) => frame,
+            Self::AgentDisconnect(frame) => frame,
+            Self::Ack(frame) => frame,
+        }
+    }
+
+    /// Boxes the frame, as returned by [`parse_frame`]
+    pub fn into_boxed(self) -> Box<dyn SpopFrame> {
+        match self {
+            Self::HaproxyHello(frame) => Box::new(frame),
+            Self::HaproxyDisconnect(frame) => Box::new(frame),
+            Self::Notify(frame) => Box::new(frame),
+            Self::AgentHello(frame) => Box::new(frame),
+            Self::AgentDisconnect(frame) => Box::new(frame),
+            Self::Ack(frame) => Box::new(frame),
+        }
+    }
+}
+
+impl From<ParsedFrame> for Box<dyn SpopFrame> {
+    fn from(frame: ParsedFrame) -> Self {
+        frame.into_boxed()
+    }
+}
+
+/// Parse a frame from the input byte slice, returning it as its concrete type
+///
+/// Same as [`parse_frame`], with the frame wrapped in a [`ParsedFrame`]:
+///
+/// ```
+/// use spop::{SpopFrame, frames::notify::NotifyFrame, parser::{ParsedFrame, parse_frame_owned}};
+///
+/// let bytes = NotifyFrame::new(3, 7).add_message("check-client-ip").build().serialize().unwrap();
+///
+/// match parse_frame_owned(&bytes).unwrap().1 {
+///     ParsedFrame::Notify(notify) => assert_eq!(notify.messages[0].name, "check-client-ip"),
+///     frame => panic!("Unexpected frame: {:?}", frame),
+/// }
+/// ```
+pub fn parse_frame_owned(input: &[u8]) -> IResult<&[u8], ParsedFrame, SpopError> {
+    let (remaining, (frame, _)) = parse_parsed_frame(input, &SpopParserConfig::default(), true)?;
+
+    Ok((remaining, frame))
+}
+
+/// Parse a frame as its concrete type, along with whether some of its values were truncated
+fn parse_parsed_frame<'a>(
+    input: &'a [u8],
+    config: &SpopParserConfig,
+    end_of_input: bool,
+) -> IResult<&'a [u8], (ParsedFrame, bool), SpopError> {
+    let result = decode_frame(input, config, end_of_input);
+
+    #[cfg(feature = "tracing")]
+    match &result {
+        Ok((_, (frame, truncated))) => {
+            let frame = frame.as_frame();
+            tracing::trace!(
+                frame_type = %frame.frame_type(),
+                stream_id = frame.metadata().stream_id,
+                frame_id = frame.metadata().frame_id,
+                truncated,
+                "parsed frame"
+            );
+        }
+        Err(nom::Err::Incomplete(_)) => {
+            tracing::trace!(available = input.len(), "incomplete frame");
+        }
+        Err(nom::Err::Error(e) | nom::Err::Failure(e)) => {
+            tracing::debug!(error = %e, "failed to parse frame");
+        }
+    }
+
+    result
+}
+
+/// Parses the header of a frame, leaving its payload unparsed
+///
+/// Returns the frame type, the metadata and the FRAME-LENGTH, which does not count the 4-byte
+/// length prefix. The remaining input starts at the payload and only holds its bytes already
+/// buffered, the next frame starts at `&input[4 + length as usize..]`. Only the header has to be
+/// received, which is enough to skip the NOTIFY frames of a stream without decoding their
+/// messages. The flags are checked as in [`parse_frame`], except ABORT is accepted.
+///
+/// ```
+/// use spop::{frame::FrameType, parser::parse_header};
+///
+/// let notify = [0, 0, 0, 9, 0x03, 0, 0, 0, 1, 0x05, 0x01, 0xff, 0xff];
+/// let (payload, (frame_type, metadata, length)) = parse_header(&notify).unwrap();
+/// assert_eq!(frame_type, FrameType::Notify);
+/// assert_eq!((metadata.stream_id, metadata.frame_id, length), (5, 1, 9));
+/// assert_eq!(payload, &[0xff, 0xff]);
+/// ```
+pub fn parse_header(input: &[u8]) -> IResult<&[u8], (FrameType, Metadata, u32), SpopError> {
+    let (input, frame_length) = be_u32(input)?;
+
+    // The header is read within the frame, whose bytes may not all be buffered yet
+    let frame = &input[..input.len().min(frame_length as usize)];
+    let buffered = frame.len() == frame_length as usize;
+
+    match decode_header(frame) {
+        Ok((payload, (frame_type, metadata))) => {
+            Ok((payload, (frame_type, metadata, frame_length)))
+        }
+        // A header cut short is only malformed once the whole frame is there
+        Err(Err::Incomplete(_) | Err::Error(SpopError::Parse(ErrorKind::Eof))) if !buffered => {
+            Err(Err::Incomplete(nom::Needed::Unknown))
+        }
+        Err(Err::Incomplete(_)) => Err(Err::Error(SpopError::Parse(ErrorKind::Eof))),
+        Err(e) => Err(e),
+    }
+}
+
+/// Decodes the type and the metadata of a frame for [`parse_header`]
+fn decode_header(frame: &[u8]) -> IResult<&[u8], (FrameType, Metadata), SpopError> {
+    let (frame, frame_type_byte) = be_u8(frame)?;
+    let frame_type = FrameType::from_u8(frame_type_byte)
+        .map_err(|_| Err::Error(SpopError::UnknownFrameType(frame_type_byte)))?;
+
+    let (frame, flags_value) = be_u32(frame)?;
+    let flags = FrameFlags::from_u32(flags_value).map_err(|kind| match kind {
+        ErrorKind::Verify => Err::Error(SpopError::FragmentationUnsupported),
+        _ => Err::Error(SpopError::InvalidFlags(flags_value)),
+    })?;
+
+    let (frame, stream_id) = decode_varint(frame).map_err(Err::convert)?;
+    let (frame, frame_id) = decode_varint(frame).map_err(Err::convert)?;
+
+    Ok((
+        frame,
+        (
+            frame_type,
+            Metadata {
+                flags,
+                stream_id,
+                frame_id,
+            },
+        ),
+    ))
+}
+
+/// Decodes the frame for [`parse_parsed_frame`]
+fn decode_frame<'a>(
+    input: &'a [u8],
+    config: &SpopParserConfig,
+    end_of_input: bool,
+) -> IResult<&'a [u8], (ParsedFrame, bool), SpopError> {
     // Exchange between HAProxy and agents are made using FRAME packets. All frames must be
     // prefixed with their size encoded on 4 bytes in network byte order:
     // <FRAME-LENGTH:4 bytes> <FRAME>
     //
+    // Both reads are streaming: a short buffer yields `Incomplete`, with the number of missing
+    // bytes, so a frame split across reads is decoded once it is complete.
     let (input, frame_length) = be_u32(input)?;
 
-    // check if the input buffer is complete
-    if input.len() < frame_length as usize {
-        return Err(nom::Err::Incomplete(nom::Needed::Unknown));
-    }
-
     // Extract only frame body
-    let (remaining, frame) = take(frame_length)(input)?;
+    let (remaining, frame) = nom::bytes::streaming::take(frame_length)(input)?;
 
-    // // check if the frame length is correct
-    // if frame.len() != frame_length as usize {
-    //     return Err(nom::Err::Error(Error::new(input, ErrorKind::Eof)));
-    // }
+    // The whole frame is there, a field running past its end is a malformed frame and must not
+    // be reported as `Incomplete`, more bytes would not help
+    decode_frame_body(frame, remaining, config, end_of_input).map_err(|e| match e {
+        Err::Incomplete(_) => Err::Error(SpopError::Parse(ErrorKind::Eof)),
+        e => e,
+    })
+}
+
+/// Decodes the body of a frame, `remaining` being the input following it
+fn decode_frame_body<'a>(
+    frame: &[u8],
+    remaining: &'a [u8],
+    config: &SpopParserConfig,
+    end_of_input: bool,
+) -> IResult<&'a [u8], (ParsedFrame, bool), SpopError> {
+    let limits = &config.limits;
 
     //A frame always starts with its type, on one byte, followed by metadata containing flags, on 4
     //bytes and a two variable-length integer representing the stream identifier and the frame
@@ -47,21 +370,42 @@ pub fn parse_frame(input: &[u8]) -> IResult<&[u8], Box<dyn SpopFrame>> {
 
     // Convert the byte to a FrameType
     let frame_type = FrameType::from_u8(frame_type_byte)
-        .map_err(|_| Err::Error(Error::new(input, ErrorKind::Alt)))?;
+        .map_err(|_| Err::Error(SpopError::UnknownFrameType(frame_type_byte)))?;
 
     // METADATA    : <FLAGS:4 bytes> <STREAM-ID:varint> <FRAME-ID:varint>
     let (frame, flags_value) = be_u32(frame)?; // Read 4-byte flags
 
-    // Convert the flags to a FrameFlags
-    let flags = FrameFlags::from_u32(flags_value)
-        .map_err(|_| Err::Error(Error::new(input, ErrorKind::Alt)))?;
+    // Treat a trailing frame without FIN as the implicit final fragment, only when nothing can
+    // follow it
+    let flags_value = if limits.tolerate_missing_fin && end_of_input && remaining.is_empty() {
+        flags_value | 0x00000001
+    } else {
+        flags_value
+    };
+
+    // Convert the flags to a FrameFlags, a frame without FIN is a fragment
+    let flags = FrameFlags::from_u32(flags_value).map_err(|kind| match kind {
+        ErrorKind::Verify => Err::Error(SpopError::FragmentationUnsupported),
+        _ => Err::Error(SpopError::InvalidFlags(flags_value)),
+    })?;
 
-    if flags.is_abort() {
-        return Err(nom::Err::Failure(Error::new(input, ErrorKind::Verify)));
+    if flags.is_abort() && !config.surface_abort {
+        return Err(nom::Err::Failure(SpopError::UnexpectedAbort));
     }
 
-    let (frame, stream_id) = decode_varint(frame)?;
-    let (frame, frame_id) = decode_varint(frame)?;
+    let (frame, stream_id) = decode_varint(frame).map_err(Err::convert)?;
+    let (frame, frame_id) = decode_varint(frame).map_err(Err::convert)?;
+
+    // NOTIFY and ACK frames are attached to a stream, so STREAM-ID and FRAME-ID must be set
+    if config.strict
+        && matches!(frame_type, FrameType::Notify | FrameType::Ack)
+        && (stream_id == 0 || frame_id == 0)
+    {
+        return Err(nom::Err::Failure(SpopError::InvalidMetadata {
+            stream_id,
+            frame_id,
+        }));
+    }
 
     // Create the metadata structure
     let metadata = Metadata {
@@ -95,20 +439,36 @@ pub fn parse_frame(input: &[u8]) -> IResult<&[u8], Box<dyn SpopFrame>> {
         //
         // The payload of this frame is a KV-LIST. STREAM-ID and FRAME-ID are must be set 0.
         FrameType::HaproxyHello => {
-            let mut parser = all_consuming(parse_key_value_pairs);
-
-            let (_, payload) = parser.parse(frame_payload)?;
+            let mut truncated = false;
+            let (_, payload) =
+                all_consuming(|input| parse_key_value_pairs(input, config, &mut truncated))
+                    .parse(frame_payload)?;
 
             // check mandatory items
-            let hello = HaproxyHello::try_from(payload)
-                .map_err(|_| nom::Err::Error(Error::new(input, ErrorKind::Tag)))?;
+            let hello = HaproxyHello::try_from(payload).map_err(Err::Error)?;
 
             let frame = HaproxyHelloFrame {
                 metadata,
                 payload: hello,
             };
 
-            Ok((remaining, Box::new(frame)))
+            Ok((remaining, (ParsedFrame::HaproxyHello(frame), truncated)))
+        }
+
+        // 3.2.5. Frame: AGENT-HELLO
+        // This frame is sent in reply to a HAPROXY-HELLO frame to finish a HELLO handshake.
+        //
+        // The payload of this frame is a KV-LIST. STREAM-ID and FRAME-ID are also set 0.
+        FrameType::AgentHello => {
+            let mut truncated = false;
+            let (_, payload) =
+                all_consuming(|input| parse_key_value_pairs(input, config, &mut truncated))
+                    .parse(frame_payload)?;
+
+            // check mandatory items
+            let hello = AgentHello::try_from(payload).map_err(Err::Error)?;
+
+            Ok((remaining, (ParsedFrame::AgentHello(hello), truncated)))
         }
 
         // 3.2.8. Frame: HAPROXY-DISCONNECT
@@ -118,20 +478,44 @@ pub fn parse_frame(input: &[u8]) -> IResult<&[u8], Box<dyn SpopFrame>> {
         //
         // The payload of this frame is a KV-LIST. STREAM-ID and FRAME-ID are must be set 0.
         FrameType::HaproxyDisconnect => {
-            let mut parser = all_consuming(parse_key_value_pairs);
-
-            let (_, payload) = parser.parse(frame_payload)?;
+            let mut truncated = false;
+            let (_, payload) =
+                all_consuming(|input| parse_key_value_pairs(input, config, &mut truncated))
+                    .parse(frame_payload)?;
 
             // check mandatory items
-            let disconnect = HaproxyDisconnect::try_from(payload)
-                .map_err(|_| nom::Err::Error(Error::new(input, ErrorKind::Tag)))?;
+            let disconnect = HaproxyDisconnect::try_from(payload).map_err(Err::Error)?;
 
             let frame = HaproxyDisconnectFrame {
                 metadata,
                 payload: disconnect,
             };
 
-            Ok((remaining, Box::new(frame)))
+            Ok((
+                remaining,
+                (ParsedFrame::HaproxyDisconnect(frame), truncated),
+            ))
+        }
+
+        // 3.2.9. Frame: AGENT-DISCONNECT
+        // If an error occurs, at anytime, from the agent size, a AGENT-DISCONNECT frame is sent,
+        // with information describing the error. such frame is also sent in reply to a
+        // HAPROXY-DISCONNECT.
+        //
+        // The payload of this frame is a KV-LIST. STREAM-ID and FRAME-ID are must be set 0.
+        FrameType::AgentDisconnect => {
+            let mut truncated = false;
+            let (_, payload) =
+                all_consuming(|input| parse_key_value_pairs(input, config, &mut truncated))
+                    .parse(frame_payload)?;
+
+            // check mandatory items
+            let disconnect = AgentDisconnect::try_from(payload).map_err(Err::Error)?;
+
+            Ok((
+                remaining,
+                (ParsedFrame::AgentDisconnect(disconnect), truncated),
+            ))
         }
 
         // 3.2.6. Frame: NOTIFY
@@ -140,112 +524,291 @@ pub fn parse_frame(input: &[u8]) -> IResult<&[u8], Box<dyn SpopFrame>> {
         //
         // The payload of NOTIFY frames is a LIST-OF-MESSAGES.
         FrameType::Notify => {
-            let mut parser = all_consuming(parse_list_of_messages);
-
-            let (_, messages) = parser.parse(frame_payload)?;
+            let mut truncated = false;
+            let (_, messages) =
+                all_consuming(|input| parse_list_of_messages(input, config, &mut truncated))
+                    .parse(frame_payload)?;
 
             let frame = NotifyFrame { metadata, messages };
 
-            Ok((remaining, Box::new(frame)))
+            Ok((remaining, (ParsedFrame::Notify(frame), truncated)))
+        }
+
+        // 3.2.7. Frame: ACK
+        // ACK frames must be sent by agents to reply to NOTIFY frames. STREAM-ID and FRAME-ID
+        // found in a NOTIFY frame must be reuse in the corresponding ACK frame.
+        //
+        // The payload of ACK frames is a LIST-OF-ACTIONS.
+        FrameType::Ack => {
+            let mut truncated = false;
+            let (_, actions) = all_consuming(many0(complete(|input| {
+                parse_action_with_config(input, config, &mut truncated)
+            })))
+            .parse(frame_payload)?;
+
+            let frame = Ack {
+                flags: metadata.flags,
+                stream_id: metadata.stream_id,
+                frame_id: metadata.frame_id,
+                actions,
+            };
+
+            Ok((remaining, (ParsedFrame::Ack(frame), truncated)))
+        }
+    }
+}
+
+/// A parsed frame along with the raw bytes it was decoded from
+pub type RawFrame<'a> = (Box<dyn SpopFrame>, &'a [u8]);
+
+/// Parse a frame from the input byte slice, also returning the raw bytes it was decoded from
+///
+/// The returned slice is the exact frame as received, including the 4-byte FRAME-LENGTH prefix.
+/// Re-serializing a parsed frame does not guarantee the same bytes (KV-LIST ordering is not
+/// preserved), so use this when the original bytes are needed, e.g. to verify a signature.
+pub fn parse_frame_with_raw(input: &[u8]) -> IResult<&[u8], RawFrame<'_>, SpopError> {
+    let (remaining, frame) = parse_frame(input)?;
+
+    let raw = &input[..input.len() - remaining.len()];
+
+    Ok((remaining, (frame, raw)))
+}
+
+/// Parse every frame of the input, see [`Frames`]
+///
+/// ```
+/// use spop::{SpopFrame, build::agent, parser::parse_frames};
+///
+/// let mut input = agent::ack(1, 1).serialize().unwrap();
+/// input.extend(agent::ack(1, 2).serialize().unwrap());
+///
+/// let frames: Vec<_> = parse_frames(&input).collect::<Result<_, _>>().unwrap();
+/// assert_eq!(frames.len(), 2);
+/// ```
+pub fn parse_frames(input: &[u8]) -> Frames<'_> {
+    Frames { input, done: false }
+}
+
+/// Iterator over the frames of a byte slice, returned by [`parse_frames`]
+///
+/// Frames are yielded until the input is exhausted. A trailing partial frame is reported as a
+/// last `Err(SpopError::Parse(ErrorKind::Complete))`, [`Frames::remaining`] then returns its
+/// bytes so the caller can keep them and wait for more data. The iteration also stops after
+/// any other error.
+#[derive(Debug)]
+pub struct Frames<'a> {
+    input: &'a [u8],
+    done: bool,
+}
+
+impl<'a> Frames<'a> {
+    /// Returns the bytes not consumed yet, starting with the frame that failed, if any
+    pub const fn remaining(&self) -> &'a [u8] {
+        self.input
+    }
+}
+
+impl Iterator for Frames<'_> {
+    type Item = Result<Box<dyn SpopFrame>, SpopError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done || self.input.is_empty() {
+            return None;
         }
 
-        // Unknown frames may be silently skipped or trigger an error, depending on the
-        // implementation.
-        _ => Err(nom::Err::Failure(Error::new(input, ErrorKind::NoneOf))),
+        match parse_frame(self.input) {
+            Ok((remaining, frame)) => {
+                self.input = remaining;
+                Some(Ok(frame))
+            }
+            Err(e) => {
+                self.done = true;
+                Some(Err(match e {
+                    Err::Incomplete(_) => SpopError::Parse(ErrorKind::Complete),
+                    Err::Error(e) | Err::Failure(e) => e,
+                }))
+            }
+        }
     }
 }
 
+impl core::iter::FusedIterator for Frames<'_> {}
+
 /// Parse entire KV-LIST payload
-fn parse_key_value_pairs(input: &[u8]) -> IResult<&[u8], FramePayload> {
+fn parse_key_value_pairs<'a>(
+    input: &'a [u8],
+    config: &SpopParserConfig,
+    truncated: &mut bool,
+) -> IResult<&'a [u8], FramePayload, SpopError> {
     // Create the parser combinator chain
-    let mut parser = all_consuming(many0(complete(parse_key_value_pair)));
+    let mut parser = all_consuming(many0(complete(|input| {
+        parse_key_value_pair(input, config, truncated)
+    })));
 
     // Execute the parser with the input
     let (input, pairs) = parser.parse(input)?;
 
-    let mut map = HashMap::new();
+    let map = collect_kv_list(pairs, config.duplicate_keys)?;
+
+    Ok((input, FramePayload::KVList(map)))
+}
+
+/// Builds a KV-LIST map, handling duplicate keys according to the policy
+fn collect_kv_list(
+    pairs: Vec<(String, TypedData)>,
+    policy: DuplicateKeyPolicy,
+) -> Result<HashMap<String, TypedData>, nom::Err<SpopError>> {
+    let mut map = HashMap::with_capacity(pairs.len());
 
-    // handle duplicate keys
     for (key, value) in pairs {
-        if map.contains_key(&key) {
-            return Err(nom::Err::Failure(Error::new(input, ErrorKind::Tag)));
+        match policy {
+            DuplicateKeyPolicy::RejectDuplicates if map.contains_key(&key) => {
+                return Err(nom::Err::Failure(SpopError::DuplicateKey(key)));
+            }
+            DuplicateKeyPolicy::FirstWins => {
+                map.entry(key).or_insert(value);
+            }
+            _ => {
+                map.insert(key, value);
+            }
         }
-        map.insert(key, value);
     }
 
-    Ok((input, FramePayload::KVList(map)))
+    Ok(map)
 }
 
 /// Parse a key-value pair (used in KV-LIST)
 /// A KV-LIST is a list of key/value pairs. Each pair is made of:
 /// - a name (STRING)
-/// - a value (TYPED-DATA)
-fn parse_key_value_pair(input: &[u8]) -> IResult<&[u8], (String, TypedData)> {
+/// - a value (TYPED-DATA), see [`parse_value`]
+fn parse_key_value_pair<'a>(
+    input: &'a [u8],
+    config: &SpopParserConfig,
+    truncated: &mut bool,
+) -> IResult<&'a [u8], (String, TypedData), SpopError> {
     // KV-NAME is a <STRING> (varint length + bytes)
     let (input, key) = parse_string(input)?;
 
-    // Ensure we have at least 1 byte left for the type
+    // Ensure we have at least 1 byte left for the type, a failure so that many0 reports the key
     if input.is_empty() {
-        return Err(nom::Err::Error(Error::new(input, ErrorKind::Eof)));
+        return Err(nom::Err::Failure(SpopError::TruncatedValue { key }));
     }
 
     // KV-VALUE is a <TYPED-DATA>
-    let (input, value) = typed_data(input)?;
+    let (input, value) = parse_value(input, config, truncated)?;
 
     Ok((input, (key, value)))
 }
 
+/// Parse a TYPED-DATA value of a KV-LIST or of an action, applying the checks of `config`
+///
+/// `truncated` is set when the value was cut to [`ParseLimits::truncate_values`].
+pub(crate) fn parse_value<'a>(
+    input: &'a [u8],
+    config: &SpopParserConfig,
+    truncated: &mut bool,
+) -> IResult<&'a [u8], TypedData, SpopError> {
+    if let Some(max) = config.limits.max_value_len
+        && let Some(length) = declared_value_len(input)
+        && length > max as u64
+    {
+        // a failure, so that lists report it instead of stopping silently
+        return Err(nom::Err::Failure(SpopError::ValueTooLarge { length, max }));
+    }
+
+    if let Some(type_id) = reserved_type_id(input) {
+        return Err(nom::Err::Failure(SpopError::ReservedType(type_id)));
+    }
+
+    if config.utf8 == Utf8Policy::Strict
+        && let Ok((_, TypedDataRef::String(bytes))) = typed_data_ref(input)
+        && core::str::from_utf8(bytes).is_err()
+    {
+        return Err(nom::Err::Failure(SpopError::InvalidUtf8));
+    }
+
+    let (input, value) = match config.limits.truncate_values {
+        // only the kept bytes are copied, the rest of the value is skipped
+        Some(max) if declared_value_len(input).is_some_and(|length| length > max as u64) => {
+            let (input, value) = typed_data_ref(input).map_err(Err::convert)?;
+            *truncated = true;
+            (input, value.truncated(max).into())
+        }
+        _ if config.strict => typed_data_strict(input).map_err(Err::convert)?,
+        _ => typed_data(input).map_err(Err::convert)?,
+    };
+
+    Ok((input, value))
+}
+
 /// Parse a length-prefixed string
-fn parse_string(input: &[u8]) -> IResult<&[u8], String> {
-    let (input, length) = decode_varint(input)?;
+fn parse_string(input: &[u8]) -> IResult<&[u8], String, SpopError> {
+    let (input, length) = decode_varint(input).map_err(Err::convert)?;
 
     if input.len() < length as usize {
-        return Err(nom::Err::Error(Error::new(input, ErrorKind::Eof)));
+        return Err(nom::Err::Error(SpopError::Parse(ErrorKind::Eof)));
     }
 
     let (input, bytes) = take(length)(input)?;
 
     String::from_utf8(bytes.to_vec())
         .map(|s| (input, s))
-        .map_err(|_| nom::Err::Error(nom::error::Error::new(input, nom::error::ErrorKind::Tag)))
+        // a failure, so that many0 reports it instead of stopping silently
+        .map_err(|_| nom::Err::Failure(SpopError::InvalidUtf8))
 }
 
 /// Parse entire list of messages payload
 ///
 /// LIST-OF-MESSAGES : [ <MESSAGE-NAME> <NB-ARGS:1 byte> <KV-LIST> ... ]
 /// MESSAGE-NAME     : <STRING>
-fn parse_list_of_messages(input: &[u8]) -> IResult<&[u8], Vec<Message>> {
+fn parse_list_of_messages<'a>(
+    input: &'a [u8],
+    config: &SpopParserConfig,
+    truncated: &mut bool,
+) -> IResult<&'a [u8], Vec<Message>, SpopError> {
+    many0(complete(|input| parse_message(input, config, truncated))).parse(input)
+}
+
+/// Parse a single message of a LIST-OF-MESSAGES, its KV-LIST holds NB-ARGS items
+fn parse_message<'a>(
+    input: &'a [u8],
+    config: &SpopParserConfig,
+    truncated: &mut bool,
+) -> IResult<&'a [u8], Message, SpopError> {
     let (remaining, message) = parse_string(input)?;
 
     let (remaining, nb_args_bytes) = take(1usize)(remaining)?;
 
     let nb_args = nb_args_bytes[0] as usize;
 
-    let mut parser = all_consuming(many_m_n(nb_args, nb_args, parse_key_value_pair));
+    let mut parser = many_m_n(nb_args, nb_args, |input| {
+        parse_key_value_pair(input, config, truncated)
+    });
 
     let (remaining, kv_list) = parser.parse(remaining)?;
 
-    let mut map = HashMap::new();
-
-    // handle duplicate keys
-    for (key, value) in kv_list {
-        if map.contains_key(&key) {
-            return Err(nom::Err::Failure(Error::new(input, ErrorKind::Tag)));
-        }
-        map.insert(key, value);
-    }
+    let map = collect_kv_list(kv_list, config.duplicate_keys)?;
 
     let msg = Message {
         name: message,
         args: map,
     };
 
-    Ok((remaining, vec![msg]))
+    Ok((remaining, msg))
+}
+
+/// Parse entire list of actions payload
+///
+/// LIST-OF-ACTIONS  : [ <ACTION-TYPE:1 byte> <NB-ARGS:1 byte> <ACTION-ARGS> ... ]
+pub fn parse_list_of_actions(input: &[u8]) -> IResult<&[u8], Vec<Action>, SpopError> {
+    many0(complete(parse_action)).parse(input)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::actions::VarScope;
+    use crate::frames::DisconnectStatus;
 
     #[rustfmt::skip]
     const HAPROXY_HELLO: &[u8] = &[
@@ -280,6 +843,44 @@ mod tests {
         0x11, // TYPE=BOOLEAN, true
     ];
 
+    #[test]
+    fn test_parse_header() {
+        let (payload, (frame_type, metadata, length)) =
+            parse_header(HAPROXY_HELLO).expect("Parses correctly");
+        assert_eq!(frame_type, FrameType::HaproxyHello);
+        assert!(metadata.flags.is_fin());
+        assert!(!metadata.flags.is_abort());
+        assert_eq!((metadata.stream_id, metadata.frame_id), (0, 0));
+        assert_eq!(length as usize, HAPROXY_HELLO.len() - 4);
+        assert_eq!(payload, &HAPROXY_HELLO[11..]);
+        assert_eq!(payload[0], 0x12);
+
+        // Only the header has to be buffered
+        let (payload, (_, metadata, length)) = parse_header(&HAPROXY_HELLO[..11]).unwrap();
+        assert!(payload.is_empty());
+        assert!(metadata.flags.is_fin());
+        assert_eq!(length, 78);
+
+        assert!(matches!(
+            parse_header(&HAPROXY_HELLO[..6]),
+            Err(Err::Incomplete(_))
+        ));
+        assert!(matches!(
+            parse_header(&HAPROXY_HELLO[..10]),
+            Err(Err::Incomplete(_))
+        ));
+
+        // A complete frame too short for its header is malformed
+        assert_eq!(
+            parse_header(&[0, 0, 0, 3, 0x01, 0, 0]),
+            Err(Err::Error(SpopError::Parse(ErrorKind::Eof)))
+        );
+        assert_eq!(
+            parse_header(&[0, 0, 0, 7, 0x7f, 0, 0, 0, 1, 0, 0]),
+            Err(Err::Error(SpopError::UnknownFrameType(0x7f)))
+        );
+    }
+
     #[test]
     fn test_parse_haproxy_hello() {
         let (_, frame) = parse_frame(HAPROXY_HELLO).expect("Parses correctly");
@@ -310,4 +911,927 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn test_parse_frame_with_raw() {
+        let (remaining, (frame, raw)) =
+            parse_frame_with_raw(HAPROXY_HELLO).expect("Parses correctly");
+        assert!(remaining.is_empty());
+        assert_eq!(frame.frame_type(), &FrameType::HaproxyHello);
+        assert_eq!(raw, HAPROXY_HELLO);
+
+        // trailing bytes are not part of the raw frame
+        let mut input = HAPROXY_HELLO.to_vec();
+        input.extend_from_slice(&[0x00, 0x00]);
+
+        let (remaining, (_, raw)) = parse_frame_with_raw(&input).expect("Parses correctly");
+        assert_eq!(remaining, &[0x00, 0x00]);
+        assert_eq!(raw, HAPROXY_HELLO);
+    }
+
+    /// Builds a HAPROXY-HELLO frame with the KV-LIST items serialized in the given order
+    fn haproxy_hello_with_order(items: &[(&str, TypedData)]) -> Vec<u8> {
+        let mut frame = vec![0x01, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00];
+
+        for (key, value) in items {
+            frame.extend(crate::encode_varint(key.len() as u64));
+            frame.extend_from_slice(key.as_bytes());
+            value.to_bytes(&mut frame);
+        }
+
+        let mut output = (frame.len() as u32).to_be_bytes().to_vec();
+        output.extend(frame);
+        output
+    }
+
+    #[test]
+    fn test_parse_haproxy_hello_key_order() {
+        let items = [
+            (
+                "supported-versions",
+                TypedData::String("2.0, 1.5".to_string()),
+            ),
+            ("max-frame-size", TypedData::UInt32(16380)),
+            ("capabilities", TypedData::String("pipelining".to_string())),
+            ("healthcheck", TypedData::Bool(true)),
+            ("engine-id", TypedData::String("engine-123".to_string())),
+        ];
+
+        let (_, frame) = parse_frame(&haproxy_hello_with_order(&items)).unwrap();
+        let expected = HaproxyHello::try_from(frame.payload()).unwrap();
+
+        // optional keys before the mandatory ones
+        let mut orders = vec![[4, 3, 0, 1, 2], [3, 4, 2, 1, 0]];
+
+        // every rotation of the spec order, and of the reversed one
+        for i in 0..items.len() {
+            orders.push([0, 1, 2, 3, 4].map(|j| (j + i) % items.len()));
+            orders.push([4, 3, 2, 1, 0].map(|j| (j + i) % items.len()));
+        }
+
+        for order in orders {
+            let shuffled = order.map(|i| items[i].clone());
+            let input = haproxy_hello_with_order(&shuffled);
+
+            let (rest, frame) = parse_frame(&input)
+                .unwrap_or_else(|e| panic!("Failed to parse order {:?}: {:?}", order, e));
+            assert!(rest.is_empty());
+
+            let hello = HaproxyHello::try_from(frame.payload()).unwrap();
+            assert_eq!(hello, expected, "Different decoding for order {:?}", order);
+        }
+    }
+
+    #[test]
+    fn test_parse_frame_value_too_large() {
+        use crate::varint::encode_varint;
+
+        // a NOTIFY of a few bytes declaring a 2MB string argument
+        let declared = 2 * 1024 * 1024;
+        #[rustfmt::skip]
+        let mut payload = vec![
+            0x03,                   // FRAME-TYPE = NOTIFY
+            0x00, 0x00, 0x00, 0x01, // FLAGS = FIN
+            0x01, 0x01,             // STREAM-ID, FRAME-ID
+            0x03, b'm', b's', b'g', // MESSAGE-NAME
+            0x01,                   // NB-ARGS
+            0x04, b'b', b'o', b'd', b'y',
+            0x08,                   // TYPE=STRING
+        ];
+        payload.extend_from_slice(&encode_varint(declared));
+        payload.extend_from_slice(b"not 2MB");
+        let mut input = (payload.len() as u32).to_be_bytes().to_vec();
+        input.extend_from_slice(&payload);
+
+        let limits = ParseLimits {
+            max_value_len: Some(64 * 1024),
+            ..ParseLimits::default()
+        };
+        assert_eq!(
+            parse_frame_with_limits(&input, &limits).err(),
+            Some(nom::Err::Failure(SpopError::ValueTooLarge {
+                length: declared,
+                max: 64 * 1024,
+            }))
+        );
+
+        // the SET-VAR values of an ACK are checked as well
+        #[rustfmt::skip]
+        let mut payload = vec![
+            0x67,                   // FRAME-TYPE = ACK
+            0x00, 0x00, 0x00, 0x01, // FLAGS = FIN
+            0x01, 0x01,             // STREAM-ID, FRAME-ID
+            0x01, 0x03,             // ACTION-TYPE = SET-VAR, NB-ARGS
+            0x02,                   // VAR-SCOPE = TRANSACTION
+            0x04, b'b', b'o', b'd', b'y',
+            0x09,                   // TYPE=BINARY
+        ];
+        payload.extend_from_slice(&encode_varint(declared));
+        payload.extend_from_slice(b"not 2MB");
+        let mut input = (payload.len() as u32).to_be_bytes().to_vec();
+        input.extend_from_slice(&payload);
+
+        assert_eq!(
+            parse_frame_with_limits(&input, &limits).err(),
+            Some(nom::Err::Failure(SpopError::ValueTooLarge {
+                length: declared,
+                max: 64 * 1024,
+            }))
+        );
+
+        // values under the limit are parsed as usual, in any KV-LIST
+        let limits = ParseLimits {
+            max_value_len: Some(16),
+            ..ParseLimits::default()
+        };
+        let (_, (frame, _)) = parse_frame_with_limits(HAPROXY_HELLO, &limits).unwrap();
+        assert_eq!(*frame.frame_type(), FrameType::HaproxyHello);
+    }
+
+    #[test]
+    fn test_parse_frame_with_truncated_values() {
+        let notify = NotifyFrame {
+            metadata: Metadata {
+                flags: FrameFlags::new(true, false),
+                stream_id: 1,
+                frame_id: 1,
+            },
+            messages: vec![Message {
+                name: "log-request".to_string(),
+                args: HashMap::from([
+                    ("body".to_string(), TypedData::String("a".repeat(10 * 1024))),
+                    ("method".to_string(), TypedData::String("POST".to_string())),
+                ]),
+            }],
+        };
+        let input = notify.serialize().unwrap();
+
+        let limits = ParseLimits {
+            truncate_values: Some(1024),
+            ..ParseLimits::default()
+        };
+        let (rest, (frame, truncated)) = parse_frame_with_limits(&input, &limits).unwrap();
+        assert!(rest.is_empty());
+        assert!(truncated);
+
+        let args = match frame.payload() {
+            FramePayload::ListOfMessages(messages) => messages[0].args.clone(),
+            _ => panic!("Wrong type of payload"),
+        };
+        assert_eq!(args["body"], TypedData::String("a".repeat(1024)));
+        assert_eq!(args["method"], TypedData::String("POST".to_string()));
+
+        // nothing to truncate
+        let (_, (_, truncated)) = parse_frame_with_limits(
+            &input,
+            &ParseLimits {
+                truncate_values: Some(16 * 1024),
+                ..ParseLimits::default()
+            },
+        )
+        .unwrap();
+        assert!(!truncated);
+
+        // KV-LIST values too, "é" is 2 bytes long and isn't split
+        let disconnect = HaproxyDisconnect::new(DisconnectStatus::Normal, &"é".repeat(8));
+        let kv_list = disconnect.into_frame(0, 0).serialize().unwrap();
+        let limits = ParseLimits {
+            truncate_values: Some(5),
+            ..ParseLimits::default()
+        };
+        let (_, (frame, truncated)) = parse_frame_with_limits(&kv_list, &limits).unwrap();
+        assert!(truncated);
+        match frame.payload() {
+            FramePayload::KVList(kv_list) => {
+                assert_eq!(kv_list["message"], TypedData::String("éé".to_string()));
+            }
+            _ => panic!("Wrong type of payload"),
+        }
+
+        // no limit by default
+        let (_, frame) = parse_frame(&input).unwrap();
+        match frame.payload() {
+            FramePayload::ListOfMessages(messages) => {
+                assert_eq!(
+                    messages[0].args["body"],
+                    TypedData::String("a".repeat(10 * 1024))
+                );
+            }
+            _ => panic!("Wrong type of payload"),
+        }
+    }
+
+    #[test]
+    fn test_parse_frame_tolerate_missing_fin() {
+        // HAPROXY-DISCONNECT without FIN
+        let frame = haproxy_disconnect_frame(0x00000000);
+
+        let strict = ParseLimits::default();
+        assert!(parse_frame_with_limits(&frame, &strict).is_err());
+
+        let tolerant = ParseLimits {
+            tolerate_missing_fin: true,
+            ..ParseLimits::default()
+        };
+        let (rest, (parsed, _)) = parse_frame_with_limits(&frame, &tolerant).unwrap();
+        assert!(rest.is_empty());
+        assert_eq!(parsed.frame_type(), &FrameType::HaproxyDisconnect);
+        assert!(parsed.metadata().flags.is_fin());
+
+        // still rejected when another frame follows
+        let mut input = frame.clone();
+        input.extend(haproxy_disconnect_frame(0x00000001));
+        assert!(parse_frame_with_limits(&input, &tolerant).is_err());
+
+        // only FIN is tolerated, not reserved bits
+        let frame = haproxy_disconnect_frame(0x00000004);
+        assert!(parse_frame_with_limits(&frame, &tolerant).is_err());
+    }
+
+    #[test]
+    fn test_parse_frame_incomplete() {
+        let frame = Ack::new(1, 1).serialize().unwrap();
+
+        // the missing bytes are known once FRAME-LENGTH is read
+        assert_eq!(
+            parse_frame(&frame[..2]).unwrap_err(),
+            nom::Err::Incomplete(nom::Needed::new(2))
+        );
+        assert_eq!(
+            parse_frame(&frame[..frame.len() - 1]).unwrap_err(),
+            nom::Err::Incomplete(nom::Needed::new(1))
+        );
+
+        // FRAME-LENGTH = 3, the body ends before STREAM-ID
+        assert_eq!(
+            parse_frame(&[0x00, 0x00, 0x00, 0x03, 0x67, 0x00, 0x00]).unwrap_err(),
+            nom::Err::Error(SpopError::Parse(ErrorKind::Eof))
+        );
+    }
+
+    #[test]
+    fn test_parse_frame_fragmented() {
+        // first fragment of a NOTIFY, FIN not set
+        let mut notify = NotifyFrame::new(3, 7)
+            .add_message("check-client-ip")
+            .arg("ip", TypedData::IPv4(std::net::Ipv4Addr::LOCALHOST))
+            .build();
+        notify.metadata.flags = FrameFlags::from_bits_retain(0x00000000);
+        let frame = notify.serialize().unwrap();
+
+        assert_eq!(
+            parse_frame(&frame).unwrap_err(),
+            nom::Err::Error(SpopError::FragmentationUnsupported)
+        );
+
+        // reported before the reserved bits
+        let frame = haproxy_disconnect_frame(0x00000004);
+        assert_eq!(
+            parse_frame(&frame).unwrap_err(),
+            nom::Err::Error(SpopError::FragmentationUnsupported)
+        );
+    }
+
+    /// Builds an empty HAPROXY-DISCONNECT frame with the given raw flags
+    fn haproxy_disconnect_frame(flags: u32) -> Vec<u8> {
+        let mut frame = vec![FrameType::HaproxyDisconnect.to_u8()];
+        frame.extend_from_slice(&flags.to_be_bytes());
+        frame.extend_from_slice(&[0x00, 0x00]);
+
+        for (key, value) in [
+            ("status-code", TypedData::UInt32(0)),
+            ("message", TypedData::String("normal".to_string())),
+        ] {
+            frame.extend(crate::encode_varint(key.len() as u64));
+            frame.extend_from_slice(key.as_bytes());
+            value.to_bytes(&mut frame);
+        }
+
+        let mut output = (frame.len() as u32).to_be_bytes().to_vec();
+        output.extend(frame);
+        output
+    }
+
+    #[test]
+    fn test_parse_ack() {
+        #[rustfmt::skip]
+        let input = [
+            0x00, 0x00, 0x00, 0x1c, // FRAME-LENGTH = 28 bytes
+            0x67,                   // FRAME-TYPE = ACK
+            0x00, 0x00, 0x00, 0x01, // FLAGS = FIN
+            0x03,                   // STREAM-ID = 3
+            0x07,                   // FRAME-ID = 7
+            // SET-VAR, NB-ARGS = 3, SESSION
+            0x01, 0x03, 0x01,
+                0x08, b'i', b'p', b'_', b's', b'c', b'o', b'r', b'e',
+                0x03, 0x2a, // TYPE=UINT32, 42
+            // UNSET-VAR, NB-ARGS = 2, TRANSACTION
+            0x02, 0x02, 0x02,
+                0x03, b'f', b'o', b'o',
+        ];
+
+        let (rest, frame) = parse_frame(&input).unwrap();
+        assert!(rest.is_empty());
+        assert_eq!(frame.frame_type(), &FrameType::Ack);
+
+        let ack = Ack::try_from((frame.metadata(), frame.payload())).unwrap();
+        assert_eq!(
+            ack,
+            Ack::new(3, 7)
+                .set_var(VarScope::Session, "ip_score", TypedData::UInt32(42))
+                .unset_var(VarScope::Transaction, "foo")
+        );
+
+        // unknown scope, the list is rejected instead of stopping before the action
+        assert_eq!(
+            parse_list_of_actions(&[0x02, 0x02, 0x05, 0x01, b'a']).unwrap_err(),
+            nom::Err::Failure(SpopError::Parse(ErrorKind::Alt))
+        );
+    }
+
+    /// Wraps a frame body with its FRAME-LENGTH prefix
+    fn with_length(frame: &[u8]) -> Vec<u8> {
+        let mut output = (frame.len() as u32).to_be_bytes().to_vec();
+        output.extend_from_slice(frame);
+        output
+    }
+
+    #[test]
+    fn test_parse_frame_errors() {
+        let error = |input: &[u8]| match parse_frame(input) {
+            Err(nom::Err::Error(e) | nom::Err::Failure(e)) => e,
+            other => panic!("Unexpected result: {:?}", other.map(|(_, f)| f.summary())),
+        };
+
+        // FIN, STREAM-ID = 0, FRAME-ID = 0
+        let metadata = [0x00, 0x00, 0x00, 0x01, 0x00, 0x00];
+
+        let frame = with_length(&[&[0x2a][..], &metadata].concat());
+        assert_eq!(error(&frame), SpopError::UnknownFrameType(0x2a));
+
+        let frame = with_length(&[0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]);
+        assert_eq!(error(&frame), SpopError::FragmentationUnsupported);
+
+        let frame = with_length(&[0x01, 0x00, 0x00, 0x00, 0x05, 0x00, 0x00]);
+        assert_eq!(error(&frame), SpopError::InvalidFlags(0x05));
+
+        let frame = with_length(&[0x01, 0x00, 0x00, 0x00, 0x03, 0x00, 0x00]);
+        assert_eq!(error(&frame), SpopError::UnexpectedAbort);
+
+        // truncated STREAM-ID varint
+        let frame = with_length(&[0x01, 0x00, 0x00, 0x00, 0x01, 0xf0]);
+        assert_eq!(error(&frame), SpopError::Parse(ErrorKind::Eof));
+
+        let hello = haproxy_hello_with_order(&[
+            ("max-frame-size", TypedData::UInt32(16380)),
+            ("max-frame-size", TypedData::UInt32(16380)),
+        ]);
+        assert_eq!(
+            error(&hello),
+            SpopError::DuplicateKey("max-frame-size".to_string())
+        );
+
+        let hello = haproxy_hello_with_order(&[("max-frame-size", TypedData::UInt32(16380))]);
+        assert_eq!(error(&hello), SpopError::MissingField("supported-versions"));
+
+        // KV-NAME is not valid UTF-8
+        let hello = with_length(&[&[0x01][..], &metadata, &[0x01, 0xff, 0x00]].concat());
+        assert_eq!(error(&hello), SpopError::InvalidUtf8);
+    }
+
+    #[test]
+    fn test_parse_frame_duplicate_keys() {
+        let items = [
+            ("status-code", TypedData::UInt32(1)),
+            ("message", TypedData::String("first".to_string())),
+            ("message", TypedData::String("last".to_string())),
+        ];
+        let mut frame = vec![FrameType::HaproxyDisconnect.to_u8(), 0x00, 0x00, 0x00, 0x01];
+        frame.extend_from_slice(&[0x00, 0x00]);
+        for (key, value) in &items {
+            frame.extend(crate::encode_varint(key.len() as u64));
+            frame.extend_from_slice(key.as_bytes());
+            value.to_bytes(&mut frame);
+        }
+        let input = with_length(&frame);
+
+        let message = |policy| {
+            let config = SpopParserConfig {
+                duplicate_keys: policy,
+                ..SpopParserConfig::default()
+            };
+            parse_frame_with_config(&input, &config).map(|(_, (frame, _))| {
+                HaproxyDisconnect::try_from(frame.payload())
+                    .unwrap()
+                    .message
+            })
+        };
+
+        assert_eq!(
+            message(DuplicateKeyPolicy::RejectDuplicates).unwrap_err(),
+            nom::Err::Failure(SpopError::DuplicateKey("message".to_string()))
+        );
+        assert_eq!(message(DuplicateKeyPolicy::FirstWins).unwrap(), "first");
+        assert_eq!(message(DuplicateKeyPolicy::LastWins).unwrap(), "last");
+
+        // rejected by default
+        assert!(parse_frame(&input).is_err());
+    }
+
+    #[test]
+    fn test_parse_frame_utf8_policy() {
+        // HAPROXY-DISCONNECT, "message" is not valid UTF-8
+        let mut frame = vec![FrameType::HaproxyDisconnect.to_u8(), 0x00, 0x00, 0x00, 0x01];
+        frame.extend_from_slice(&[0x00, 0x00]);
+        frame.push(0x0b);
+        frame.extend_from_slice(b"status-code");
+        TypedData::UInt32(1).to_bytes(&mut frame);
+        frame.push(0x07);
+        frame.extend_from_slice(b"message");
+        frame.extend_from_slice(&[0x08, 0x03, b'o', 0xff, b'k']);
+        let input = with_length(&frame);
+
+        let parse = |input: &[u8], utf8| {
+            let config = SpopParserConfig {
+                utf8,
+                ..SpopParserConfig::default()
+            };
+            parse_frame_with_config(input, &config).map(|(_, (frame, _))| {
+                HaproxyDisconnect::try_from(frame.payload())
+                    .unwrap()
+                    .message
+            })
+        };
+
+        assert_eq!(parse(&input, Utf8Policy::Lossy).unwrap(), "o\u{fffd}k");
+        assert_eq!(
+            parse(&input, Utf8Policy::Strict).unwrap_err(),
+            nom::Err::Failure(SpopError::InvalidUtf8)
+        );
+        // lossy by default
+        assert!(parse_frame(&input).is_ok());
+
+        // the same bytes in a key are rejected by both policies
+        let mut frame = vec![FrameType::HaproxyDisconnect.to_u8(), 0x00, 0x00, 0x00, 0x01];
+        frame.extend_from_slice(&[0x00, 0x00]);
+        frame.extend_from_slice(&[0x03, b'o', 0xff, b'k']);
+        TypedData::UInt32(1).to_bytes(&mut frame);
+        let input = with_length(&frame);
+
+        for policy in [Utf8Policy::Lossy, Utf8Policy::Strict] {
+            assert_eq!(
+                parse(&input, policy).unwrap_err(),
+                nom::Err::Failure(SpopError::InvalidUtf8)
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_frame_strict_types() {
+        let hello = |healthcheck: u8| {
+            let mut frame = haproxy_hello_with_order(&[
+                ("supported-versions", TypedData::String("2.0".to_string())),
+                ("max-frame-size", TypedData::UInt32(16380)),
+                ("capabilities", TypedData::String("pipelining".to_string())),
+            ])
+            .split_off(4);
+            frame.push(0x0b);
+            frame.extend_from_slice(b"healthcheck");
+            frame.push(healthcheck);
+            with_length(&frame)
+        };
+        let strict = SpopParserConfig {
+            strict: true,
+            ..SpopParserConfig::default()
+        };
+        let healthcheck = |input: &[u8], config: &SpopParserConfig| {
+            parse_frame_with_config(input, config)
+                .map(|(_, (frame, _))| HaproxyHello::try_from(frame.payload()).unwrap().healthcheck)
+        };
+
+        for (byte, expected) in [(0x01, false), (0x11, true)] {
+            let input = hello(byte);
+            assert_eq!(healthcheck(&input, &strict).unwrap(), Some(expected));
+            assert_eq!(
+                healthcheck(&input, &SpopParserConfig::default()).unwrap(),
+                Some(expected)
+            );
+        }
+
+        // bit 1 of the flags is reserved, only strict parsing notices it
+        let input = hello(0x21);
+        assert_eq!(
+            healthcheck(&input, &SpopParserConfig::default()).unwrap(),
+            Some(false)
+        );
+        assert_eq!(
+            healthcheck(&input, &strict).unwrap_err(),
+            nom::Err::Failure(SpopError::Parse(ErrorKind::Verify))
+        );
+
+        // reserved type ids are always rejected
+        for type_id in 10..=15 {
+            let input = hello(type_id);
+            assert!(healthcheck(&input, &strict).is_err());
+            assert!(healthcheck(&input, &SpopParserConfig::default()).is_err());
+        }
+    }
+
+    #[test]
+    fn test_parse_frame_reserved_type() {
+        // NOTIFY with an argument of type 12, its length can't be known
+        let mut frame = vec![
+            FrameType::Notify.to_u8(),
+            0x00,
+            0x00,
+            0x00,
+            0x01,
+            0x01,
+            0x01,
+        ];
+        frame.push(0x03);
+        frame.extend_from_slice(b"msg");
+        frame.push(0x01); // NB-ARGS
+        frame.push(0x03);
+        frame.extend_from_slice(b"arg");
+        frame.extend_from_slice(&[0x0c, 0x01, 0x02]);
+        let input = with_length(&frame);
+
+        assert_eq!(
+            parse_frame(&input).unwrap_err(),
+            nom::Err::Failure(SpopError::ReservedType(12))
+        );
+
+        // flags don't hide the type id
+        let mut frame = frame.clone();
+        let len = frame.len();
+        frame[len - 3] = 0x1c;
+        assert_eq!(
+            parse_frame(&with_length(&frame)).unwrap_err(),
+            nom::Err::Failure(SpopError::ReservedType(12))
+        );
+    }
+
+    #[test]
+    fn test_parse_frame_truncated_value() {
+        // HAPROXY-HELLO whose payload ends right after the "healthcheck" key
+        let mut frame = HAPROXY_HELLO[4..HAPROXY_HELLO.len() - 1].to_vec();
+        assert_eq!(
+            parse_frame(&with_length(&frame)).unwrap_err(),
+            nom::Err::Failure(SpopError::TruncatedValue {
+                key: "healthcheck".to_string()
+            })
+        );
+
+        // NOTIFY whose single argument has no value
+        frame = vec![
+            FrameType::Notify.to_u8(),
+            0x00,
+            0x00,
+            0x00,
+            0x01,
+            0x01,
+            0x01,
+        ];
+        frame.push(0x03);
+        frame.extend_from_slice(b"msg");
+        frame.push(0x01); // NB-ARGS
+        frame.push(0x03);
+        frame.extend_from_slice(b"arg");
+        assert_eq!(
+            parse_frame(&with_length(&frame)).unwrap_err(),
+            nom::Err::Failure(SpopError::TruncatedValue {
+                key: "arg".to_string()
+            })
+        );
+    }
+
+    #[rustfmt::skip]
+    const NOTIFY_TWO_MESSAGES: &[u8] = &[
+        0x00, 0x00, 0x00, 0x39, // FRAME-LENGTH = 57 bytes
+        0x03,                   // FRAME-TYPE = NOTIFY
+        0x00, 0x00, 0x00, 0x01, // FLAGS = FIN
+        0x01,                   // STREAM-ID = 1
+        0x02,                   // FRAME-ID = 2
+        // MESSAGE-NAME = "check-client-ip"
+        0x0f, b'c', b'h', b'e', b'c', b'k', b'-', b'c', b'l', b'i', b'e', b'n', b't', b'-', b'i', b'p',
+        0x01,                   // NB-ARGS = 1
+        0x02, b'i', b'p',       // KV-NAME = "ip"
+        0x06, 10, 0, 0, 1,      // KV-VALUE = IPV4 10.0.0.1
+        // MESSAGE-NAME = "log-request"
+        0x0b, b'l', b'o', b'g', b'-', b'r', b'e', b'q', b'u', b'e', b's', b't',
+        0x01,                   // NB-ARGS = 1
+        0x06, b'm', b'e', b't', b'h', b'o', b'd', // KV-NAME = "method"
+        0x08, 0x03, b'G', b'E', b'T', // KV-VALUE = STRING "GET"
+    ];
+
+    #[test]
+    fn test_parse_notify_two_messages() {
+        let (rest, frame) = parse_frame(NOTIFY_TWO_MESSAGES).unwrap();
+        assert!(rest.is_empty());
+
+        let messages = match frame.payload() {
+            FramePayload::ListOfMessages(messages) => messages,
+            _ => panic!("Wrong type of payload"),
+        };
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0].name, "check-client-ip");
+        assert_eq!(
+            messages[0].args["ip"],
+            TypedData::IPv4(std::net::Ipv4Addr::new(10, 0, 0, 1))
+        );
+        assert_eq!(messages[1].name, "log-request");
+        assert_eq!(
+            messages[1].args["method"],
+            TypedData::String("GET".to_string())
+        );
+
+        // a truncated second message is still an error
+        let mut input = NOTIFY_TWO_MESSAGES[..NOTIFY_TWO_MESSAGES.len() - 1].to_vec();
+        input[3] -= 1;
+        assert!(parse_frame(&input).is_err());
+    }
+
+    #[test]
+    fn test_parse_frame_owned() {
+        let (rest, frame) = parse_frame_owned(NOTIFY_TWO_MESSAGES).unwrap();
+        assert!(rest.is_empty());
+
+        let notify = match &frame {
+            ParsedFrame::Notify(notify) => notify,
+            frame => panic!("Unexpected frame: {:?}", frame),
+        };
+        assert_eq!(
+            (notify.metadata.stream_id, notify.metadata.frame_id),
+            (1, 2)
+        );
+        assert_eq!(notify.messages.len(), 2);
+        assert_eq!(notify.messages[1].name, "log-request");
+
+        // same frame as the boxed API
+        let (_, boxed) = parse_frame(NOTIFY_TWO_MESSAGES).unwrap();
+        assert!(crate::testing::frames_equal(frame.as_frame(), &*boxed));
+        assert!(crate::testing::frames_equal(&*frame.into_boxed(), &*boxed));
+
+        match parse_frame_owned(HAPROXY_HELLO).unwrap().1 {
+            ParsedFrame::HaproxyHello(hello) => assert_eq!(hello.payload.max_frame_size, 16380),
+            frame => panic!("Unexpected frame: {:?}", frame),
+        }
+    }
+
+    #[test]
+    fn test_parse_ack_value_checks() {
+        let ack = |value: &[u8]| {
+            let mut payload = vec![0x67, 0x00, 0x00, 0x00, 0x01, 0x01, 0x01];
+            payload.extend_from_slice(&[0x01, 0x03, 0x02, 0x01, b'v']);
+            payload.extend_from_slice(value);
+            let mut input = (payload.len() as u32).to_be_bytes().to_vec();
+            input.extend_from_slice(&payload);
+            input
+        };
+        fn action(input: &[u8], config: &SpopParserConfig) -> Result<Action, Err<SpopError>> {
+            let (_, (frame, _)) = parse_frame_with_config(input, config)?;
+            match frame.payload() {
+                FramePayload::ListOfActions(actions) => Ok(actions[0].clone()),
+                _ => panic!("Wrong type of payload"),
+            }
+        }
+
+        // a STRING which is not UTF-8
+        let input = ack(&[0x08, 0x02, b'o', 0xff]);
+        let utf8 = SpopParserConfig {
+            utf8: Utf8Policy::Strict,
+            ..SpopParserConfig::default()
+        };
+        assert_eq!(
+            action(&input, &utf8).err(),
+            Some(nom::Err::Failure(SpopError::InvalidUtf8))
+        );
+        assert!(action(&input, &SpopParserConfig::default()).is_ok());
+
+        // a BOOLEAN with a reserved flag bit set
+        let input = ack(&[0x21]);
+        let strict = SpopParserConfig {
+            strict: true,
+            ..SpopParserConfig::default()
+        };
+        assert!(action(&input, &strict).is_err());
+        assert!(action(&input, &SpopParserConfig::default()).is_ok());
+
+        // truncated values are reported
+        let input = ack(&[0x09, 0x04, 0x01, 0x02, 0x03, 0x04]);
+        let limits = ParseLimits {
+            truncate_values: Some(2),
+            ..ParseLimits::default()
+        };
+        let (_, (frame, truncated)) = parse_frame_with_limits(&input, &limits).unwrap();
+        assert!(truncated);
+        assert_eq!(
+            frame.payload(),
+            FramePayload::ListOfActions(vec![Action::SetVar {
+                scope: VarScope::Transaction,
+                name: "v".to_string(),
+                value: TypedData::Binary(vec![0x01, 0x02]),
+            }])
+        );
+    }
+
+    #[test]
+    fn test_parse_frame_strict_zero_ids() {
+        let strict = SpopParserConfig {
+            strict: true,
+            ..SpopParserConfig::default()
+        };
+        let notify = |stream_id, frame_id| {
+            NotifyFrame::new(stream_id, frame_id)
+                .add_message("check-client-ip")
+                .build()
+                .serialize()
+                .unwrap()
+        };
+
+        for (stream_id, frame_id) in [(0, 0), (0, 1), (1, 0)] {
+            let input = notify(stream_id, frame_id);
+            assert_eq!(
+                parse_frame_with_config(&input, &strict).err(),
+                Some(nom::Err::Failure(SpopError::InvalidMetadata {
+                    stream_id,
+                    frame_id
+                }))
+            );
+
+            // accepted by default
+            let (_, frame) = parse_frame(&input).unwrap();
+            assert_eq!(frame.metadata().stream_id, stream_id);
+
+            let ack = Ack::new(stream_id, frame_id).serialize().unwrap();
+            assert!(parse_frame_with_config(&ack, &strict).is_err());
+            assert!(parse_frame(&ack).is_ok());
+        }
+
+        assert!(parse_frame_with_config(&notify(1, 1), &strict).is_ok());
+
+        // HELLO and DISCONNECT frames are not attached to a stream
+        assert!(parse_frame_with_config(HAPROXY_HELLO, &strict).is_ok());
+    }
+
+    #[test]
+    fn test_parse_frame_surface_abort() {
+        let surface = SpopParserConfig {
+            surface_abort: true,
+            ..SpopParserConfig::default()
+        };
+
+        let abort = NotifyFrame::abort(3, 7).serialize().unwrap();
+        assert_eq!(abort[4..9], [0x03, 0x00, 0x00, 0x00, 0x03]);
+
+        // rejected by default
+        assert_eq!(
+            parse_frame(&abort).unwrap_err(),
+            nom::Err::Failure(SpopError::UnexpectedAbort)
+        );
+
+        let (rest, (frame, _)) = parse_frame_with_config(&abort, &surface).unwrap();
+        assert!(rest.is_empty());
+        let metadata = frame.metadata();
+        assert!(metadata.flags.is_abort());
+        assert!(metadata.flags.is_fin());
+        assert_eq!((metadata.stream_id, metadata.frame_id), (3, 7));
+        assert!(frame.payload().is_empty());
+
+        // an aborted ACK keeps its actions
+        let mut ack = Ack::new(3, 7).set_var(VarScope::Request, "score", TypedData::UInt32(1));
+        ack.flags = FrameFlags::new(true, true);
+        let (_, (frame, _)) = parse_frame_with_config(&ack.serialize().unwrap(), &surface).unwrap();
+        assert!(frame.metadata().flags.is_abort());
+        assert_eq!(frame.payload().len(), 1);
+
+        // FIN is still mandatory
+        let frame = with_length(&[0x03, 0x00, 0x00, 0x00, 0x02, 0x03, 0x07]);
+        assert_eq!(
+            parse_frame_with_config(&frame, &surface).unwrap_err(),
+            nom::Err::Error(SpopError::FragmentationUnsupported)
+        );
+    }
+
+    #[test]
+    fn test_parse_frames() {
+        let first = Ack::new(1, 1).serialize().unwrap();
+        let second = NotifyFrame::new(1, 2)
+            .add_message("check-client-ip")
+            .arg("ip", TypedData::IPv4(std::net::Ipv4Addr::LOCALHOST))
+            .build()
+            .serialize()
+            .unwrap();
+        let third = Ack::new(1, 3).serialize().unwrap();
+
+        let input = [&first[..], &second, &third[..third.len() - 2]].concat();
+
+        let mut frames = parse_frames(&input);
+        let frame = frames.next().unwrap().unwrap();
+        assert_eq!(frame.frame_type(), &FrameType::Ack);
+        let frame = frames.next().unwrap().unwrap();
+        assert_eq!(frame.frame_type(), &FrameType::Notify);
+        assert_eq!(frame.metadata().frame_id, 2);
+
+        // the partial third frame is reported, and kept for later
+        assert_eq!(
+            frames.next().unwrap().unwrap_err(),
+            SpopError::Parse(ErrorKind::Complete)
+        );
+        assert_eq!(frames.remaining(), &third[..third.len() - 2]);
+        assert!(frames.next().is_none());
+
+        // an exhausted input ends cleanly
+        let input = [&first[..], &third].concat();
+        let frames: Vec<_> = parse_frames(&input).collect();
+        assert_eq!(frames.len(), 2);
+        assert!(frames.iter().all(Result::is_ok));
+        assert_eq!(parse_frames(&[]).count(), 0);
+    }
+
+    #[test]
+    fn test_kv_list_round_trip_all_types() {
+        let kv_list = HashMap::from([
+            ("null".to_string(), TypedData::Null),
+            ("bool".to_string(), TypedData::Bool(true)),
+            ("int32".to_string(), TypedData::Int32(-42)),
+            ("uint32".to_string(), TypedData::UInt32(42)),
+            ("int64".to_string(), TypedData::Int64(-1 << 40)),
+            ("uint64".to_string(), TypedData::UInt64(1 << 40)),
+            (
+                "ipv4".to_string(),
+                TypedData::IPv4(std::net::Ipv4Addr::new(192, 168, 0, 1)),
+            ),
+            (
+                "ipv6".to_string(),
+                TypedData::IPv6(std::net::Ipv6Addr::LOCALHOST),
+            ),
+            (
+                "string".to_string(),
+                TypedData::String("tequila".to_string()),
+            ),
+            ("binary".to_string(), TypedData::Binary(vec![0x00, 0xff])),
+        ]);
+        let payload = FramePayload::KVList(kv_list);
+
+        let mut buf = Vec::new();
+        crate::encode_payload(&payload, &mut buf, None).unwrap();
+
+        let (rest, parsed) = parse_key_value_pairs(
+            &buf,
+            &SpopParserConfig {
+                strict: true,
+                ..SpopParserConfig::default()
+            },
+            &mut false,
+        )
+        .unwrap();
+        assert!(rest.is_empty());
+        assert_eq!(parsed, payload);
+    }
+
+    #[test]
+    fn test_serialize_with_order() {
+        let frame = HaproxyHelloFrame {
+            metadata: Metadata {
+                flags: FrameFlags::new(true, false),
+                stream_id: 0,
+                frame_id: 0,
+            },
+            payload: HaproxyHello {
+                supported_versions: vec![semver::Version::new(2, 0, 0)],
+                max_frame_size: 16380,
+                capabilities: vec![],
+                healthcheck: Some(true),
+                engine_id: None,
+            },
+        };
+
+        let order = [
+            "supported-versions",
+            "max-frame-size",
+            "capabilities",
+            "healthcheck",
+        ];
+        assert_eq!(frame.serialize_with_order(&order).unwrap(), HAPROXY_HELLO);
+
+        // keys missing from the order come last, sorted
+        let serialized = frame
+            .serialize_with_order(&["supported-versions", "max-frame-size"])
+            .unwrap();
+        let expected = haproxy_hello_with_order(&[
+            ("supported-versions", TypedData::String("2.0".to_string())),
+            ("max-frame-size", TypedData::UInt32(16380)),
+            ("capabilities", TypedData::String("".to_string())),
+            ("healthcheck", TypedData::Bool(true)),
+        ]);
+        assert_eq!(serialized, expected);
+
+        // unknown keys in the order are ignored
+        let serialized = frame
+            .serialize_with_order(&["engine-id", "supported-versions", "max-frame-size"])
+            .unwrap();
+        assert_eq!(serialized, expected);
+    }
 }
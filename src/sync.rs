@@ -0,0 +1,137 @@
+//! Blocking frame I/O over `std::io`, for tools and tests without an async runtime
+//!
+//! ```
+//! use spop::{build::agent, sync::{read_frame_sync, write_frame_sync}};
+//! use std::io::Cursor;
+//!
+//! let mut buf = Vec::new();
+//! write_frame_sync(&mut buf, &agent::ack(1, 1)).unwrap();
+//!
+//! let frame = read_frame_sync(&mut Cursor::new(buf), 16380).unwrap().unwrap();
+//! assert_eq!(frame.metadata().frame_id, 1);
+//! ```
+
+use crate::{SpopError, SpopFrame, frame::MAX_FRAME_LENGTH, parser::parse_frame};
+use std::io::{self, Read, Write};
+
+/// Reads a single frame from `reader`, blocking until it is complete
+///
+/// The FRAME-LENGTH is checked against `max` before reading the frame, a longer frame fails with
+/// [`SpopError::FrameTooLarge`], as does one over [`MAX_FRAME_LENGTH`]. Returns `Ok(None)` when
+/// the stream ends on a frame boundary, a stream ending in the middle of a frame is an
+/// `UnexpectedEof` error. Parse errors are reported as `InvalidData`, with the [`SpopError`] as
+/// source.
+pub fn read_frame_sync<R: Read>(
+    reader: &mut R,
+    max: usize,
+) -> io::Result<Option<Box<dyn SpopFrame>>> {
+    let mut prefix = [0u8; 4];
+    let mut filled = 0;
+
+    while filled < prefix.len() {
+        match reader.read(&mut prefix[filled..]) {
+            Ok(0) if filled == 0 => return Ok(None),
+            Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+
+    let length = u32::from_be_bytes(prefix);
+    let max = max.min(MAX_FRAME_LENGTH as usize) as u32;
+
+    if length > max {
+        return Err(SpopError::FrameTooLarge { length, max }.into());
+    }
+
+    // read_exact loops over partial reads until the frame is buffered
+    let mut frame = vec![0; 4 + length as usize];
+    frame[..4].copy_from_slice(&prefix);
+    reader.read_exact(&mut frame[4..])?;
+
+    match parse_frame(&frame) {
+        Ok((_, frame)) => Ok(Some(frame)),
+        // the whole frame was read, parse_frame can't ask for more bytes
+        Err(nom::Err::Incomplete(_)) => Err(io::ErrorKind::UnexpectedEof.into()),
+        Err(nom::Err::Error(e) | nom::Err::Failure(e)) => Err(e.into()),
+    }
+}
+
+/// Writes a frame to `writer`, prefixed with its FRAME-LENGTH
+pub fn write_frame_sync<W: Write>(writer: &mut W, frame: &dyn SpopFrame) -> io::Result<()> {
+    writer.write_all(&frame.serialize()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        actions::VarScope,
+        frame::FrameType,
+        frames::{Ack, AgentDisconnect},
+        testing::frames_equal,
+        types::TypedData,
+    };
+    use std::io::Cursor;
+
+    /// Returns at most 3 bytes per read
+    struct Trickle<R>(R);
+
+    impl<R: Read> Read for Trickle<R> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let len = buf.len().min(3);
+            self.0.read(&mut buf[..len])
+        }
+    }
+
+    #[test]
+    fn test_sync_round_trip() {
+        let frames: Vec<Box<dyn SpopFrame>> = vec![
+            Box::new(Ack::new(1, 2).set_var(VarScope::Session, "score", TypedData::UInt32(42))),
+            Box::new(AgentDisconnect {
+                status_code: 0,
+                message: "normal".to_string(),
+            }),
+        ];
+
+        let mut cursor = Cursor::new(Vec::new());
+        for frame in &frames {
+            write_frame_sync(&mut cursor, frame.as_ref()).unwrap();
+        }
+        cursor.set_position(0);
+
+        let mut reader = Trickle(cursor);
+        for frame in &frames {
+            let read = read_frame_sync(&mut reader, 16380).unwrap().unwrap();
+            assert!(frames_equal(read.as_ref(), frame.as_ref()));
+        }
+        assert!(read_frame_sync(&mut reader, 16380).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_sync_read_errors() {
+        let frame = Ack::new(1, 2).serialize().unwrap();
+        let length = frame.len() as u32 - 4;
+
+        let err = read_frame_sync(&mut Cursor::new(&frame), length as usize - 1).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(
+            err.into_inner().unwrap().downcast_ref::<SpopError>(),
+            Some(&SpopError::FrameTooLarge {
+                length,
+                max: length - 1
+            })
+        );
+
+        for truncated in [&frame[..2], &frame[..frame.len() - 1]] {
+            let err = read_frame_sync(&mut Cursor::new(truncated), 16380).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+        }
+
+        let frame = read_frame_sync(&mut Cursor::new(&frame), 16380)
+            .unwrap()
+            .unwrap();
+        assert_eq!(frame.frame_type(), &FrameType::Ack);
+    }
+}
@@ -0,0 +1,353 @@
+//! Helpers for testing agents without sockets
+
+use crate::HashMap;
+use crate::{SpopFrame, frame::FramePayload, types::TypedData};
+use alloc::collections::BTreeSet;
+use alloc::{format, string::String, vec::Vec};
+#[cfg(feature = "std")]
+use {
+    crate::SpopCodec,
+    tokio::io::{DuplexStream, duplex},
+    tokio_util::codec::Framed,
+};
+
+/// Size of the in-memory pipe used by [`loopback`], large enough for a default HAProxy frame
+#[cfg(feature = "std")]
+const LOOPBACK_BUFFER_SIZE: usize = 64 * 1024;
+
+/// One end of an in-memory SPOP connection
+#[cfg(feature = "std")]
+pub type LoopbackFramed = Framed<DuplexStream, SpopCodec>;
+
+/// Returns a pair of framed streams connected in memory, `(haproxy, agent)`
+///
+/// Frames sent on one end are received on the other, so a test can play the HAProxy role on the
+/// first stream and hand the second one to the agent under test.
+#[cfg(feature = "std")]
+pub fn loopback() -> (LoopbackFramed, LoopbackFramed) {
+    let (haproxy, agent) = duplex(LOOPBACK_BUFFER_SIZE);
+
+    (
+        Framed::new(haproxy, SpopCodec::default()),
+        Framed::new(agent, SpopCodec::default()),
+    )
+}
+
+/// Returns the human-readable differences between two frames, one per line
+///
+/// Compares the frame type, the metadata and the payload. KV-LIST items are compared by key,
+/// regardless of their order. An empty result means both frames are equivalent.
+pub fn diff_frames(a: &dyn SpopFrame, b: &dyn SpopFrame) -> Vec<String> {
+    let mut diff = Vec::new();
+
+    if a.frame_type() != b.frame_type() {
+        diff.push(format!(
+            "frame type: {} != {}",
+            a.frame_type(),
+            b.frame_type()
+        ));
+    }
+
+    let (meta_a, meta_b) = (a.metadata(), b.metadata());
+
+    let (flags_a, flags_b) = (
+        u32::from_be_bytes(meta_a.flags.to_be_bytes()),
+        u32::from_be_bytes(meta_b.flags.to_be_bytes()),
+    );
+    if flags_a != flags_b {
+        diff.push(format!("flags: {:#010x} != {:#010x}", flags_a, flags_b));
+    }
+
+    if meta_a.stream_id != meta_b.stream_id {
+        diff.push(format!(
+            "stream-id: {} != {}",
+            meta_a.stream_id, meta_b.stream_id
+        ));
+    }
+
+    if meta_a.frame_id != meta_b.frame_id {
+        diff.push(format!(
+            "frame-id: {} != {}",
+            meta_a.frame_id, meta_b.frame_id
+        ));
+    }
+
+    match (a.payload(), b.payload()) {
+        (FramePayload::KVList(kv_a), FramePayload::KVList(kv_b)) => {
+            diff_kv_list("", &kv_a, &kv_b, &mut diff);
+        }
+
+        (FramePayload::ListOfMessages(messages_a), FramePayload::ListOfMessages(messages_b)) => {
+            if messages_a.len() != messages_b.len() {
+                diff.push(format!(
+                    "messages: {} != {}",
+                    messages_a.len(),
+                    messages_b.len()
+                ));
+            }
+
+            for (i, (msg_a, msg_b)) in messages_a.iter().zip(&messages_b).enumerate() {
+                if msg_a.name != msg_b.name {
+                    diff.push(format!(
+                        "message[{}] name: {:?} != {:?}",
+                        i, msg_a.name, msg_b.name
+                    ));
+                }
+
+                let prefix = format!("message[{}] ", i);
+                diff_kv_list(&prefix, &msg_a.args, &msg_b.args, &mut diff);
+            }
+        }
+
+        (FramePayload::ListOfActions(actions_a), FramePayload::ListOfActions(actions_b)) => {
+            if actions_a.len() != actions_b.len() {
+                diff.push(format!(
+                    "actions: {} != {}",
+                    actions_a.len(),
+                    actions_b.len()
+                ));
+            }
+
+            for (i, (action_a, action_b)) in actions_a.iter().zip(&actions_b).enumerate() {
+                if action_a != action_b {
+                    diff.push(format!("action[{}]: {:?} != {:?}", i, action_a, action_b));
+                }
+            }
+        }
+
+        (payload_a, payload_b) => {
+            diff.push(format!(
+                "payload: {} != {}",
+                payload_a.kind(),
+                payload_b.kind()
+            ));
+        }
+    }
+
+    diff
+}
+
+/// Returns `true` when both frames have the same type, metadata and payload
+///
+/// KV-LIST items are compared regardless of their order, so `frames_equal(&frame, &*parsed)`
+/// holds for a frame parsed back from its serialization. Use [`diff_frames`] to report what
+/// differs.
+pub fn frames_equal(a: &dyn SpopFrame, b: &dyn SpopFrame) -> bool {
+    a.frame_type() == b.frame_type() && a.metadata() == b.metadata() && a.payload() == b.payload()
+}
+
+/// Helper function to compare two KV-LISTs, keys are reported in alphabetical order
+fn diff_kv_list(
+    prefix: &str,
+    kv_a: &HashMap<String, TypedData>,
+    kv_b: &HashMap<String, TypedData>,
+    diff: &mut Vec<String>,
+) {
+    let keys: BTreeSet<&String> = kv_a.keys().chain(kv_b.keys()).collect();
+
+    for key in keys {
+        match (kv_a.get(key), kv_b.get(key)) {
+            (Some(value_a), Some(value_b)) if value_a != value_b => {
+                diff.push(format!(
+                    "{}key {:?}: {:?} != {:?}",
+                    prefix, key, value_a, value_b
+                ));
+            }
+            (Some(_), None) => diff.push(format!("{}key {:?}: only in left", prefix, key)),
+            (None, Some(_)) => diff.push(format!("{}key {:?}: only in right", prefix, key)),
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        actions::VarScope,
+        frame::{FrameFlags, Metadata},
+        frames::{
+            Ack, HaproxyDisconnect, haproxy_disconnect::HaproxyDisconnectFrame, notify::NotifyFrame,
+        },
+    };
+    use std::net::Ipv4Addr;
+
+    #[cfg(feature = "std")]
+    mod loopback {
+        use super::*;
+        use crate::{
+            frame::{FramePayload, FrameType, Message},
+            frames::{
+                AgentHello, FrameCapabilities, HaproxyHello, haproxy_hello::HaproxyHelloFrame,
+            },
+        };
+        use futures::{SinkExt, StreamExt};
+        use semver::Version;
+        use tokio::io::AsyncReadExt;
+
+        /// Reads one raw frame (without the length prefix) from the underlying stream
+        async fn read_raw_frame(framed: &mut LoopbackFramed) -> Vec<u8> {
+            let stream = framed.get_mut();
+
+            let frame_length = stream.read_u32().await.unwrap();
+            let mut frame = vec![0; frame_length as usize];
+            stream.read_exact(&mut frame).await.unwrap();
+
+            frame
+        }
+
+        #[tokio::test]
+        async fn test_loopback_handshake_and_notify() {
+            let (mut haproxy, mut agent) = loopback();
+
+            // HAPROXY-HELLO
+            let hello = HaproxyHelloFrame {
+                metadata: Metadata {
+                    flags: FrameFlags::new(true, false),
+                    stream_id: 0,
+                    frame_id: 0,
+                },
+                payload: HaproxyHello {
+                    supported_versions: vec![Version::new(2, 0, 0)],
+                    max_frame_size: 16380,
+                    capabilities: vec![FrameCapabilities::Pipelining],
+                    healthcheck: None,
+                    engine_id: None,
+                },
+            };
+            haproxy.send(Box::new(hello)).await.unwrap();
+
+            let frame = agent.next().await.unwrap().unwrap();
+            assert_eq!(frame.frame_type(), &FrameType::HaproxyHello);
+            let hello = HaproxyHello::try_from(frame.payload()).unwrap();
+
+            // AGENT-HELLO
+            let agent_hello = AgentHello {
+                version: Version::new(2, 0, 0),
+                max_frame_size: hello.max_frame_size,
+                capabilities: hello.capabilities,
+            };
+            agent.send(Box::new(agent_hello)).await.unwrap();
+
+            let frame = read_raw_frame(&mut haproxy).await;
+            assert_eq!(frame[0], FrameType::AgentHello.to_u8());
+
+            // NOTIFY
+            let notify = NotifyFrame {
+                metadata: Metadata {
+                    flags: FrameFlags::new(true, false),
+                    stream_id: 3,
+                    frame_id: 7,
+                },
+                messages: vec![Message {
+                    name: "check-client-ip".to_string(),
+                    args: HashMap::from([("ip".to_string(), TypedData::IPv4(Ipv4Addr::LOCALHOST))]),
+                }],
+            };
+            haproxy.send(Box::new(notify)).await.unwrap();
+
+            let frame = agent.next().await.unwrap().unwrap();
+            assert_eq!(frame.frame_type(), &FrameType::Notify);
+            let metadata = frame.metadata();
+            assert_eq!(metadata.stream_id, 3);
+            assert_eq!(metadata.frame_id, 7);
+
+            let messages = match frame.payload() {
+                FramePayload::ListOfMessages(messages) => messages,
+                _ => panic!("Wrong type of payload"),
+            };
+            assert_eq!(messages.len(), 1);
+            assert!(messages[0].client_ip().unwrap().is_loopback());
+
+            // ACK
+            let ack = Ack::new(metadata.stream_id, metadata.frame_id).set_var(
+                VarScope::Session,
+                "ip_score",
+                TypedData::UInt32(100),
+            );
+            agent.send(Box::new(ack.clone())).await.unwrap();
+
+            let frame = haproxy.next().await.unwrap().unwrap();
+            assert_eq!(frame.frame_type(), &FrameType::Ack);
+            assert_eq!(
+                Ack::try_from((frame.metadata(), frame.payload())).unwrap(),
+                ack
+            );
+        }
+    }
+
+    fn haproxy_disconnect(status_code: u32, message: &str) -> HaproxyDisconnectFrame {
+        HaproxyDisconnectFrame {
+            metadata: Metadata {
+                flags: FrameFlags::new(true, false),
+                stream_id: 0,
+                frame_id: 0,
+            },
+            payload: HaproxyDisconnect {
+                status_code,
+                message: message.to_string(),
+            },
+        }
+    }
+
+    #[test]
+    fn test_diff_frames() {
+        let a = haproxy_disconnect(0, "normal");
+        assert!(diff_frames(&a, &haproxy_disconnect(0, "normal")).is_empty());
+
+        let b = haproxy_disconnect(2, "normal");
+        assert_eq!(
+            diff_frames(&a, &b),
+            vec![r#"key "status-code": UInt32(0) != UInt32(2)"#]
+        );
+
+        let ack_a = Ack::new(1, 2).set_var(VarScope::Session, "score", TypedData::UInt32(1));
+        let ack_b = Ack::new(1, 3);
+        assert_eq!(
+            diff_frames(&ack_a, &ack_b),
+            vec!["frame-id: 2 != 3", "actions: 1 != 0"]
+        );
+
+        assert_eq!(
+            diff_frames(&a, &ack_b),
+            vec![
+                "frame type: HAPROXY-DISCONNECT != ACK",
+                "stream-id: 0 != 1",
+                "frame-id: 0 != 3",
+                "payload: items != actions",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_frames_equal_round_trip() {
+        let ack = Ack::new(3, 7)
+            .set_var(VarScope::Session, "score", TypedData::UInt32(42))
+            .set_var(
+                VarScope::Request,
+                "ip",
+                TypedData::IPv4(Ipv4Addr::LOCALHOST),
+            )
+            .unset_var(VarScope::Transaction, "tmp");
+
+        let notify = NotifyFrame::new(3, 7)
+            .add_message("check-client-ip")
+            .arg("ip", TypedData::IPv4(Ipv4Addr::LOCALHOST))
+            .arg("country", TypedData::String("xx".to_string()))
+            .arg("ua", TypedData::Binary(vec![0x00, 0xff]))
+            .build();
+
+        let frames: Vec<Box<dyn SpopFrame>> = vec![Box::new(ack), Box::new(notify)];
+        for frame in &frames {
+            let serialized = frame.serialize().unwrap();
+            let (_, parsed) = crate::parser::parse_frame(&serialized).unwrap();
+            assert!(frames_equal(frame.as_ref(), parsed.as_ref()));
+        }
+
+        assert!(!frames_equal(frames[0].as_ref(), frames[1].as_ref()));
+        assert!(!frames_equal(
+            frames[0].as_ref(),
+            &Ack::new(3, 8).set_var(VarScope::Session, "score", TypedData::UInt32(42))
+        ));
+    }
+}
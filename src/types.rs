@@ -1,11 +1,15 @@
-use crate::varint::{decode_varint, encode_varint};
+use crate::HashMap;
+use crate::varint::{decode_varint, put_varint, varint_len};
+use alloc::{string::String, string::ToString, vec::Vec};
+use bytes::BufMut;
+use core::fmt;
+use core::net::{Ipv4Addr, Ipv6Addr};
 use nom::{
     IResult,
     bytes::complete::take,
     error::{Error, ErrorKind},
-    number::complete::be_u8,
+    number::complete::{be_u8, be_u32, be_u128},
 };
-use std::net::{Ipv4Addr, Ipv6Addr};
 
 /// <https://github.com/haproxy/haproxy/blob/master/doc/SPOE.txt#L635>
 ///
@@ -42,7 +46,14 @@ const TYPE_IPV6: u8 = 0x07;
 const TYPE_STRING: u8 = 0x08;
 const TYPE_BINARY: u8 = 0x09;
 
+/// A TYPED-DATA value
+///
+/// Signed integers are encoded like HAProxy does, by casting them to a 64-bit unsigned integer
+/// before the varint encoding, with no zigzag: a negative value always takes 10 bytes. An INT32
+/// is decoded from the low 32 bits of the varint, so both its sign-extended and zero-extended
+/// encodings are accepted.
 #[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum TypedData {
     Null,
     Bool(bool),
@@ -57,6 +68,22 @@ pub enum TypedData {
 }
 
 impl TypedData {
+    /// Returns the name of the type as written in the spec, e.g. `UINT32`
+    pub const fn type_name(&self) -> &'static str {
+        match self {
+            Self::Null => "NULL",
+            Self::Bool(_) => "BOOLEAN",
+            Self::Int32(_) => "INT32",
+            Self::UInt32(_) => "UINT32",
+            Self::Int64(_) => "INT64",
+            Self::UInt64(_) => "UINT64",
+            Self::IPv4(_) => "IPV4",
+            Self::IPv6(_) => "IPV6",
+            Self::String(_) => "STRING",
+            Self::Binary(_) => "BINARY",
+        }
+    }
+
     pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
         match typed_data(bytes) {
             Ok((_rest, typed_data)) => Some(typed_data),
@@ -64,55 +91,427 @@ impl TypedData {
         }
     }
 
-    pub fn to_bytes(&self, buf: &mut Vec<u8>) {
+    /// Returns the value of a BOOLEAN, `None` for any other type
+    pub const fn as_bool(&self) -> Option<bool> {
+        match self {
+            Self::Bool(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    /// Returns the value of a INT32, `None` for any other type
+    pub const fn as_i32(&self) -> Option<i32> {
+        match self {
+            Self::Int32(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    /// Returns the value of a UINT32, `None` for any other type
+    pub const fn as_u32(&self) -> Option<u32> {
+        match self {
+            Self::UInt32(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    /// Returns the value of a INT64, `None` for any other type
+    pub const fn as_i64(&self) -> Option<i64> {
+        match self {
+            Self::Int64(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    /// Returns the value of a UINT64, `None` for any other type
+    pub const fn as_u64(&self) -> Option<u64> {
+        match self {
+            Self::UInt64(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    /// Returns the value of a IPV4, `None` for any other type
+    pub const fn as_ipv4(&self) -> Option<Ipv4Addr> {
+        match self {
+            Self::IPv4(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    /// Returns the value of a IPV6, `None` for any other type
+    pub const fn as_ipv6(&self) -> Option<Ipv6Addr> {
+        match self {
+            Self::IPv6(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    /// Returns the value of a STRING, `None` for any other type
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Self::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Returns the bytes of a BINARY, `None` for any other type
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            Self::Binary(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Shortens a `String` or `Binary` value to at most `max_len` bytes
+    ///
+    /// Strings are cut on a character boundary, so they may end up slightly shorter. Returns
+    /// whether the value was truncated, other types are left untouched.
+    pub fn truncate(&mut self, max_len: usize) -> bool {
+        match self {
+            Self::String(value) if value.len() > max_len => {
+                value.truncate(floor_char_boundary(value, max_len));
+                value.shrink_to_fit();
+                true
+            }
+            Self::Binary(value) if value.len() > max_len => {
+                value.truncate(max_len);
+                value.shrink_to_fit();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Builds a value from an HTTP header value
+    ///
+    /// Valid UTF-8 is kept as a `String`, anything else is passed through untouched as `Binary`
+    /// instead of being lossily converted.
+    pub fn from_http_value(bytes: &[u8]) -> Self {
+        match core::str::from_utf8(bytes) {
+            Ok(value) => Self::String(value.to_string()),
+            Err(_) => Self::Binary(bytes.to_vec()),
+        }
+    }
+
+    /// Returns the number of bytes [`TypedData::to_bytes`] writes, type byte included
+    pub fn encoded_len(&self) -> usize {
+        1 + match self {
+            Self::Null | Self::Bool(_) => 0,
+            Self::Int32(val) => varint_len(*val as u64),
+            Self::UInt32(val) => varint_len(*val as u64),
+            Self::Int64(val) => varint_len(*val as u64),
+            Self::UInt64(val) => varint_len(*val),
+            Self::IPv4(_) => 4,
+            Self::IPv6(_) => 16,
+            Self::String(val) => varint_len(val.len() as u64) + val.len(),
+            Self::Binary(val) => varint_len(val.len() as u64) + val.len(),
+        }
+    }
+
+    pub fn to_bytes<B: BufMut + ?Sized>(&self, buf: &mut B) {
         match self {
             Self::Null => {
-                buf.push(TYPE_NULL);
+                buf.put_u8(TYPE_NULL);
             }
             Self::Bool(val) => {
                 let flags = if *val { 0x01 } else { 0x00 } << 4;
-                buf.push(flags | TYPE_BOOL);
+                buf.put_u8(flags | TYPE_BOOL);
             }
             Self::Int32(val) => {
-                buf.push(TYPE_INT32);
-                buf.extend(encode_varint(*val as u64));
+                buf.put_u8(TYPE_INT32);
+                put_varint(buf, *val as u64);
             }
             Self::UInt32(val) => {
-                buf.push(TYPE_UINT32);
-                buf.extend(encode_varint(*val as u64));
+                buf.put_u8(TYPE_UINT32);
+                put_varint(buf, *val as u64);
             }
             Self::Int64(val) => {
-                buf.push(TYPE_INT64);
-                buf.extend(encode_varint(*val as u64));
+                buf.put_u8(TYPE_INT64);
+                put_varint(buf, *val as u64);
             }
             Self::UInt64(val) => {
-                buf.push(TYPE_UINT64);
-                buf.extend(encode_varint(*val));
+                buf.put_u8(TYPE_UINT64);
+                put_varint(buf, *val);
             }
             Self::IPv4(addr) => {
-                buf.push(TYPE_IPV4);
-                buf.extend_from_slice(&addr.octets());
+                buf.put_u8(TYPE_IPV4);
+                buf.put_slice(&addr.octets());
             }
             Self::IPv6(addr) => {
-                buf.push(TYPE_IPV6);
-                buf.extend_from_slice(&addr.octets());
-            }
-            Self::String(val) => {
-                buf.push(TYPE_STRING);
-                buf.extend(encode_varint(val.len() as u64));
-                buf.extend_from_slice(val.as_bytes());
+                buf.put_u8(TYPE_IPV6);
+                buf.put_slice(&addr.octets());
             }
+            Self::String(val) => put_string(buf, val),
             Self::Binary(val) => {
-                buf.push(TYPE_BINARY);
-                buf.extend(encode_varint(val.len() as u64));
-                buf.extend_from_slice(val);
+                buf.put_u8(TYPE_BINARY);
+                put_varint(buf, val.len() as u64);
+                buf.put_slice(val);
             }
         }
     }
 }
 
+/// Writes a STRING TYPED-DATA at the end of `buf`, without building a [`TypedData`]
+pub(crate) fn put_string<B: BufMut + ?Sized>(buf: &mut B, value: &str) {
+    buf.put_u8(TYPE_STRING);
+    put_varint(buf, value.len() as u64);
+    buf.put_slice(value.as_bytes());
+}
+
+/// Returns the length in bytes of the `Display` output of `value`, without formatting it into a
+/// string
+pub(crate) fn display_len(value: &dyn fmt::Display) -> usize {
+    struct Counter(usize);
+
+    impl fmt::Write for Counter {
+        fn write_str(&mut self, s: &str) -> fmt::Result {
+            self.0 += s.len();
+            Ok(())
+        }
+    }
+
+    let mut counter = Counter(0);
+    // Counter never fails, only a broken `Display` implementation could
+    let _ = fmt::write(&mut counter, format_args!("{}", value));
+    counter.0
+}
+
+/// Writes a STRING TYPED-DATA at the end of `buf` from the `Display` output of `value`, without
+/// formatting it into a string first
+pub(crate) fn put_display(buf: &mut dyn BufMut, value: &dyn fmt::Display) {
+    struct Writer<'a>(&'a mut dyn BufMut);
+
+    impl fmt::Write for Writer<'_> {
+        fn write_str(&mut self, s: &str) -> fmt::Result {
+            self.0.put_slice(s.as_bytes());
+            Ok(())
+        }
+    }
+
+    buf.put_u8(TYPE_STRING);
+    put_varint(buf, display_len(value) as u64);
+    let _ = fmt::write(&mut Writer(buf), format_args!("{}", value));
+}
+
+/// Displays the value the way HAProxy logs it, strings are quoted and binaries only show their
+/// length, e.g. `"GET"` or `<12 bytes>`
+impl fmt::Display for TypedData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Null => write!(f, "null"),
+            Self::Bool(val) => write!(f, "{}", val),
+            Self::Int32(val) => write!(f, "{}", val),
+            Self::UInt32(val) => write!(f, "{}", val),
+            Self::Int64(val) => write!(f, "{}", val),
+            Self::UInt64(val) => write!(f, "{}", val),
+            Self::IPv4(addr) => write!(f, "{}", addr),
+            Self::IPv6(addr) => write!(f, "{}", addr),
+            Self::String(val) => write!(f, "{:?}", val),
+            Self::Binary(val) => write!(f, "<{} bytes>", val.len()),
+        }
+    }
+}
+
+impl From<&str> for TypedData {
+    fn from(value: &str) -> Self {
+        Self::String(value.to_string())
+    }
+}
+
+impl From<String> for TypedData {
+    fn from(value: String) -> Self {
+        Self::String(value)
+    }
+}
+
+impl From<bool> for TypedData {
+    fn from(value: bool) -> Self {
+        Self::Bool(value)
+    }
+}
+
+impl From<i32> for TypedData {
+    fn from(value: i32) -> Self {
+        Self::Int32(value)
+    }
+}
+
+impl From<u32> for TypedData {
+    fn from(value: u32) -> Self {
+        Self::UInt32(value)
+    }
+}
+
+impl From<i64> for TypedData {
+    fn from(value: i64) -> Self {
+        Self::Int64(value)
+    }
+}
+
+impl From<u64> for TypedData {
+    fn from(value: u64) -> Self {
+        Self::UInt64(value)
+    }
+}
+
+impl From<Ipv4Addr> for TypedData {
+    fn from(value: Ipv4Addr) -> Self {
+        Self::IPv4(value)
+    }
+}
+
+impl From<Ipv6Addr> for TypedData {
+    fn from(value: Ipv6Addr) -> Self {
+        Self::IPv6(value)
+    }
+}
+
+impl From<Vec<u8>> for TypedData {
+    fn from(value: Vec<u8>) -> Self {
+        Self::Binary(value)
+    }
+}
+
+/// Typed getters over a KV-LIST or the arguments of a message
+///
+/// Each getter returns `None` when the key is missing or when its value has another type, there
+/// is no conversion between types.
+///
+/// ```
+/// use spop::{HashMap, KvListExt, TypedData};
+/// use std::net::Ipv4Addr;
+///
+/// let args = HashMap::from([("ip".to_string(), TypedData::IPv4(Ipv4Addr::LOCALHOST))]);
+/// assert_eq!(args.get_ipv4("ip"), Some(Ipv4Addr::LOCALHOST));
+/// assert_eq!(args.get_ipv6("ip"), None);
+/// ```
+pub trait KvListExt {
+    /// Returns the value of a key, whatever its type
+    fn get_typed(&self, key: &str) -> Option<&TypedData>;
+
+    /// Returns true if the key is present with a NULL value
+    fn is_null(&self, key: &str) -> bool {
+        matches!(self.get_typed(key), Some(TypedData::Null))
+    }
+
+    fn get_bool(&self, key: &str) -> Option<bool> {
+        self.get_typed(key).and_then(TypedData::as_bool)
+    }
+
+    fn get_i32(&self, key: &str) -> Option<i32> {
+        self.get_typed(key).and_then(TypedData::as_i32)
+    }
+
+    fn get_u32(&self, key: &str) -> Option<u32> {
+        self.get_typed(key).and_then(TypedData::as_u32)
+    }
+
+    fn get_i64(&self, key: &str) -> Option<i64> {
+        self.get_typed(key).and_then(TypedData::as_i64)
+    }
+
+    fn get_u64(&self, key: &str) -> Option<u64> {
+        self.get_typed(key).and_then(TypedData::as_u64)
+    }
+
+    fn get_ipv4(&self, key: &str) -> Option<Ipv4Addr> {
+        self.get_typed(key).and_then(TypedData::as_ipv4)
+    }
+
+    fn get_ipv6(&self, key: &str) -> Option<Ipv6Addr> {
+        self.get_typed(key).and_then(TypedData::as_ipv6)
+    }
+
+    fn get_str(&self, key: &str) -> Option<&str> {
+        self.get_typed(key).and_then(TypedData::as_str)
+    }
+
+    fn get_bytes(&self, key: &str) -> Option<&[u8]> {
+        self.get_typed(key).and_then(TypedData::as_bytes)
+    }
+}
+
+impl KvListExt for HashMap<String, TypedData> {
+    fn get_typed(&self, key: &str) -> Option<&TypedData> {
+        self.get(key)
+    }
+}
+
+/// Borrowed counterpart of [`TypedData`], STRING and BINARY values point into the parsed input
+///
+/// Returned by [`typed_data_ref`], convert it with [`TypedData::from`] only when an owned value
+/// is needed.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum TypedDataRef<'a> {
+    Null,
+    Bool(bool),
+    Int32(i32),
+    UInt32(u32),
+    Int64(i64),
+    UInt64(u64),
+    IPv4(Ipv4Addr),
+    IPv6(Ipv6Addr),
+    /// Raw bytes of the string, not checked for UTF-8
+    String(&'a [u8]),
+    Binary(&'a [u8]),
+}
+
+impl TypedDataRef<'_> {
+    /// Shortens a STRING or BINARY value to at most `max_len` bytes, like [`TypedData::truncate`]
+    ///
+    /// Valid UTF-8 strings are cut on a character boundary, other types are left untouched.
+    pub(crate) fn truncated(self, max_len: usize) -> Self {
+        match self {
+            Self::String(value) if value.len() > max_len => {
+                let end = match core::str::from_utf8(value) {
+                    Ok(value) => floor_char_boundary(value, max_len),
+                    Err(_) => max_len,
+                };
+                Self::String(&value[..end])
+            }
+            Self::Binary(value) if value.len() > max_len => Self::Binary(&value[..max_len]),
+            other => other,
+        }
+    }
+}
+
+/// Returns the largest character boundary of `value` that is not after `index`
+fn floor_char_boundary(value: &str, index: usize) -> usize {
+    let mut index = index.min(value.len());
+    while !value.is_char_boundary(index) {
+        index -= 1;
+    }
+    index
+}
+
+impl From<TypedDataRef<'_>> for TypedData {
+    fn from(value: TypedDataRef<'_>) -> Self {
+        match value {
+            TypedDataRef::Null => Self::Null,
+            TypedDataRef::Bool(v) => Self::Bool(v),
+            TypedDataRef::Int32(v) => Self::Int32(v),
+            TypedDataRef::UInt32(v) => Self::UInt32(v),
+            TypedDataRef::Int64(v) => Self::Int64(v),
+            TypedDataRef::UInt64(v) => Self::UInt64(v),
+            TypedDataRef::IPv4(v) => Self::IPv4(v),
+            TypedDataRef::IPv6(v) => Self::IPv6(v),
+            TypedDataRef::String(v) => Self::String(String::from_utf8_lossy(v).into_owned()),
+            TypedDataRef::Binary(v) => Self::Binary(v.to_vec()),
+        }
+    }
+}
+
 /// Returns the Type ID and Flags from the first byte of the input
+///
+/// Reserved type ids (10 to 15) fail with `ErrorKind::Tag`: their encoding is unknown, so the
+/// value can't be skipped. Frame parsers report them as [`crate::SpopError::ReservedType`].
 pub fn typed_data(input: &[u8]) -> IResult<&[u8], TypedData> {
+    typed_data_ref(input).map(|(input, data)| (input, data.into()))
+}
+
+/// Same as [`typed_data`] without allocating, STRING and BINARY values borrow from `input`
+pub fn typed_data_ref(input: &[u8]) -> IResult<&[u8], TypedDataRef<'_>> {
     if input.is_empty() {
         return Err(nom::Err::Error(Error::new(input, ErrorKind::Eof)));
     }
@@ -126,28 +525,15 @@ pub fn typed_data(input: &[u8]) -> IResult<&[u8], TypedData> {
     let flags = type_and_flags >> 4;
 
     match type_id {
-        TYPE_NULL => Ok((input, TypedData::Null)),
-        TYPE_BOOL => Ok((input, TypedData::Bool((flags & 1) != 0))),
-        TYPE_INT32 => decode_varint(input).map(|(i, v)| (i, TypedData::Int32(v as i32))),
-        TYPE_UINT32 => decode_varint(input).map(|(i, v)| (i, TypedData::UInt32(v as u32))),
-        TYPE_INT64 => decode_varint(input).map(|(i, v)| (i, TypedData::Int64(v as i64))),
-        TYPE_UINT64 => decode_varint(input).map(|(i, v)| (i, TypedData::UInt64(v))),
-        TYPE_IPV4 => {
-            if input.len() < 4 {
-                return Err(nom::Err::Error(Error::new(input, ErrorKind::Eof)));
-            }
-            let (input, bytes) = take(4usize)(input)?;
-            let addr = Ipv4Addr::new(bytes[0], bytes[1], bytes[2], bytes[3]);
-            Ok((input, TypedData::IPv4(addr)))
-        }
-        TYPE_IPV6 => {
-            if input.len() < 16 {
-                return Err(nom::Err::Error(Error::new(input, ErrorKind::Eof)));
-            }
-            let (input, bytes) = take(16usize)(input)?;
-            let addr = Ipv6Addr::from(<[u8; 16]>::try_from(bytes).unwrap());
-            Ok((input, TypedData::IPv6(addr)))
-        }
+        TYPE_NULL => Ok((input, TypedDataRef::Null)),
+        TYPE_BOOL => Ok((input, TypedDataRef::Bool((flags & 1) != 0))),
+        TYPE_INT32 => decode_varint(input).map(|(i, v)| (i, TypedDataRef::Int32(v as i32))),
+        TYPE_UINT32 => decode_varint(input).map(|(i, v)| (i, TypedDataRef::UInt32(v as u32))),
+        TYPE_INT64 => decode_varint(input).map(|(i, v)| (i, TypedDataRef::Int64(v as i64))),
+        TYPE_UINT64 => decode_varint(input).map(|(i, v)| (i, TypedDataRef::UInt64(v))),
+        // addresses are in network byte order, a short input is an `ErrorKind::Eof` error
+        TYPE_IPV4 => be_u32(input).map(|(i, v)| (i, TypedDataRef::IPv4(Ipv4Addr::from(v)))),
+        TYPE_IPV6 => be_u128(input).map(|(i, v)| (i, TypedDataRef::IPv6(Ipv6Addr::from(v)))),
         TYPE_STRING | TYPE_BINARY => {
             let (input, length) = decode_varint(input)?;
 
@@ -157,10 +543,9 @@ pub fn typed_data(input: &[u8]) -> IResult<&[u8], TypedData> {
 
             let (input, data) = take(length)(input)?;
             if type_id == TYPE_STRING {
-                let s = String::from_utf8_lossy(data).into_owned();
-                Ok((input, TypedData::String(s)))
+                Ok((input, TypedDataRef::String(data)))
             } else {
-                Ok((input, TypedData::Binary(data.to_vec())))
+                Ok((input, TypedDataRef::Binary(data)))
             }
         }
         _ => Err(nom::Err::Error(nom::error::Error::new(
@@ -170,9 +555,66 @@ pub fn typed_data(input: &[u8]) -> IResult<&[u8], TypedData> {
     }
 }
 
+/// Returns the declared length of a STRING or BINARY typed data, without reading its value
+///
+/// `None` for the other types, or when the length can't be decoded.
+pub(crate) fn declared_value_len(input: &[u8]) -> Option<u64> {
+    let (&type_and_flags, input) = input.split_first()?;
+
+    match type_and_flags & 0x0F {
+        TYPE_STRING | TYPE_BINARY => decode_varint(input).ok().map(|(_, length)| length),
+        _ => None,
+    }
+}
+
+/// Returns the type id of a TYPED-DATA using a reserved type, 10 to 15
+///
+/// Frame parsers check it before reading a value, to fail with [`crate::SpopError::ReservedType`]
+/// instead of a generic `ErrorKind::Tag`.
+pub(crate) fn reserved_type_id(input: &[u8]) -> Option<u8> {
+    let type_id = input.first()? & 0x0F;
+    (type_id > TYPE_BINARY).then_some(type_id)
+}
+
+/// Same as [`typed_data`], rejecting the encodings the spec leaves reserved
+///
+/// A BOOLEAN only uses bit 0 of its flags, any other flag bit set is a `nom::Err::Failure`
+/// with `ErrorKind::Verify`. Reserved type ids (10 to 15) are rejected by both parsers.
+pub fn typed_data_strict(input: &[u8]) -> IResult<&[u8], TypedData> {
+    if let Some(&type_and_flags) = input.first()
+        && type_and_flags & 0x0F == TYPE_BOOL
+        && type_and_flags >> 4 > 1
+    {
+        // a failure, so that lists of typed data report it instead of stopping silently
+        return Err(nom::Err::Failure(Error::new(input, ErrorKind::Verify)));
+    }
+
+    typed_data(input)
+}
+
+/// Generates any [`TypedData`], for property tests
+#[cfg(test)]
+pub(crate) fn arb_typed_data() -> impl proptest::strategy::Strategy<Value = TypedData> {
+    use proptest::prelude::*;
+
+    prop_oneof![
+        Just(TypedData::Null),
+        any::<bool>().prop_map(TypedData::Bool),
+        any::<i32>().prop_map(TypedData::Int32),
+        any::<u32>().prop_map(TypedData::UInt32),
+        any::<i64>().prop_map(TypedData::Int64),
+        any::<u64>().prop_map(TypedData::UInt64),
+        any::<[u8; 4]>().prop_map(|b| TypedData::IPv4(Ipv4Addr::from(b))),
+        any::<[u8; 16]>().prop_map(|b| TypedData::IPv6(Ipv6Addr::from(b))),
+        ".{0,64}".prop_map(TypedData::String),
+        proptest::collection::vec(any::<u8>(), 0..256).prop_map(TypedData::Binary),
+    ]
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::varint::encode_varint;
     use std::net::{Ipv4Addr, Ipv6Addr};
 
     /// List of test cases for type parsing
@@ -273,4 +715,327 @@ mod tests {
             assert_eq!(buf, input, "Test case '{}' failed", desc);
         }
     }
+
+    #[test]
+    fn test_uint32_varint_boundaries() {
+        // (value, expected bytes including the UINT32 type byte)
+        let cases: Vec<(u32, Vec<u8>)> = vec![
+            (127, vec![0x03, 0x7F]),
+            (128, vec![0x03, 0x80]),
+            (239, vec![0x03, 0xEF]),
+            (240, vec![0x03, 0xF0, 0x00]),
+            // max-frame-size sent by HAProxy in the HELLO fixture
+            (16380, vec![0x03, 0xFC, 0xF0, 0x06]),
+            (16383, vec![0x03, 0xFF, 0xF0, 0x06]),
+            (16384, vec![0x03, 0xF0, 0xF1, 0x06]),
+            (u32::MAX, vec![0x03, 0xFF, 0xF0, 0xFE, 0xFE, 0x7E]),
+        ];
+
+        for (value, bytes) in cases {
+            let (rest, parsed) =
+                typed_data(&bytes).unwrap_or_else(|e| panic!("Failed to parse {}: {:?}", value, e));
+            assert!(rest.is_empty(), "Trailing bytes for {}", value);
+            assert_eq!(parsed, TypedData::UInt32(value));
+
+            let mut buf = Vec::new();
+            TypedData::UInt32(value).to_bytes(&mut buf);
+            assert_eq!(buf, bytes, "Wrong encoding for {}", value);
+        }
+    }
+
+    #[test]
+    fn test_signed_varint_encoding() {
+        // (value, expected bytes including the type byte), as encoded by HAProxy's
+        // encode_varint((uint64_t)value)
+        let cases: Vec<(TypedData, Vec<u8>)> = vec![
+            (TypedData::Int32(0), vec![0x02, 0x00]),
+            (TypedData::Int32(239), vec![0x02, 0xEF]),
+            (
+                TypedData::Int32(-1),
+                vec![
+                    0x02, 0xFF, 0xF0, 0xFE, 0xFE, 0xFE, 0xFE, 0xFE, 0xFE, 0xFE, 0x0E,
+                ],
+            ),
+            (
+                TypedData::Int32(-2),
+                vec![
+                    0x02, 0xFE, 0xF0, 0xFE, 0xFE, 0xFE, 0xFE, 0xFE, 0xFE, 0xFE, 0x0E,
+                ],
+            ),
+            (
+                TypedData::Int32(i32::MIN),
+                vec![
+                    0x02, 0xF0, 0xF1, 0xFE, 0xFE, 0xBE, 0xFE, 0xFE, 0xFE, 0xFE, 0x0E,
+                ],
+            ),
+            (
+                TypedData::Int32(i32::MAX),
+                vec![0x02, 0xFF, 0xF0, 0xFE, 0xFE, 0x3E],
+            ),
+            (
+                TypedData::Int64(-1),
+                vec![
+                    0x04, 0xFF, 0xF0, 0xFE, 0xFE, 0xFE, 0xFE, 0xFE, 0xFE, 0xFE, 0x0E,
+                ],
+            ),
+            (
+                TypedData::Int64(i64::MIN),
+                vec![
+                    0x04, 0xF0, 0xF1, 0xFE, 0xFE, 0xFE, 0xFE, 0xFE, 0xFE, 0xFE, 0x06,
+                ],
+            ),
+            (
+                TypedData::Int64(i64::MAX),
+                vec![
+                    0x04, 0xFF, 0xF0, 0xFE, 0xFE, 0xFE, 0xFE, 0xFE, 0xFE, 0xFE, 0x06,
+                ],
+            ),
+        ];
+
+        for (value, bytes) in cases {
+            let mut buf = Vec::new();
+            value.to_bytes(&mut buf);
+            assert_eq!(buf, bytes, "Wrong encoding for {:?}", value);
+            assert_eq!(value.encoded_len(), bytes.len());
+
+            let (rest, parsed) = typed_data(&bytes).unwrap();
+            assert!(rest.is_empty(), "Trailing bytes for {:?}", value);
+            assert_eq!(parsed, value);
+        }
+
+        // -1 encoded as a 32-bit unsigned value is still -1
+        let mut bytes = vec![0x02];
+        bytes.extend_from_slice(&encode_varint(u32::MAX as u64));
+        assert_eq!(typed_data(&bytes).unwrap().1, TypedData::Int32(-1));
+    }
+
+    #[test]
+    fn test_from_http_value() {
+        assert_eq!(
+            TypedData::from_http_value(b"Mozilla/5.0 (X11; Linux x86_64)"),
+            TypedData::String("Mozilla/5.0 (X11; Linux x86_64)".to_string())
+        );
+
+        // latin-1 encoded "café" is not valid UTF-8
+        let binary = b"caf\xe9";
+        assert_eq!(
+            TypedData::from_http_value(binary),
+            TypedData::Binary(binary.to_vec())
+        );
+    }
+
+    #[test]
+    fn test_truncate() {
+        let mut value = TypedData::Binary(vec![0xff; 10]);
+        assert!(value.truncate(4));
+        assert_eq!(value, TypedData::Binary(vec![0xff; 4]));
+        assert!(!value.truncate(4));
+
+        // "é" is 2 bytes long, the cut happens before it
+        let mut value = TypedData::String("café".to_string());
+        assert!(value.truncate(4));
+        assert_eq!(value, TypedData::String("caf".to_string()));
+
+        let mut value = TypedData::UInt32(123);
+        assert!(!value.truncate(0));
+    }
+
+    #[test]
+    fn test_accessors() {
+        assert_eq!(TypedData::Bool(true).as_bool(), Some(true));
+        assert_eq!(TypedData::Int32(-1).as_i32(), Some(-1));
+        assert_eq!(TypedData::UInt32(1).as_u32(), Some(1));
+        assert_eq!(TypedData::Int64(-1).as_i64(), Some(-1));
+        assert_eq!(TypedData::UInt64(1).as_u64(), Some(1));
+        assert_eq!(
+            TypedData::IPv4(Ipv4Addr::LOCALHOST).as_ipv4(),
+            Some(Ipv4Addr::LOCALHOST)
+        );
+        assert_eq!(
+            TypedData::IPv6(Ipv6Addr::LOCALHOST).as_ipv6(),
+            Some(Ipv6Addr::LOCALHOST)
+        );
+        assert_eq!(TypedData::String("abc".to_string()).as_str(), Some("abc"));
+        assert_eq!(
+            TypedData::Binary(vec![1, 2]).as_bytes(),
+            Some(&[1u8, 2][..])
+        );
+
+        // no conversion between types
+        assert_eq!(TypedData::UInt32(1).as_u64(), None);
+        assert_eq!(TypedData::Int32(1).as_u32(), None);
+        assert_eq!(TypedData::Binary(b"abc".to_vec()).as_str(), None);
+        assert_eq!(TypedData::String("abc".to_string()).as_bytes(), None);
+        assert_eq!(TypedData::Null.as_bool(), None);
+    }
+
+    #[test]
+    fn test_kv_list_getters() {
+        let kv_list = HashMap::from([
+            ("null".to_string(), TypedData::Null),
+            ("bool".to_string(), TypedData::Bool(true)),
+            ("i32".to_string(), TypedData::Int32(-1)),
+            ("u32".to_string(), TypedData::UInt32(1)),
+            ("i64".to_string(), TypedData::Int64(-2)),
+            ("u64".to_string(), TypedData::UInt64(2)),
+            ("ipv4".to_string(), TypedData::IPv4(Ipv4Addr::LOCALHOST)),
+            ("ipv6".to_string(), TypedData::IPv6(Ipv6Addr::LOCALHOST)),
+            ("str".to_string(), TypedData::String("abc".to_string())),
+            ("bytes".to_string(), TypedData::Binary(vec![1, 2])),
+        ]);
+
+        assert!(kv_list.is_null("null"));
+        assert_eq!(kv_list.get_bool("bool"), Some(true));
+        assert_eq!(kv_list.get_i32("i32"), Some(-1));
+        assert_eq!(kv_list.get_u32("u32"), Some(1));
+        assert_eq!(kv_list.get_i64("i64"), Some(-2));
+        assert_eq!(kv_list.get_u64("u64"), Some(2));
+        assert_eq!(kv_list.get_ipv4("ipv4"), Some(Ipv4Addr::LOCALHOST));
+        assert_eq!(kv_list.get_ipv6("ipv6"), Some(Ipv6Addr::LOCALHOST));
+        assert_eq!(kv_list.get_str("str"), Some("abc"));
+        assert_eq!(kv_list.get_bytes("bytes"), Some(&[1u8, 2][..]));
+        assert_eq!(kv_list.get_typed("u32"), Some(&TypedData::UInt32(1)));
+
+        // missing keys
+        assert!(!kv_list.is_null("missing"));
+        assert_eq!(kv_list.get_ipv4("missing"), None);
+        assert_eq!(kv_list.get_typed("missing"), None);
+
+        // wrong types
+        assert!(!kv_list.is_null("bool"));
+        assert_eq!(kv_list.get_bool("null"), None);
+        assert_eq!(kv_list.get_i32("u32"), None);
+        assert_eq!(kv_list.get_u32("i32"), None);
+        assert_eq!(kv_list.get_i64("u64"), None);
+        assert_eq!(kv_list.get_u64("u32"), None);
+        assert_eq!(kv_list.get_ipv4("ipv6"), None);
+        assert_eq!(kv_list.get_ipv6("ipv4"), None);
+        assert_eq!(kv_list.get_str("bytes"), None);
+        assert_eq!(kv_list.get_bytes("str"), None);
+    }
+
+    #[test]
+    fn test_from_conversions() {
+        assert_eq!(TypedData::from("abc"), TypedData::String("abc".to_string()));
+        assert_eq!(
+            TypedData::from("abc".to_string()),
+            TypedData::String("abc".to_string())
+        );
+        assert_eq!(TypedData::from(true), TypedData::Bool(true));
+        assert_eq!(TypedData::from(-1i32), TypedData::Int32(-1));
+        assert_eq!(TypedData::from(1u32), TypedData::UInt32(1));
+        assert_eq!(TypedData::from(-1i64), TypedData::Int64(-1));
+        assert_eq!(TypedData::from(1u64), TypedData::UInt64(1));
+        assert_eq!(
+            TypedData::from(Ipv4Addr::LOCALHOST),
+            TypedData::IPv4(Ipv4Addr::LOCALHOST)
+        );
+        assert_eq!(
+            TypedData::from(Ipv6Addr::LOCALHOST),
+            TypedData::IPv6(Ipv6Addr::LOCALHOST)
+        );
+        assert_eq!(TypedData::from(vec![1u8, 2]), TypedData::Binary(vec![1, 2]));
+
+        let value: TypedData = 42u32.into();
+        assert_eq!(value, TypedData::UInt32(42));
+    }
+
+    #[test]
+    fn test_typed_data_ip_boundaries() {
+        let mut ipv6 = vec![0x07];
+        ipv6.extend_from_slice(&Ipv6Addr::LOCALHOST.octets());
+
+        // exactly 16 bytes, then one more
+        assert_eq!(
+            typed_data(&ipv6).unwrap(),
+            (&[][..], TypedData::IPv6(Ipv6Addr::LOCALHOST))
+        );
+        ipv6.push(0xff);
+        assert_eq!(
+            typed_data(&ipv6).unwrap(),
+            (&[0xff][..], TypedData::IPv6(Ipv6Addr::LOCALHOST))
+        );
+
+        // short buffers fail without panicking
+        for len in 0..17 {
+            let err = typed_data(&ipv6[..len]).unwrap_err();
+            assert!(
+                matches!(err, nom::Err::Error(e) if e.code == ErrorKind::Eof),
+                "{}",
+                len
+            );
+        }
+
+        let ipv4 = [0x06, 10, 0, 0, 1];
+        assert_eq!(
+            typed_data(&ipv4).unwrap(),
+            (&[][..], TypedData::IPv4(Ipv4Addr::new(10, 0, 0, 1)))
+        );
+        for len in 0..5 {
+            let err = typed_data(&ipv4[..len]).unwrap_err();
+            assert!(
+                matches!(err, nom::Err::Error(e) if e.code == ErrorKind::Eof),
+                "{}",
+                len
+            );
+        }
+    }
+
+    #[test]
+    fn test_typed_data_strict() {
+        for (input, expected) in [(0x01, false), (0x11, true)] {
+            let (_, data) = typed_data_strict(&[input]).unwrap();
+            assert_eq!(data, TypedData::Bool(expected));
+        }
+
+        assert_eq!(typed_data(&[0x21]).unwrap().1, TypedData::Bool(false));
+        assert_eq!(
+            typed_data_strict(&[0x21]).unwrap_err(),
+            nom::Err::Failure(Error::new(&[0x21][..], ErrorKind::Verify))
+        );
+
+        for type_id in 10..=15u8 {
+            assert!(typed_data(&[type_id]).is_err());
+            assert!(typed_data_strict(&[type_id]).is_err());
+        }
+    }
+
+    #[test]
+    fn test_typed_data_ref() {
+        for (description, input, expected) in test_cases() {
+            let (rest, data) = typed_data_ref(&input).unwrap();
+            assert!(rest.is_empty(), "{}", description);
+            assert_eq!(TypedData::from(data), expected, "{}", description);
+        }
+
+        let input = [0x09, 0x03, 0x01, 0x02, 0x03, 0xff];
+        let (rest, data) = typed_data_ref(&input).unwrap();
+        assert_eq!(rest, &[0xff]);
+        let TypedDataRef::Binary(bytes) = data else {
+            panic!("Expected BINARY, got {:?}", data);
+        };
+        assert_eq!(bytes, &[0x01, 0x02, 0x03]);
+        assert!(std::ptr::eq(bytes.as_ptr(), input[2..].as_ptr()));
+
+        // invalid UTF-8 is kept as is, and replaced only once owned
+        let (_, data) = typed_data_ref(&[0x08, 0x02, 0xff, b'a']).unwrap();
+        assert_eq!(data, TypedDataRef::String(&[0xff, b'a']));
+        assert_eq!(
+            TypedData::from(data),
+            TypedData::String("\u{fffd}a".to_string())
+        );
+    }
+
+    proptest::proptest! {
+        #[test]
+        fn prop_typed_data_round_trip(value in arb_typed_data()) {
+            let mut buf = Vec::new();
+            value.to_bytes(&mut buf);
+            proptest::prop_assert_eq!(buf.len(), value.encoded_len());
+
+            let (rest, parsed) = typed_data(&buf).unwrap();
+            proptest::prop_assert!(rest.is_empty());
+            proptest::prop_assert_eq!(parsed, value);
+        }
+    }
 }
@@ -1,4 +1,10 @@
-use nom::{IResult, number::complete::be_u8};
+use alloc::vec::Vec;
+use bytes::BufMut;
+use nom::{
+    IResult,
+    error::{Error, ErrorKind},
+    number::complete::be_u8,
+};
 
 /// <https://github.com/haproxy/haproxy/blob/master/doc/SPOE.txt#L659-L667>
 ///
@@ -15,49 +21,84 @@ use nom::{IResult, number::complete::be_u8};
 ///
 /// ```
 pub fn encode_varint(i: u64) -> Vec<u8> {
-    let mut buf = Vec::new();
+    let mut buf = Vec::with_capacity(varint_len(i));
+    put_varint(&mut buf, i);
+    buf
+}
 
+/// Writes the varint encoding of `i` at the end of `buf`, see [`encode_varint`]
+pub(crate) fn put_varint<B: BufMut + ?Sized>(buf: &mut B, i: u64) {
     if i < 240 {
-        buf.push(i as u8);
+        buf.put_u8(i as u8);
     } else {
-        buf.push((i | 240) as u8);
+        buf.put_u8((i | 240) as u8);
         let mut i = (i - 240) >> 4;
 
         while i >= 128 {
-            buf.push((i | 128) as u8);
+            buf.put_u8((i | 128) as u8);
             i = (i - 128) >> 7;
         }
 
-        buf.push(i as u8);
+        buf.put_u8(i as u8);
     }
+}
 
-    buf
+/// Returns the number of bytes [`encode_varint`] writes for `i`, without encoding it
+pub const fn varint_len(i: u64) -> usize {
+    if i < 240 {
+        return 1;
+    }
+
+    let mut len = 2;
+    let mut i = (i - 240) >> 4;
+
+    while i >= 128 {
+        len += 1;
+        i = (i - 128) >> 7;
+    }
+
+    len
 }
 
+/// Longest varint encoding of a u64, `u64::MAX` included
+const MAX_VARINT_LEN: usize = 10;
+
 /// Decodes a variable-length integer (varint) from the input byte slice.
+///
+/// Encodings longer than 10 bytes, or decoding to a value past `u64::MAX`, are rejected with
+/// `ErrorKind::TooLarge` instead of silently wrapping.
+///
+/// Every accepted encoding is the canonical one, there is no overlong form to reject: the value
+/// ranges of the encoding lengths do not overlap, e.g. `0x80` is a complete 1-byte varint and
+/// 2-byte varints start at 240, and within a length each value has a single representation. The
+/// only other way to encode a value twice is to set bits past 64 in the last byte, which is
+/// reported as `TooLarge`.
 pub fn decode_varint(input: &[u8]) -> IResult<&[u8], u64> {
-    let (mut input, first_byte) = be_u8(input)?;
+    let (mut remaining, first_byte) = be_u8(input)?;
 
     if first_byte < 240 {
-        return Ok((input, first_byte as u64));
+        return Ok((remaining, first_byte as u64));
     }
 
-    let mut value = first_byte as u64;
+    // the last bytes may carry bits past 64 bits, kept to detect the overflow
+    let mut value = first_byte as u128;
     let mut shift = 4;
 
-    loop {
-        let (new_input, next_byte) = be_u8(input)?;
-        input = new_input;
+    for _ in 1..MAX_VARINT_LEN {
+        let (new_remaining, next_byte) = be_u8(remaining)?;
+        remaining = new_remaining;
 
-        value += (next_byte as u64) << shift;
+        value += (next_byte as u128) << shift;
         shift += 7;
 
         if next_byte < 128 {
-            break;
+            return u64::try_from(value)
+                .map(|value| (remaining, value))
+                .map_err(|_| nom::Err::Error(Error::new(input, ErrorKind::TooLarge)));
         }
     }
 
-    Ok((input, value))
+    Err(nom::Err::Error(Error::new(input, ErrorKind::TooLarge)))
 }
 
 #[cfg(test)]
@@ -245,4 +286,104 @@ mod tests {
             assert!(remaining_input.is_empty());
         }
     }
+
+    #[test]
+    fn test_varint_len() {
+        // first and last values of each encoded length, from the Peers encoding table
+        let boundaries = [
+            0,
+            239,
+            240,
+            2287,
+            2288,
+            264431,
+            264432,
+            33818863,
+            33818864,
+            4328786159,
+            4328786160,
+            u32::MAX as u64,
+            i64::MAX as u64,
+            u64::MAX - 1,
+            u64::MAX,
+        ];
+
+        for value in boundaries {
+            assert_eq!(varint_len(value), encode_varint(value).len(), "{}", value);
+        }
+
+        for value in (0..100_000).chain((0..64).map(|shift| 1u64 << shift)) {
+            assert_eq!(varint_len(value), encode_varint(value).len(), "{}", value);
+        }
+
+        assert_eq!(varint_len(u64::MAX), MAX_VARINT_LEN);
+    }
+
+    #[test]
+    fn test_decode_varint_canonical() {
+        // each value has a single encoding, the one encode_varint produces
+        for value in [0, 239, 240, 300, 2287, 2288, 264431, 264432, u64::MAX] {
+            let encoded = encode_varint(value);
+            assert_eq!(decode_varint(&encoded), Ok((&[][..], value)));
+        }
+        assert_eq!(
+            decode_varint(&[0xF0, 0x80, 0x00, 0x01]),
+            Ok((&[0x01][..], 2288))
+        );
+
+        // 0x80 is a complete single byte varint, the 0x00 is not a padding of it
+        assert_eq!(decode_varint(&[0x80, 0x00]), Ok((&[0x00][..], 128)));
+
+        #[rustfmt::skip]
+        let canonical = [0xF0, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x00];
+        // would be the same value if the high bits of the last byte were shifted out
+        #[rustfmt::skip]
+        let overlong = [0xF0, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x10];
+
+        let (_, value) = decode_varint(&canonical).unwrap();
+        assert_eq!(encode_varint(value), canonical);
+        assert_eq!(
+            decode_varint(&overlong),
+            Err(nom::Err::Error(Error::new(
+                &overlong[..],
+                ErrorKind::TooLarge
+            )))
+        );
+    }
+
+    #[test]
+    fn test_decode_varint_bounds() {
+        // maximal valid varint
+        let max = encode_varint(u64::MAX);
+        assert_eq!(max.len(), MAX_VARINT_LEN);
+        assert_eq!(decode_varint(&max), Ok((&[][..], u64::MAX)));
+
+        // u64::MAX + 2^60
+        let mut overflow = max.clone();
+        assert!(overflow[9] < 0x7F);
+        overflow[9] += 1;
+        assert_eq!(
+            decode_varint(&overflow),
+            Err(nom::Err::Error(Error::new(
+                &overflow[..],
+                ErrorKind::TooLarge
+            )))
+        );
+
+        // more than 10 bytes
+        let too_long = [0xFF; 11];
+        assert_eq!(
+            decode_varint(&too_long),
+            Err(nom::Err::Error(Error::new(
+                &too_long[..],
+                ErrorKind::TooLarge
+            )))
+        );
+
+        // ends mid-sequence
+        assert_eq!(
+            decode_varint(&[0xF0, 0x80]),
+            Err(nom::Err::Error(Error::new(&[][..], ErrorKind::Eof)))
+        );
+    }
 }
@@ -0,0 +1,138 @@
+use crate::frames::{
+    Ack, AgentDisconnect, AgentHello, haproxy_disconnect::HaproxyDisconnectFrame,
+    haproxy_hello::HaproxyHelloFrame, notify::NotifyFrame,
+};
+
+/// Visitor over the concrete frame types
+///
+/// Every method has an empty default implementation, so a visitor only needs to
+/// override the frames it cares about. Dispatch is done by [`crate::SpopFrame::accept`]:
+///
+/// ```
+/// use spop::{FrameVisitor, SpopFrame, frames::Ack};
+///
+/// #[derive(Default)]
+/// struct Counter {
+///     acks: usize,
+/// }
+///
+/// impl FrameVisitor for Counter {
+///     fn visit_ack(&mut self, _frame: &Ack) {
+///         self.acks += 1;
+///     }
+/// }
+///
+/// let mut counter = Counter::default();
+/// Ack::new(1, 1).accept(&mut counter);
+/// assert_eq!(counter.acks, 1);
+/// ```
+pub trait FrameVisitor {
+    fn visit_haproxy_hello(&mut self, _frame: &HaproxyHelloFrame) {}
+
+    fn visit_haproxy_disconnect(&mut self, _frame: &HaproxyDisconnectFrame) {}
+
+    fn visit_notify(&mut self, _frame: &NotifyFrame) {}
+
+    fn visit_agent_hello(&mut self, _frame: &AgentHello) {}
+
+    fn visit_agent_disconnect(&mut self, _frame: &AgentDisconnect) {}
+
+    fn visit_ack(&mut self, _frame: &Ack) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        SpopFrame,
+        frame::Metadata,
+        frames::{HaproxyDisconnect, HaproxyHello},
+    };
+    use semver::Version;
+
+    #[derive(Default)]
+    struct Counter {
+        haproxy_hello: usize,
+        haproxy_disconnect: usize,
+        notify: usize,
+        agent_hello: usize,
+        agent_disconnect: usize,
+        ack: usize,
+    }
+
+    impl FrameVisitor for Counter {
+        fn visit_haproxy_hello(&mut self, _frame: &HaproxyHelloFrame) {
+            self.haproxy_hello += 1;
+        }
+
+        fn visit_haproxy_disconnect(&mut self, _frame: &HaproxyDisconnectFrame) {
+            self.haproxy_disconnect += 1;
+        }
+
+        fn visit_notify(&mut self, _frame: &NotifyFrame) {
+            self.notify += 1;
+        }
+
+        fn visit_agent_hello(&mut self, _frame: &AgentHello) {
+            self.agent_hello += 1;
+        }
+
+        fn visit_agent_disconnect(&mut self, _frame: &AgentDisconnect) {
+            self.agent_disconnect += 1;
+        }
+
+        fn visit_ack(&mut self, _frame: &Ack) {
+            self.ack += 1;
+        }
+    }
+
+    #[test]
+    fn test_visitor_counts_frame_types() {
+        let frames: Vec<Box<dyn SpopFrame>> = vec![
+            Box::new(HaproxyHelloFrame {
+                metadata: Metadata::default(),
+                payload: HaproxyHello {
+                    supported_versions: vec![Version::new(2, 0, 0)],
+                    max_frame_size: 16380,
+                    capabilities: vec![],
+                    healthcheck: None,
+                    engine_id: None,
+                },
+            }),
+            Box::new(HaproxyDisconnectFrame {
+                metadata: Metadata::default(),
+                payload: HaproxyDisconnect {
+                    status_code: 0,
+                    message: "normal".to_string(),
+                },
+            }),
+            Box::new(NotifyFrame {
+                metadata: Metadata::default(),
+                messages: vec![],
+            }),
+            Box::new(AgentHello {
+                version: Version::new(2, 0, 0),
+                max_frame_size: 16380,
+                capabilities: vec![],
+            }),
+            Box::new(AgentDisconnect {
+                status_code: 0,
+                message: "Goodbye".to_string(),
+            }),
+            Box::new(Ack::new(1, 1)),
+            Box::new(Ack::new(1, 2)),
+        ];
+
+        let mut counter = Counter::default();
+        for frame in &frames {
+            frame.accept(&mut counter);
+        }
+
+        assert_eq!(counter.haproxy_hello, 1);
+        assert_eq!(counter.haproxy_disconnect, 1);
+        assert_eq!(counter.notify, 1);
+        assert_eq!(counter.agent_hello, 1);
+        assert_eq!(counter.agent_disconnect, 1);
+        assert_eq!(counter.ack, 2);
+    }
+}
@@ -0,0 +1,118 @@
+//! Checks that measuring a frame, and serializing it into a buffer with enough room, does not
+//! allocate
+//!
+//! The allocations are counted by a global allocator, so this test runs without the libtest
+//! harness: its threads would allocate while a frame is serialized.
+
+use bytes::BytesMut;
+use semver::Version;
+use spop::{
+    Action, SpopFrame, TypedData, VarScope,
+    frames::{
+        Ack, AgentDisconnect, AgentHello, DisconnectStatus, FrameCapabilities, HaproxyDisconnect,
+        HaproxyHello, notify::NotifyFrame,
+    },
+};
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    net::Ipv4Addr,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        unsafe { System.alloc(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        unsafe { System.realloc(ptr, layout, new_size) }
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+/// Returns the number of allocations made by `f`
+fn allocations(f: impl FnOnce()) -> usize {
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    f();
+    ALLOCATIONS.load(Ordering::Relaxed) - before
+}
+
+fn assert_does_not_allocate(name: &str, frame: &dyn SpopFrame) {
+    let mut wire_len = 0;
+    let count = allocations(|| wire_len = frame.wire_len());
+    assert_eq!(count, 0, "{name}: wire_len allocated {count} times");
+
+    let count = allocations(|| assert_eq!(frame.encoded_len() + 4, wire_len));
+    assert_eq!(count, 0, "{name}: encoded_len allocated {count} times");
+
+    let mut dst = BytesMut::with_capacity(wire_len);
+
+    let count = allocations(|| frame.serialize_into(&mut dst).unwrap());
+    assert_eq!(count, 0, "{name}: serialize_into allocated {count} times");
+    assert_eq!(dst.len(), wire_len, "{name}: wrong wire_len");
+    assert_eq!(&dst[..], &frame.serialize().unwrap()[..], "{name}");
+}
+
+fn main() {
+    let capabilities = vec![
+        FrameCapabilities::Pipelining,
+        FrameCapabilities::Async,
+        FrameCapabilities::Unknown("frobnicate".to_string()),
+    ];
+
+    let mut haproxy_hello = HaproxyHello::new(
+        vec![Version::new(2, 0, 0), Version::new(1, 5, 0)],
+        16380,
+        capabilities.clone(),
+    );
+    haproxy_hello.healthcheck = Some(false);
+    haproxy_hello.engine_id = Some("54A0A1F8-6F09-4B56-9AE5-3E8B7CA4E6A0".to_string());
+    assert_does_not_allocate("HAPROXY-HELLO", &haproxy_hello.into_frame(0, 0));
+
+    let agent_hello = AgentHello {
+        version: Version::new(2, 0, 0),
+        max_frame_size: 16380,
+        capabilities,
+    };
+    assert_does_not_allocate("AGENT-HELLO", &agent_hello);
+
+    let haproxy_disconnect = HaproxyDisconnect::new(DisconnectStatus::Normal, "bye");
+    assert_does_not_allocate("HAPROXY-DISCONNECT", &haproxy_disconnect.into_frame(0, 0));
+
+    let agent_disconnect = AgentDisconnect {
+        status_code: DisconnectStatus::Normal.to_u32(),
+        message: "bye".to_string(),
+    };
+    assert_does_not_allocate("AGENT-DISCONNECT", &agent_disconnect);
+
+    let notify = NotifyFrame::new(3, 7)
+        .add_message("check-client-ip")
+        .arg("ip", TypedData::IPv4(Ipv4Addr::new(10, 0, 0, 1)))
+        .arg("path", "/login")
+        .add_message("log-request")
+        .arg("method", "GET")
+        .build();
+    assert_does_not_allocate("NOTIFY", &notify);
+
+    let mut ack = Ack::new(3, 7)
+        .set_var(VarScope::Session, "score", TypedData::UInt32(42))
+        .unset_var(VarScope::Transaction, "country");
+    ack.actions.push(Action::SetVarExpr {
+        scope: VarScope::Request,
+        name: "token".to_string(),
+        expr: "req.hdr(x-token)".to_string(),
+    });
+    assert_does_not_allocate("ACK", &ack);
+}
@@ -0,0 +1,7 @@
+//! Compile-fail tests, the expected compiler errors are pinned in the `.stderr` files
+
+#[test]
+fn ui() {
+    let t = trybuild::TestCases::new();
+    t.compile_fail("tests/ui/*.rs");
+}
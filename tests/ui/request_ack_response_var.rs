@@ -0,0 +1,6 @@
+use spop::{actions::scope, frames::RequestAck, types::TypedData};
+
+fn main() {
+    // res. variables have no effect while processing a request
+    let _ack = RequestAck::new(1, 1).set_var(scope::Response, "my_var", TypedData::UInt32(1));
+}
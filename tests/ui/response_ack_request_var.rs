@@ -0,0 +1,6 @@
+use spop::{actions::scope, frames::ResponseAck, types::TypedData};
+
+fn main() {
+    // req. variables have no effect while processing a response
+    let _ack = ResponseAck::new(1, 1).unset_var(scope::Request, "my_var");
+}